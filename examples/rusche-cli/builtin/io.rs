@@ -1,23 +1,26 @@
 use rusche::{
-    eval::{eval, EvalContext, EvalError, EvalResult},
+    eval::{eval, Arity, EvalContext, EvalError, EvalErrorKind, EvalResult},
     expr::{Expr, NIL},
     list::List,
+    pretty::{pretty_print, PrettyOptions},
+    utils::get_exact_1_arg,
 };
 use std::io::Write;
 
 pub fn load_io_procs(context: &EvalContext) {
+    context.env.define_native_proc("display", display);
+    context.env.define_native_proc("newline", newline);
     context.env.define_native_proc("print", print);
     context.env.define_native_proc("println", println);
+    context.env.define_native_proc("pp", pp);
     context.env.define_native_proc("read", read);
     context.env.define_native_proc("read-num", read_num);
+    context.env.define_native_proc("write", write);
 }
 
 fn print_args(args: &List, context: &EvalContext) -> Result<(), EvalError> {
     for expr in args.iter() {
-        match eval(expr, context)? {
-            Expr::Str(text, _) => print!("{}", text), // w/o double quotes
-            expr => print!("{}", expr),
-        }
+        print!("{}", eval(expr, context)?.display());
     }
     Ok(())
 }
@@ -34,6 +37,39 @@ fn println(_: &str, args: &List, context: &EvalContext) -> EvalResult {
     Ok(NIL)
 }
 
+fn display(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let expr = eval(get_exact_1_arg(proc_name, args)?, context)?;
+    print!("{}", expr.display());
+    let _ = std::io::stdout().flush();
+    Ok(NIL)
+}
+
+fn write(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let expr = eval(get_exact_1_arg(proc_name, args)?, context)?;
+    print!("{}", expr.write());
+    let _ = std::io::stdout().flush();
+    Ok(NIL)
+}
+
+fn newline(proc_name: &str, args: &List, _: &EvalContext) -> EvalResult {
+    if !args.is_nil() {
+        let kind = EvalErrorKind::ArityMismatch {
+            proc_name: proc_name.to_string(),
+            expected: Arity::Exactly(0),
+            actual: args.len(),
+        };
+        return Err(EvalError::new(kind, args.span()));
+    }
+    println!();
+    Ok(NIL)
+}
+
+fn pp(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let expr = eval(get_exact_1_arg(proc_name, args)?, context)?;
+    println!("{}", pretty_print(&expr, &PrettyOptions::default()));
+    Ok(NIL)
+}
+
 fn read_line() -> Result<String, EvalError> {
     let mut input = String::new();
     if let Err(error) = std::io::stdin().read_line(&mut input) {
@@ -1,10 +1,11 @@
 use rusche::{
-    eval::{eval, EvalContext, EvalError,  EvalResult},
-    expr::{Expr, NIL},
+    eval::{eval, EvalContext, EvalError, EvalErrorKind, EvalResult},
+    expr::{Expr, Printed, NIL},
+    foreign::ForeignObject,
     list::List,
     utils::{eval_into_foreign, eval_into_int, get_exact_1_arg, get_exact_2_args},
 };
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, fmt, rc::Rc};
 
 pub fn load_vec_procs(context: &EvalContext) {
     context.env.define_native_proc("vec?", is_vec);
@@ -14,20 +15,56 @@ pub fn load_vec_procs(context: &EvalContext) {
     context.env.define_native_proc("vec-get", get);
 }
 
-type ExprVecRefCell = RefCell<Vec<Expr>>;
+#[derive(Default)]
+struct ExprVec(RefCell<Vec<Expr>>);
+
+impl ExprVec {
+    fn fmt_items(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        print: fn(&Expr) -> Printed<'_>,
+    ) -> fmt::Result {
+        write!(f, "#(")?;
+        for (index, item) in self.0.borrow().iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", print(item))?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl ForeignObject for ExprVec {
+    fn type_name(&self) -> &str {
+        "vec"
+    }
+
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_items(f, Expr::display)
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_items(f, Expr::write)
+    }
+
+    fn trace(&self, visit: &mut dyn FnMut(&Expr)) {
+        self.0.borrow().iter().for_each(visit);
+    }
+}
 
 fn eval_into_vec(
     proc_name: &str,
     expr: &Expr,
     context: &EvalContext,
-) -> Result<Rc<ExprVecRefCell>, EvalError> {
+) -> Result<Rc<ExprVec>, EvalError> {
     eval_into_foreign(proc_name, expr, context)?
-        .downcast::<ExprVecRefCell>()
-        .or_else(|_| {
-            Err(EvalError {
-                message: format!("{proc_name}: `{expr}` does not evaluate to a vector."),
-                span: expr.span(),
-            })
+        .downcast::<ExprVec>()
+        .map_err(|object| {
+            EvalError::new(
+                EvalErrorKind::type_mismatch(proc_name, "vec", &Expr::Foreign(object)),
+                expr.span(),
+            )
         })
 }
 
@@ -37,29 +74,29 @@ fn is_vec(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
 }
 
 fn make(_: &str, _: &List, _: &EvalContext) -> EvalResult {
-    Ok(Expr::Foreign(Rc::new(RefCell::new(Vec::<Expr>::new()))))
+    Ok(Expr::Foreign(Rc::new(ExprVec::default())))
 }
 
 fn push(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let (arg1, arg2) = get_exact_2_args(proc_name, args)?;
     let vec = eval_into_vec(proc_name, arg1, context)?;
     let item = eval(arg2, context)?;
-    vec.borrow_mut().push(item);
+    vec.0.borrow_mut().push(item);
     Ok(NIL)
 }
 
 fn pop(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let vec_expr = get_exact_1_arg(proc_name, args)?;
     let vec = eval_into_vec(proc_name, vec_expr, context)?;
-    let item = vec.borrow_mut().pop();
+    let item = vec.0.borrow_mut().pop();
 
     if let Some(item) = item {
         Ok(item)
     } else {
-        Err(EvalError {
-            message: format!("{proc_name}: vector is empty."),
-            span: vec_expr.span(),
-        })
+        Err(EvalError::new(
+            format!("{proc_name}: vector is empty."),
+            vec_expr.span(),
+        ))
     }
 }
 
@@ -68,20 +105,20 @@ fn get(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let vec = eval_into_vec(proc_name, vec_expr, context)?;
     let index = eval_into_int(proc_name, "index", index_expr, context)?;
 
-    if index < 0 {
-        return Err(EvalError {
-            message: format!("{proc_name}: index must be zero or positive integer."),
-            span: index_expr.span(),
-        });
-    }
-
-    let item = vec.borrow().get(index as usize).cloned();
+    let items = vec.0.borrow();
+    let item = usize::try_from(index)
+        .ok()
+        .and_then(|index| items.get(index));
     if let Some(item) = item {
-        Ok(item)
+        Ok(item.clone())
     } else {
-        Err(EvalError {
-            message: format!("{proc_name}: index out-of-bounds {index}."),
-            span: index_expr.span(),
-        })
+        Err(EvalError::new(
+            EvalErrorKind::IndexOutOfRange {
+                proc_name: proc_name.to_string(),
+                index: index as i64,
+                len: items.len(),
+            },
+            index_expr.span(),
+        ))
     }
 }
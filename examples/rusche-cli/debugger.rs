@@ -0,0 +1,209 @@
+//! Debugger commands of the REPL.
+//!
+//! At the prompt, `:break` sets breakpoints and `:step` pauses at the start of
+//! the next evaluation. While paused, the prompt changes to `debug ❯` and takes
+//! the commands listed by `:help`, or a name to print its value.
+
+use std::{cell::RefCell, rc::Rc};
+
+use rusche::{
+    debug::{Breakpoint, Pause, PauseReason, Resume},
+    diagnostic::Diagnostic,
+    env::Env,
+    eval::Evaluator,
+    expr::Expr,
+    pretty::pretty_print,
+    source::SourceMap,
+};
+use rustyline::DefaultEditor;
+
+use crate::{
+    repl::RESULT_OPTIONS,
+    runner::{parse_source, report_all},
+};
+
+const REPL_HELP: &str = "\
+:break <proc>        pause on entry to a procedure
+:break <file>:<line> pause at a line
+:break error         pause when an expression fails
+:delete <id>|error   remove a breakpoint, or stop pausing on errors
+:breakpoints         list the breakpoints
+:step                pause at the start of the next evaluation
+:load <path>         evaluate a file, so that its lines can have breakpoints
+:help                show this help";
+
+const PAUSE_HELP: &str = "\
+:step, :s            evaluate the next expression, stepping into calls
+:next, :n            evaluate the next expression, stepping over calls
+:out                 run until the current procedure returns
+:continue, :c        run until the next breakpoint
+:locals              show the variables of the current procedure
+:env                 show the variables of every enclosing environment
+:backtrace, :bt      show the call stack
+<name>               show the value of a variable
+:help                show this help";
+
+/// Makes `evaluator` prompt for debugger commands with `editor` whenever it pauses.
+pub fn attach_debugger(evaluator: &Evaluator, editor: Rc<RefCell<DefaultEditor>>) {
+    evaluator
+        .debugger()
+        .set_pause_handler(Some(Box::new(move |pause| {
+            print_pause(pause);
+            loop {
+                let line = match editor.borrow_mut().readline("debug ❯ ") {
+                    Ok(line) => line,
+                    Err(_) => return Resume::Continue, // e.g. Ctrl-D
+                };
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let _ = editor.borrow_mut().add_history_entry(line);
+
+                match line {
+                    ":step" | ":s" => return Resume::StepInto,
+                    ":next" | ":n" => return Resume::StepOver,
+                    ":out" => return Resume::StepOut,
+                    ":continue" | ":c" => return Resume::Continue,
+                    ":locals" => print_locals(pause.env),
+                    ":env" => print_env_chain(pause.env),
+                    ":backtrace" | ":bt" => print!("{}", pause.backtrace),
+                    ":help" => println!("{}", PAUSE_HELP),
+                    name if !name.starts_with(':') => match pause.env.lookup(name) {
+                        Some(value) => print_value(name, &value),
+                        None => println!("Unbound symbol: {}", name),
+                    },
+                    _ => println!("Unknown command: {} (see :help)", line),
+                }
+            }
+        })));
+}
+
+/// Runs a REPL command, i.e. a line starting with ':'.
+pub fn run_command(line: &str, evaluator: &Evaluator) {
+    let debugger = evaluator.debugger();
+    let (command, arg) = match line.trim().split_once(char::is_whitespace) {
+        Some((command, arg)) => (command, arg.trim()),
+        None => (line.trim(), ""),
+    };
+
+    match (command, arg) {
+        (":break", "error") => debugger.set_break_on_error(true),
+        (":break", arg) if !arg.is_empty() => {
+            let line = arg
+                .rsplit_once(':')
+                .and_then(|(path, line)| Some((path, line.parse().ok()?)));
+            let breakpoint = match line {
+                Some((path, line)) => Breakpoint::Line {
+                    path: path.to_string(),
+                    line,
+                },
+                None => Breakpoint::Proc(arg.to_string()),
+            };
+            let id = debugger.add_breakpoint(breakpoint.clone());
+            println!("Breakpoint {} at {}", id, breakpoint);
+        }
+        (":delete", "error") => debugger.set_break_on_error(false),
+        (":delete", arg) if !arg.is_empty() => match arg.parse() {
+            Ok(id) if debugger.remove_breakpoint(id) => {}
+            _ => println!("No breakpoint {}", arg),
+        },
+        (":breakpoints", "") => {
+            for (id, breakpoint) in debugger.breakpoints() {
+                println!("{:>3}  {}", id, breakpoint);
+            }
+            if debugger.breaks_on_error() {
+                println!("     on error");
+            }
+        }
+        (":step", "") => debugger.step(),
+        (":load", path) if !path.is_empty() => load_file(path, evaluator),
+        (":help", "") => println!("{}", REPL_HELP),
+        _ => println!("Unknown command: {} (see :help)", line.trim()),
+    }
+}
+
+fn load_file(path: &str, evaluator: &Evaluator) {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("Failed to read file at \"{path}\": {e}");
+            return;
+        }
+    };
+
+    let (exprs, diagnostics) = parse_source(SourceMap::add(path, text));
+    if !diagnostics.is_empty() {
+        report_all(&diagnostics);
+        return;
+    }
+    for expr in exprs {
+        if let Err(error) = evaluator.eval(&expr) {
+            report_all(&[Diagnostic::from(&error)]);
+        }
+    }
+    evaluator.debugger().cancel_step();
+}
+
+fn print_pause(pause: &Pause) {
+    let reason = match &pause.reason {
+        PauseReason::Breakpoint(id) => format!("Breakpoint {}", id),
+        PauseReason::Step => "Step".to_string(),
+        PauseReason::Error(_) => "Error".to_string(),
+    };
+    let location = match (pause.span, pause.span.and_then(|span| span.path())) {
+        (Some(span), Some(path)) => format!(" at {}:{}", path, span.begin),
+        (Some(span), None) => format!(" at {}", span.begin),
+        (None, _) => String::new(),
+    };
+    println!("{}{}", reason, location);
+
+    match (pause.expr, pause.backtrace.frames.first()) {
+        (Some(expr), _) => println!("  {}", expr),
+        (None, Some(frame)) => println!("  entering {}", frame.name),
+        (None, None) => {}
+    }
+    if let PauseReason::Error(error) = &pause.reason {
+        println!("  {}", error.message());
+    }
+}
+
+fn print_locals(env: &Rc<Env>) {
+    match env.base() {
+        Some(_) => print_names(env),
+        None => println!("Paused at the top level (see :env)"),
+    }
+}
+
+fn print_names(env: &Env) {
+    let mut names = env.names();
+    names.sort();
+    for name in names {
+        if let Some(value) = env.lookup(&name) {
+            print_value(&name, &value);
+        }
+    }
+}
+
+/// Prints the variables of `env` and of its bases, except for the global ones,
+/// which are too many to be useful.
+fn print_env_chain(env: &Rc<Env>) {
+    let mut env = env;
+    let mut level = 0;
+    while let Some(base) = env.base() {
+        println!("[{}]", level);
+        print_names(env);
+        env = base;
+        level += 1;
+    }
+    println!("[{}] global, {} names", level, env.names().len());
+}
+
+fn print_value(name: &str, value: &Expr) {
+    let text = pretty_print(value, &RESULT_OPTIONS);
+    let mut lines = text.lines();
+    println!("{} = {}", name, lines.next().unwrap_or(""));
+    for line in lines {
+        println!("{:width$}{}", "", line, width = name.len() + 3);
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::IsTerminal;
+use std::process::ExitCode;
+
+use rusche::{
+    cst::Cst,
+    diagnostic::Diagnostic,
+    format::{format_cst, FormatOptions},
+    source::SourceMap,
+};
+
+const USAGE: &str = "Usage: rusche-cli fmt [--check] [--width <columns>] <path>...";
+
+/// Formats the files given in `args` in place. With `--check`, reports the files
+/// that are not formatted instead, and fails if there are any.
+pub fn run_fmt(args: &[&str]) -> ExitCode {
+    let mut options = FormatOptions::default();
+    let mut is_check = false;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--check" => is_check = true,
+            "--width" => match args.next().and_then(|width| width.parse().ok()) {
+                Some(width) => options.width = width,
+                None => {
+                    eprintln!("{USAGE}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            path => paths.push(path),
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for path in paths {
+        if !format_file(path, &options, is_check) {
+            exit_code = ExitCode::FAILURE;
+        }
+    }
+    exit_code
+}
+
+/// Returns false if the file could not be formatted, or is not formatted in
+/// check mode.
+fn format_file(path: &str, options: &FormatOptions, is_check: bool) -> bool {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Failed to read file at \"{path}\": {e}");
+            return false;
+        }
+    };
+
+    let source = SourceMap::add(path, text.as_str());
+    let formatted = match Cst::parse_source(source) {
+        Ok(cst) => format_cst(&cst, options),
+        Err(error) => {
+            let use_color = std::io::stderr().is_terminal();
+            eprint!("{}", Diagnostic::from(&error).render(use_color));
+            return false;
+        }
+    };
+
+    if formatted == text {
+        true
+    } else if is_check {
+        println!("{path} is not formatted");
+        false
+    } else if let Err(e) = std::fs::write(path, formatted) {
+        eprintln!("Failed to write file at \"{path}\": {e}");
+        false
+    } else {
+        true
+    }
+}
@@ -1,18 +1,48 @@
 mod builtin;
+mod debugger;
+mod fmt;
 mod repl;
 mod runner;
+mod test;
 
+use std::process::ExitCode;
+
+use fmt::run_fmt;
 use repl::run_repl;
-use runner::run_file;
+use runner::{check_file, run_file, CoverageOptions, ProfileOptions};
+use test::run_tests;
 
-fn main() {
-    let mut args = std::env::args().skip(1); // skip the program name
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>(); // skip the program name
 
-    if let Some(path) = args.next() {
-        run_file(&path);
-    } else {
-        print_logo();
-        run_repl();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {
+            print_logo();
+            run_repl();
+            ExitCode::SUCCESS
+        }
+        ["fmt", ref args @ ..] => run_fmt(args),
+        ["test", ref args @ ..] => run_tests(args),
+        ["--check", path] => check_file(path),
+        ["--profile", path] => run_file(path, Some(ProfileOptions { folded_path: None }), None),
+        [flag, path] if flag.starts_with("--profile=") => {
+            let folded_path = flag.strip_prefix("--profile=");
+            run_file(path, Some(ProfileOptions { folded_path }), None)
+        }
+        ["--coverage", path] => run_file(path, None, Some(CoverageOptions { lcov_path: None })),
+        [flag, path] if flag.starts_with("--coverage=") => {
+            let lcov_path = flag.strip_prefix("--coverage=");
+            run_file(path, None, Some(CoverageOptions { lcov_path }))
+        }
+        [path] => run_file(path, None, None),
+        _ => {
+            eprintln!("Usage: rusche-cli [--check] [path]");
+            eprintln!("       rusche-cli --profile[=<folded-stacks-path>] <path>");
+            eprintln!("       rusche-cli --coverage[=<lcov-path>] <path>");
+            eprintln!("       rusche-cli fmt [--check] [--width <columns>] <path>...");
+            eprintln!("       rusche-cli test [--filter <name>] [<path>...]");
+            ExitCode::FAILURE
+        }
     }
 }
 
@@ -1,40 +1,76 @@
+use std::{cell::RefCell, io::IsTerminal, rc::Rc};
+
 use rusche::{
+    diagnostic::{Diagnostic, Renderer},
     eval::Evaluator,
-    lexer::{tokenize, LexError},
+    lexer::{LexError, Lexer},
     parser::{ParseError, Parser},
+    pretty::{pretty_print, PrettyOptions},
+    span::Loc,
+    token::Token,
 };
 use rustyline::{error::ReadlineError, DefaultEditor};
 
-use crate::builtin::{load_io_procs, load_vec_procs};
+use crate::{
+    builtin::{load_io_procs, load_vec_procs},
+    debugger::{attach_debugger, run_command},
+};
+
+/// Layout of evaluation results, which are printed after a "; " prefix.
+pub const RESULT_OPTIONS: PrettyOptions = PrettyOptions {
+    width: 78,
+    indent: 2,
+    max_depth: Some(32),
+    max_length: Some(256),
+};
 
 pub fn run_repl() {
-    let mut rl = DefaultEditor::new().expect("Failed to initialize line reader!");
+    // shared with the debugger, which prompts while an evaluation is paused
+    let rl = Rc::new(RefCell::new(
+        DefaultEditor::new().expect("Failed to initialize line reader!"),
+    ));
     let mut parser = Parser::new();
 
     let evaluator = Evaluator::with_prelude();
 
     load_io_procs(evaluator.context());
     load_vec_procs(evaluator.context());
+    attach_debugger(&evaluator, rl.clone());
+
+    // lines of the input being parsed, so that diagnostics can show them
+    let mut input = String::new();
+    let use_color = std::io::stdout().is_terminal();
+    let report = |input: &str, diagnostic: Diagnostic| {
+        let renderer = Renderer::new("<repl>", input).with_color(use_color);
+        print!("{}", renderer.render(&diagnostic));
+    };
 
     loop {
         let prompt = if parser.is_parsing() {
             "...... ❯ "
         } else {
+            input.clear();
             "rusche ❯ "
         };
 
-        match rl.readline(prompt) {
+        let line = rl.borrow_mut().readline(prompt);
+        match line {
             Ok(line) => {
-                let _ = rl.add_history_entry(line.as_str());
+                let _ = rl.borrow_mut().add_history_entry(line.as_str());
 
-                match tokenize(&line) {
+                if !parser.is_parsing() && line.trim_start().starts_with(':') {
+                    run_command(&line, &evaluator);
+                    continue;
+                }
+
+                let loc = Loc::new(input.lines().count() + 1, 1, input.len());
+                input.push_str(&line);
+                input.push('\n');
+
+                match tokenize_line(&line, loc) {
                     Ok(tokens) => parser.add_tokens(tokens),
-                    Err(LexError::IncompleteString(span)) => {
-                        println!("Error:{span}: Incomplete string");
-                        continue;
-                    }
-                    Err(LexError::InvalidNumber(span)) => {
-                        println!("Error:{span}: Invalid number");
+                    Err(error) => {
+                        report(&input, Diagnostic::from(&error));
                         continue;
                     }
                 }
@@ -44,23 +80,32 @@ pub fn run_repl() {
                         Ok(None) => {
                             break;
                         }
-                        Ok(Some(expr)) => match evaluator.eval(&expr) {
-                            Ok(result) => {
-                                println!("; {}", result);
-                            }
-                            Err(error) => {
-                                println!("Error: {}", error);
+                        Ok(Some(expr)) => {
+                            match evaluator.eval(&expr) {
+                                Ok(result) => {
+                                    for line in pretty_print(&result, &RESULT_OPTIONS).lines() {
+                                        println!("; {}", line);
+                                    }
+                                }
+                                Err(error) => {
+                                    report(&input, Diagnostic::from(&error));
+                                }
                             }
-                        },
+                            // a step that outlived the evaluation ends with it
+                            evaluator.debugger().cancel_step();
+                        }
                         Err(ParseError::NeedMoreToken) => break,
-                        Err(ParseError::UnexpectedToken(token)) => {
+                        Err(error) => {
                             parser.reset();
-                            println!("Error: Unexpected token - {token}");
+                            report(&input, Diagnostic::from(&error));
                         }
                     }
                 }
             }
             Err(ReadlineError::Eof) => {
+                if let Err(error) = parser.finish() {
+                    report(&input, Diagnostic::from(&error));
+                }
                 break;
             }
             Err(error) => {
@@ -70,3 +115,14 @@ pub fn run_repl() {
         }
     }
 }
+
+fn tokenize_line(line: &str, loc: Loc) -> Result<Vec<Token>, LexError> {
+    let mut tokens = Vec::new();
+    let mut lexer = Lexer::with_loc(line.chars(), loc);
+
+    while let Some(token) = lexer.get_token()? {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
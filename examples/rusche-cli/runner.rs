@@ -1,57 +1,172 @@
+use std::io::IsTerminal;
+use std::process::ExitCode;
+
 use rusche::{
+    coverage::{CoverageData, FileCoverage},
+    diagnostic::Diagnostic,
     eval::Evaluator,
-    lexer::{tokenize, LexError},
-    parser::{ParseError, Parser},
+    expr::Expr,
+    lexer::tokenize_source_all,
+    parser::Parser,
+    source::{SourceId, SourceMap},
 };
 
 use crate::builtin::{load_io_procs, load_vec_procs};
 
-pub fn run_file(path: &str) {
+/// How to profile a run, if at all.
+pub struct ProfileOptions<'a> {
+    /// Where to write the folded stacks, in addition to printing a table.
+    pub folded_path: Option<&'a str>,
+}
+
+/// How to report the coverage of a run, if at all.
+pub struct CoverageOptions<'a> {
+    /// Where to write an LCOV tracefile, instead of printing an annotated listing.
+    pub lcov_path: Option<&'a str>,
+}
+
+pub fn run_file(
+    path: &str,
+    profile: Option<ProfileOptions>,
+    coverage: Option<CoverageOptions>,
+) -> ExitCode {
     match std::fs::read_to_string(path) {
-        Ok(text) => run_file_content(&text),
-        Err(e) => eprintln!("Failed to read file at \"{path}\": {e}"),
+        Ok(text) => run_file_content(path, text, profile, coverage),
+        Err(e) => {
+            eprintln!("Failed to read file at \"{path}\": {e}");
+            ExitCode::FAILURE
+        }
     }
 }
 
-fn run_file_content(text: &str) {
-    let tokens = match tokenize(text) {
-        Ok(tokens) => tokens,
-        Err(LexError::IncompleteString(span)) => {
-            eprintln!("Error:{span}: Incomplete string");
-            return;
+/// Reports all lex and parse errors in the file at `path` without evaluating it.
+pub fn check_file(path: &str) -> ExitCode {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            let (_, diagnostics) = parse_source(SourceMap::add(path, text));
+            report_all(&diagnostics)
         }
-        Err(LexError::InvalidNumber(span)) => {
-            eprintln!("Error:{span}: Invalid number");
-            return;
+        Err(e) => {
+            eprintln!("Failed to read file at \"{path}\": {e}");
+            ExitCode::FAILURE
         }
-    };
-    let mut parser = Parser::with_tokens(tokens);
+    }
+}
+
+fn run_file_content(
+    path: &str,
+    text: String,
+    profile: Option<ProfileOptions>,
+    coverage: Option<CoverageOptions>,
+) -> ExitCode {
+    let source = SourceMap::add(path, text);
+    let (exprs, diagnostics) = parse_source(source);
+    if !diagnostics.is_empty() {
+        // don't run anything if the file is malformed
+        return report_all(&diagnostics);
+    }
 
     let evaluator = Evaluator::with_prelude();
 
     load_io_procs(evaluator.context());
     load_vec_procs(evaluator.context());
 
-    loop {
-        match parser.parse() {
-            Ok(None) => {
-                break;
+    if profile.is_some() {
+        evaluator.profiler().start();
+    }
+    if coverage.is_some() {
+        evaluator.coverage().start();
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for expr in exprs {
+        if let Err(error) = evaluator.eval(&expr) {
+            report_all(&[Diagnostic::from(&error)]);
+            exit_code = ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(options) = profile {
+        let profile = evaluator.profiler().stop();
+        eprint!("{}", profile.table());
+        if let Some(folded_path) = options.folded_path {
+            if let Err(e) = std::fs::write(folded_path, profile.folded_stacks()) {
+                eprintln!("Failed to write file at \"{folded_path}\": {e}");
+                exit_code = ExitCode::FAILURE;
             }
-            Ok(Some(expr)) => match evaluator.eval(&expr) {
-                Ok(_) => {}
-                Err(e) => match e.span {
-                    Some(span) => eprintln!("Error: {}:{}", span, e.message),
-                    None => eprintln!("Error: {}", e.message),
-                },
-            },
-            Err(ParseError::NeedMoreToken) => {
-                eprintln!("Failed to parse - incomplete expression");
-                break;
+        }
+    }
+
+    if let Some(options) = coverage {
+        let data = evaluator.coverage().stop();
+        let coverages = covered_sources(source, &data)
+            .into_iter()
+            .filter_map(|source| FileCoverage::new(source, &data))
+            .collect::<Vec<_>>();
+        match options.lcov_path {
+            Some(lcov_path) => {
+                let lcov = coverages
+                    .iter()
+                    .map(FileCoverage::to_lcov)
+                    .collect::<String>();
+                if let Err(e) = std::fs::write(lcov_path, lcov) {
+                    eprintln!("Failed to write file at \"{lcov_path}\": {e}");
+                    exit_code = ExitCode::FAILURE;
+                }
             }
-            Err(ParseError::UnexpectedToken(token)) => {
-                eprintln!("Error: Unexpected token - {token}");
-                break;
+            None => {
+                for coverage in &coverages {
+                    eprint!("{}", coverage.annotate());
+                }
             }
         }
     }
+    exit_code
+}
+
+/// The main source, followed by every other file that had code evaluated.
+/// Sources that aren't files, like the prelude, are left out.
+fn covered_sources(main_source: SourceId, data: &CoverageData) -> Vec<SourceId> {
+    let mut sources = vec![main_source];
+    for source in data.sources() {
+        let is_file = SourceMap::path(source).is_some_and(|path| !path.starts_with('<'));
+        if is_file && source != main_source {
+            sources.push(source);
+        }
+    }
+    sources
+}
+
+/// Parses the whole source, collecting a diagnostic for every lex and parse error.
+pub fn parse_source(source: SourceId) -> (Vec<Expr>, Vec<Diagnostic>) {
+    let (tokens, lex_errors) = tokenize_source_all(source);
+    let (exprs, parse_errors) = Parser::with_tokens(tokens).parse_all();
+
+    let mut diagnostics = lex_errors
+        .iter()
+        .map(Diagnostic::from)
+        .chain(parse_errors.iter().map(Diagnostic::from))
+        .collect::<Vec<_>>();
+
+    // errors without a location go last
+    diagnostics.sort_by_key(|diagnostic| {
+        diagnostic
+            .span()
+            .map_or(usize::MAX, |span| span.begin.offset)
+    });
+
+    (exprs, diagnostics)
+}
+
+pub fn report_all(diagnostics: &[Diagnostic]) -> ExitCode {
+    let use_color = std::io::stderr().is_terminal();
+    for diagnostic in diagnostics {
+        eprint!("{}", diagnostic.render(use_color));
+    }
+
+    if diagnostics.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
 }
@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use rusche::{
+    diagnostic::Diagnostic,
+    eval::Evaluator,
+    expr::Expr,
+    source::SourceMap,
+    testing::{Test, TestOutcome},
+};
+
+use crate::{
+    builtin::{load_io_procs, load_vec_procs},
+    runner::{parse_source, report_all},
+};
+
+const USAGE: &str = "Usage: rusche-cli test [--filter <name>] [<path>...]";
+
+/// Suffix of the files searched for tests in directories.
+const TEST_FILE_SUFFIX: &str = "-test.rsc";
+
+#[derive(Default)]
+struct Summary {
+    passed: usize,
+    failed: usize,
+    errored: usize,
+    filtered_out: usize,
+}
+
+/// Runs the tests defined with `deftest` in the files given in `args`, or found
+/// in the directories given, each in an evaluator of its own. With `--filter`,
+/// only runs the tests whose name contains the given text.
+pub fn run_tests(args: &[&str]) -> ExitCode {
+    let mut filter = None;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--filter" => match args.next() {
+                Some(&name) => filter = Some(name),
+                None => {
+                    eprintln!("{USAGE}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let mut files = Vec::new();
+    for path in &paths {
+        if let Err(e) = find_test_files(path, &mut files) {
+            eprintln!("Failed to read \"{}\": {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let mut summary = Summary::default();
+    for file in &files {
+        run_file_tests(&file.to_string_lossy(), filter, &mut summary);
+    }
+
+    let is_ok = summary.failed == 0 && summary.errored == 0;
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} errored; {} filtered out",
+        if is_ok { "ok" } else { "FAILED" },
+        summary.passed,
+        summary.failed,
+        summary.errored,
+        summary.filtered_out
+    );
+    if is_ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Adds `path` if it's a file, or the test files in it, recursively, if it's a
+/// directory.
+fn find_test_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        if entry.is_dir() {
+            find_test_files(&entry, files)?;
+        } else if name.ends_with(TEST_FILE_SUFFIX) {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+fn run_file_tests(path: &str, filter: Option<&str>, summary: &mut Summary) {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Failed to read file at \"{path}\": {e}");
+            summary.errored += 1;
+            return;
+        }
+    };
+
+    let (exprs, diagnostics) = parse_source(SourceMap::add(path, text));
+    if !diagnostics.is_empty() {
+        report_all(&diagnostics);
+        summary.errored += 1;
+        return;
+    }
+
+    // every test runs in a fresh evaluator of its own, starting with the one
+    // that found the tests, which no test has run in yet
+    let Some(evaluator) = load(&exprs) else {
+        summary.errored += 1;
+        return;
+    };
+    let tests = evaluator.tests().tests();
+    let mut unused = Some(evaluator);
+    for test in tests {
+        if filter.is_some_and(|filter| !test.name.contains(filter)) {
+            summary.filtered_out += 1;
+            continue;
+        }
+        match unused.take().or_else(|| load(&exprs)) {
+            Some(evaluator) => report_test(path, &test, test.run(&evaluator), summary),
+            None => summary.errored += 1,
+        }
+    }
+}
+
+/// Evaluates the top-level expressions of a test file in a new evaluator.
+/// Returns `None` after reporting the error if any of them fails.
+fn load(exprs: &[Expr]) -> Option<Evaluator> {
+    let evaluator = Evaluator::with_prelude();
+
+    load_io_procs(evaluator.context());
+    load_vec_procs(evaluator.context());
+
+    for expr in exprs {
+        if let Err(error) = evaluator.eval(expr) {
+            report_all(&[Diagnostic::from(&error)]);
+            return None;
+        }
+    }
+    Some(evaluator)
+}
+
+fn report_test(path: &str, test: &Test, outcome: TestOutcome, summary: &mut Summary) {
+    let name = format!("{path}: {}", test.name);
+    match outcome {
+        TestOutcome::Passed => {
+            println!("test {name} ... ok");
+            summary.passed += 1;
+        }
+        TestOutcome::Failed(error) => {
+            println!("test {name} ... FAILED");
+            report_all(&[Diagnostic::from(&error)]);
+            summary.failed += 1;
+        }
+        TestOutcome::Errored(error) => {
+            println!("test {name} ... ERROR");
+            report_all(&[Diagnostic::from(&error)]);
+            summary.errored += 1;
+        }
+    }
+}
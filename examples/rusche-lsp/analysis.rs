@@ -0,0 +1,205 @@
+//! What the server knows about a document, rebuilt whenever the document
+//! changes. Everything is derived from the tokens and the expressions of the
+//! recovering parser, so a document with errors still gets definitions for its
+//! well-formed parts.
+
+use rusche::{
+    diagnostic::Diagnostic,
+    expr::Expr,
+    lexer::Lexer,
+    list::List,
+    parser::Parser,
+    span::{Loc, Span},
+    token::Token,
+    utils::make_formal_args,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DefinitionKind {
+    Variable,
+    Procedure,
+    Macro,
+}
+
+/// A `define` or `defmacro` form in the document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// Formal arguments of a procedure or a macro.
+    pub formal_args: Option<Vec<String>>,
+    /// Span of the defined name.
+    pub name_span: Span,
+    /// Span of the whole form.
+    pub span: Span,
+}
+
+impl Definition {
+    /// E.g. `(fact n)` for a procedure, or just the name for a variable.
+    pub fn signature(&self) -> String {
+        signature(&self.name, self.formal_args.as_deref())
+    }
+}
+
+pub fn signature(name: &str, formal_args: Option<&[String]>) -> String {
+    match formal_args {
+        Some([]) => format!("({})", name),
+        Some(formal_args) => format!("({} {})", name, formal_args.join(" ")),
+        None => name.to_string(),
+    }
+}
+
+pub struct Analysis {
+    pub text: String,
+    tokens: Vec<Token>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Definitions in the order they appear, including nested ones.
+    pub definitions: Vec<Definition>,
+}
+
+impl Analysis {
+    pub fn new(text: String) -> Self {
+        let (tokens, lex_errors) = Lexer::new(text.chars()).read_all();
+        let (exprs, parse_errors) = Parser::with_tokens(tokens.clone()).parse_all();
+
+        let mut diagnostics = lex_errors
+            .iter()
+            .map(Diagnostic::from)
+            .chain(parse_errors.iter().map(Diagnostic::from))
+            .collect::<Vec<_>>();
+        diagnostics.sort_by_key(|diagnostic| {
+            diagnostic
+                .span()
+                .map_or(usize::MAX, |span| span.begin.offset)
+        });
+
+        let mut definitions = Vec::new();
+        for expr in &exprs {
+            collect_definitions(expr, &mut definitions);
+        }
+
+        Self {
+            text,
+            tokens,
+            diagnostics,
+            definitions,
+        }
+    }
+
+    /// Returns the symbol at `offset`, including when `offset` is right after it.
+    pub fn symbol_at(&self, offset: usize) -> Option<(&str, Span)> {
+        self.tokens.iter().find_map(|token| match token {
+            Token::Sym(name, span) if (span.begin.offset..=span.end.offset).contains(&offset) => {
+                Some((name.as_str(), *span))
+            }
+            _ => None,
+        })
+    }
+
+    pub fn find_definition(&self, name: &str) -> Option<&Definition> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.name == name)
+    }
+
+    /// Converts `loc` to an LSP position, whose character counts UTF-16 code units.
+    pub fn position(&self, loc: Loc) -> (usize, usize) {
+        let line_start = self.text[..loc.offset].rfind('\n').map_or(0, |i| i + 1);
+        let character = self.text[line_start..loc.offset].encode_utf16().count();
+        (loc.line - 1, character)
+    }
+
+    /// The LSP position at the end of the document.
+    pub fn end_position(&self) -> (usize, usize) {
+        let line_start = self.text.rfind('\n').map_or(0, |i| i + 1);
+        let character = self.text[line_start..].encode_utf16().count();
+        (self.text.matches('\n').count(), character)
+    }
+
+    /// Converts an LSP position to a byte offset, clamped to the end of its line.
+    pub fn offset(&self, line: usize, character: usize) -> Option<usize> {
+        let line_start = if line == 0 {
+            0
+        } else {
+            self.text.match_indices('\n').nth(line - 1)?.0 + 1
+        };
+        let line_text = self.text[line_start..].split('\n').next().unwrap_or("");
+
+        let mut units = 0;
+        for (index, ch) in line_text.char_indices() {
+            if units >= character {
+                return Some(line_start + index);
+            }
+            units += ch.len_utf16();
+        }
+        Some(line_start + line_text.len())
+    }
+}
+
+/// Collects the definitions in `expr`, including the ones nested in bodies, but
+/// not the ones in quoted data.
+fn collect_definitions(expr: &Expr, definitions: &mut Vec<Definition>) {
+    let Expr::List(List::Cons(cons), span) = expr else {
+        return;
+    };
+
+    match cons.car.as_ref() {
+        Expr::Sym(head, _) if head == "quote" || head == "quasiquote" => return,
+        Expr::Sym(head, _) if head == "define" || head == "defmacro" => {
+            if let Some(definition) = make_definition(head, &cons.cdr, *span) {
+                definitions.push(definition);
+            }
+        }
+        _ => {}
+    }
+
+    for item in cons.cdr.iter() {
+        collect_definitions(item, definitions);
+    }
+}
+
+/// Recognizes the same forms as `define` and `defmacro` do:
+/// `(define name value)`, `(define (name args) body)`,
+/// `(defmacro name (args) body)` and `(defmacro (name args) body)`.
+fn make_definition(head: &str, args: &List, span: Option<Span>) -> Option<Definition> {
+    let is_macro = head == "defmacro";
+    let mut iter = args.iter();
+
+    let (name, name_span, formal_args) = match iter.next()? {
+        Expr::Sym(name, name_span) => {
+            let formal_args = match (is_macro, iter.next()) {
+                (true, Some(Expr::List(list, _))) => make_formal_args(list).ok(),
+                (false, Some(Expr::List(List::Cons(value), _))) => match value.car.as_ref() {
+                    Expr::Sym(head, _) if head == "lambda" => match value.cdr.iter().next() {
+                        Some(Expr::List(list, _)) => make_formal_args(list).ok(),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            };
+            (name, *name_span, formal_args)
+        }
+        Expr::List(List::Cons(cons), _) => {
+            let Expr::Sym(name, name_span) = cons.car.as_ref() else {
+                return None;
+            };
+            (name, *name_span, make_formal_args(&cons.cdr).ok())
+        }
+        _ => return None,
+    };
+
+    let kind = match (is_macro, &formal_args) {
+        (true, _) => DefinitionKind::Macro,
+        (false, Some(_)) => DefinitionKind::Procedure,
+        (false, None) => DefinitionKind::Variable,
+    };
+
+    Some(Definition {
+        name: name.clone(),
+        kind,
+        formal_args,
+        name_span: name_span?,
+        span: span?,
+    })
+}
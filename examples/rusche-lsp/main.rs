@@ -0,0 +1,22 @@
+mod analysis;
+mod rpc;
+mod server;
+
+use std::io;
+use std::process::ExitCode;
+
+use server::Server;
+
+fn main() -> ExitCode {
+    let mut reader = io::stdin().lock();
+    let mut writer = io::stdout().lock();
+
+    match Server::new().run(&mut reader, &mut writer) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE, // exited without a shutdown request
+        Err(e) => {
+            eprintln!("rusche-lsp: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Framing of the LSP base protocol: headers, a blank line, then a JSON body
+//! whose length in bytes is given by the `Content-Length` header.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Largest body accepted, so that a bogus `Content-Length` can't make the server
+/// allocate arbitrary amounts of memory.
+const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Reads the body of the next message. Returns `None` at the end of the input.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut content_length = None;
+    let content_length = loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        match (line.split_once(':'), content_length) {
+            (Some((name, value)), _) if name.eq_ignore_ascii_case("Content-Length") => {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+            (None, Some(content_length)) if line.is_empty() => break content_length,
+            _ => {} // other headers, or a stray blank line between messages
+        }
+    };
+
+    if content_length > MAX_CONTENT_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Content-Length of {content_length} exceeds the limit of {MAX_CONTENT_LENGTH}"),
+        ));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_message() {
+        let mut input = "Content-Length: 2\r\n\r\n{}Content-Length: 3\r\n\r\n[1]".as_bytes();
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("{}"));
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("[1]"));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_message_too_long() {
+        let header = format!("Content-Length: {}\r\n\r\n", MAX_CONTENT_LENGTH + 1);
+        let error = read_message(&mut header.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,590 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use rusche::{diagnostic::Diagnostic, eval::Evaluator, expr::Expr, proc::Proc, span::Span};
+use serde_json::{json, Value};
+
+use crate::analysis::{signature, Analysis, Definition, DefinitionKind};
+use crate::rpc::{read_message, write_message};
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// LSP enumerations
+const TEXT_DOCUMENT_SYNC_FULL: i64 = 1;
+const SEVERITY_ERROR: i64 = 1;
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_VARIABLE: i64 = 6;
+const COMPLETION_KEYWORD: i64 = 14;
+const SYMBOL_FUNCTION: i64 = 12;
+const SYMBOL_VARIABLE: i64 = 13;
+
+type RequestResult = Result<Value, (i64, String)>;
+
+/// A name defined by the builtins or the prelude.
+struct Global {
+    name: String,
+    kind: DefinitionKind,
+    /// Formal arguments, unknown for native procedures.
+    formal_args: Option<Vec<String>>,
+}
+
+pub struct Server {
+    /// Analyses of the open documents by URI.
+    documents: HashMap<String, Analysis>,
+    globals: Vec<Global>,
+    is_shut_down: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        let evaluator = Evaluator::with_prelude();
+        let root_env = evaluator.root_env();
+
+        let mut globals = root_env
+            .names()
+            .into_iter()
+            .map(|name| {
+                let (kind, formal_args) = match root_env.lookup(&name) {
+                    Some(Expr::Proc(Proc::Closure { formal_args, .. }, _)) => {
+                        (DefinitionKind::Procedure, Some(formal_args))
+                    }
+                    Some(Expr::Proc(Proc::Macro { formal_args, .. }, _)) => {
+                        (DefinitionKind::Macro, Some(formal_args))
+                    }
+                    Some(Expr::Proc(Proc::Native { .. }, _)) => (DefinitionKind::Procedure, None),
+                    _ => (DefinitionKind::Variable, None),
+                };
+                Global {
+                    name,
+                    kind,
+                    formal_args,
+                }
+            })
+            .collect::<Vec<_>>();
+        globals.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            documents: HashMap::new(),
+            globals,
+            is_shut_down: false,
+        }
+    }
+
+    /// Serves messages until the `exit` notification or the end of the input.
+    /// Returns whether the client asked to shut down before, as the exit code
+    /// depends on it.
+    pub fn run(&mut self, reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<bool> {
+        while let Some(body) = read_message(reader)? {
+            let message = match serde_json::from_str::<Value>(&body) {
+                Ok(message) => message,
+                Err(e) => {
+                    write_message(writer, &error_response(&Value::Null, PARSE_ERROR, e))?;
+                    continue;
+                }
+            };
+            let Some(method) = message["method"].as_str() else {
+                continue; // a response to a request we never send
+            };
+            let params = &message["params"];
+
+            match message.get("id") {
+                Some(id) => {
+                    let response = match self.handle_request(method, params) {
+                        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                        Err((code, text)) => error_response(id, code, text),
+                    };
+                    write_message(writer, &response)?;
+                }
+                None if method == "exit" => return Ok(self.is_shut_down),
+                None => {
+                    for notification in self.handle_notification(method, params) {
+                        write_message(writer, &notification)?;
+                    }
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn handle_request(&mut self, method: &str, params: &Value) -> RequestResult {
+        if self.is_shut_down {
+            return Err((INVALID_REQUEST, "the server is shut down".to_string()));
+        }
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": TEXT_DOCUMENT_SYNC_FULL,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "rusche-lsp" },
+            })),
+            "shutdown" => {
+                self.is_shut_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "textDocument/documentSymbol" => Ok(self.document_symbols(params)),
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method: {}", method))),
+        }
+    }
+
+    /// Returns the notifications to send back.
+    fn handle_notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let text = match method {
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+            // the whole text, since we only support full synchronization
+            "textDocument/didChange" => params["contentChanges"]
+                .as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change["text"].as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Vec::new())];
+            }
+            _ => return Vec::new(), // e.g. `initialized`
+        };
+        let Some(text) = text else {
+            return Vec::new();
+        };
+
+        let analysis = Analysis::new(text.to_string());
+        let diagnostics = analysis
+            .diagnostics
+            .iter()
+            .map(|diagnostic| to_lsp_diagnostic(&analysis, diagnostic))
+            .collect();
+        self.documents.insert(uri.to_string(), analysis);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    /// Finds the document and the symbol at the position of a request.
+    fn symbol_at(&self, params: &Value) -> Option<(&str, &Analysis, &str, Span)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let (uri, analysis) = self.documents.get_key_value(uri)?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        let (name, span) = analysis.symbol_at(analysis.offset(line, character)?)?;
+        Some((uri, analysis, name, span))
+    }
+
+    /// Looks up a definition in the requesting document first, then in the
+    /// other open documents.
+    fn find_definition<'a>(
+        &'a self,
+        uri: &'a str,
+        name: &str,
+    ) -> Option<(&'a str, &'a Analysis, &'a Definition)> {
+        let documents = std::iter::once((uri, &self.documents[uri])).chain(
+            self.documents
+                .iter()
+                .filter(|(other, _)| *other != uri)
+                .map(|(other, analysis)| (other.as_str(), analysis)),
+        );
+        documents.into_iter().find_map(|(uri, analysis)| {
+            analysis
+                .find_definition(name)
+                .map(|definition| (uri, analysis, definition))
+        })
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let Some((uri, _, name, _)) = self.symbol_at(params) else {
+            return Value::Null;
+        };
+        match self.find_definition(uri, name) {
+            Some((uri, analysis, definition)) => json!({
+                "uri": uri,
+                "range": to_range(analysis, definition.name_span),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((uri, analysis, name, span)) = self.symbol_at(params) else {
+            return Value::Null;
+        };
+
+        let (signature, description) =
+            if let Some((_, defining, definition)) = self.find_definition(uri, name) {
+                let (line, _) = defining.position(definition.name_span.begin);
+                let description = format!(
+                    "{} defined on line {}",
+                    kind_name(definition.kind),
+                    line + 1
+                );
+                (definition.signature(), description)
+            } else if let Some(global) = self.globals.iter().find(|global| global.name == name) {
+                let description = match (global.kind, &global.formal_args) {
+                    (DefinitionKind::Procedure, None) => "builtin procedure".to_string(),
+                    (kind, _) => format!("builtin {}", kind_name(kind)),
+                };
+                (signature(name, global.formal_args.as_deref()), description)
+            } else {
+                return Value::Null;
+            };
+
+        json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```scheme\n{}\n```\n{}", signature, description),
+            },
+            "range": to_range(analysis, span),
+        })
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let mut items = Vec::new();
+        let mut names = Vec::new();
+
+        if let Some(analysis) = self.documents.get(uri) {
+            for definition in &analysis.definitions {
+                if !names.contains(&definition.name.as_str()) {
+                    names.push(&definition.name);
+                    items.push(completion_item(
+                        &definition.name,
+                        definition.kind,
+                        definition.signature(),
+                    ));
+                }
+            }
+        }
+        for global in &self.globals {
+            if !names.contains(&global.name.as_str()) {
+                let detail = signature(&global.name, global.formal_args.as_deref());
+                items.push(completion_item(&global.name, global.kind, detail));
+            }
+        }
+
+        Value::Array(items)
+    }
+
+    /// Returns the definitions as a tree, where definitions nested in the body of
+    /// another one are its children.
+    fn document_symbols(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let Some(analysis) = self.documents.get(uri) else {
+            return Value::Null;
+        };
+
+        // definitions are in pre-order, so the enclosing ones are on the stack
+        let mut stack: Vec<(&Definition, Vec<Value>)> = Vec::new();
+        let mut symbols = Vec::new();
+        let pop = |stack: &mut Vec<(&Definition, Vec<Value>)>, symbols: &mut Vec<Value>| {
+            let (definition, children) = stack.pop().unwrap();
+            let symbol = document_symbol(analysis, definition, children);
+            match stack.last_mut() {
+                Some((_, siblings)) => siblings.push(symbol),
+                None => symbols.push(symbol),
+            }
+        };
+
+        for definition in &analysis.definitions {
+            while stack
+                .last()
+                .is_some_and(|(parent, _)| parent.span.end.offset <= definition.span.begin.offset)
+            {
+                pop(&mut stack, &mut symbols);
+            }
+            stack.push((definition, Vec::new()));
+        }
+        while !stack.is_empty() {
+            pop(&mut stack, &mut symbols);
+        }
+
+        Value::Array(symbols)
+    }
+}
+
+fn kind_name(kind: DefinitionKind) -> &'static str {
+    match kind {
+        DefinitionKind::Variable => "variable",
+        DefinitionKind::Procedure => "procedure",
+        DefinitionKind::Macro => "macro",
+    }
+}
+
+fn to_range(analysis: &Analysis, span: Span) -> Value {
+    let (begin_line, begin_character) = analysis.position(span.begin);
+    let (end_line, end_character) = analysis.position(span.end);
+    json!({
+        "start": { "line": begin_line, "character": begin_character },
+        "end": { "line": end_line, "character": end_character },
+    })
+}
+
+fn to_lsp_diagnostic(analysis: &Analysis, diagnostic: &Diagnostic) -> Value {
+    let range = match diagnostic.span() {
+        Some(span) => to_range(analysis, span),
+        None => {
+            // e.g. an incomplete expression; point at the end of the document
+            let (line, character) = analysis.end_position();
+            json!({
+                "start": { "line": line, "character": character },
+                "end": { "line": line, "character": character },
+            })
+        }
+    };
+
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message += "\n";
+        message += note;
+    }
+
+    json!({
+        "range": range,
+        "severity": SEVERITY_ERROR,
+        "source": "rusche",
+        "message": message,
+    })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+fn completion_item(name: &str, kind: DefinitionKind, detail: String) -> Value {
+    let kind = match kind {
+        DefinitionKind::Variable => COMPLETION_VARIABLE,
+        DefinitionKind::Procedure => COMPLETION_FUNCTION,
+        DefinitionKind::Macro => COMPLETION_KEYWORD,
+    };
+    json!({ "label": name, "kind": kind, "detail": detail })
+}
+
+fn document_symbol(analysis: &Analysis, definition: &Definition, children: Vec<Value>) -> Value {
+    let kind = match definition.kind {
+        DefinitionKind::Variable => SYMBOL_VARIABLE,
+        DefinitionKind::Procedure | DefinitionKind::Macro => SYMBOL_FUNCTION,
+    };
+    json!({
+        "name": definition.name,
+        "detail": definition.signature(),
+        "kind": kind,
+        "range": to_range(analysis, definition.span),
+        "selectionRange": to_range(analysis, definition.name_span),
+        "children": children,
+    })
+}
+
+fn error_response(id: &Value, code: i64, message: impl ToString) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message.to_string() },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.rsc";
+
+    /// Runs the server on `messages`, as a client would send them, and returns
+    /// the messages the server sent back along with its exit status.
+    fn run_script(messages: &[Value]) -> (Vec<Value>, bool) {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+
+        let mut output = Vec::new();
+        let is_clean_exit = Server::new()
+            .run(&mut input.as_slice(), &mut output)
+            .unwrap();
+
+        let mut reader = output.as_slice();
+        let mut responses = Vec::new();
+        while let Some(body) = read_message(&mut reader).unwrap() {
+            responses.push(serde_json::from_str(&body).unwrap());
+        }
+        (responses, is_clean_exit)
+    }
+
+    fn request(id: i64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn at(line: usize, character: usize) -> Value {
+        json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    fn session(text: &str, requests: Vec<Value>) -> Vec<Value> {
+        let mut messages = vec![
+            request(0, "initialize", json!({ "capabilities": {} })),
+            notification("initialized", json!({})),
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": { "uri": URI, "languageId": "rusche", "version": 1, "text": text } }),
+            ),
+        ];
+        messages.extend(requests);
+        messages.push(request(99, "shutdown", Value::Null));
+        messages.push(notification("exit", Value::Null));
+
+        let (responses, is_clean_exit) = run_script(&messages);
+        assert!(is_clean_exit);
+        assert_eq!(responses.first().unwrap()["id"], 0);
+        assert_eq!(responses.last().unwrap()["id"], 99);
+        responses
+    }
+
+    const TEXT: &str = "\
+(define (square x) (* x x))
+(define answer 42)
+(defmacro (unless c *body) `(if ,c #f (begin ,@body)))
+(define (outer)
+  (define (inner y) y)
+  (inner (square answer)))
+";
+
+    #[test]
+    fn test_diagnostics() {
+        let responses = session("(define x \"λ\" 1x)\n(display x", vec![]);
+        let published = &responses[1];
+        assert_eq!(published["method"], "textDocument/publishDiagnostics");
+
+        let diagnostics = published["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0]["message"], "invalid number");
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({ "start": { "line": 0, "character": 14 }, "end": { "line": 0, "character": 16 } })
+        );
+        assert_eq!(diagnostics[1]["message"], "unclosed list, expected `)`");
+        assert_eq!(
+            diagnostics[1]["range"]["start"],
+            json!({ "line": 1, "character": 0 })
+        );
+    }
+
+    #[test]
+    fn test_definition_and_hover() {
+        let responses = session(
+            TEXT,
+            vec![
+                request(1, "textDocument/definition", at(5, 11)), // square
+                request(2, "textDocument/hover", at(5, 11)),
+                request(3, "textDocument/hover", at(5, 18)), // answer
+                request(4, "textDocument/hover", at(0, 20)), // *
+                request(5, "textDocument/hover", at(5, 3)),  // inner
+                request(6, "textDocument/definition", at(0, 17)), // x, not defined
+            ],
+        );
+
+        assert_eq!(responses[2]["result"]["uri"], URI);
+        assert_eq!(
+            responses[2]["result"]["range"],
+            json!({ "start": { "line": 0, "character": 9 }, "end": { "line": 0, "character": 15 } })
+        );
+        assert_eq!(
+            responses[3]["result"]["contents"]["value"],
+            "```scheme\n(square x)\n```\nprocedure defined on line 1"
+        );
+        assert_eq!(
+            responses[4]["result"]["contents"]["value"],
+            "```scheme\nanswer\n```\nvariable defined on line 2"
+        );
+        assert_eq!(
+            responses[5]["result"]["contents"]["value"],
+            "```scheme\n*\n```\nbuiltin procedure"
+        );
+        assert_eq!(
+            responses[6]["result"]["contents"]["value"],
+            "```scheme\n(inner y)\n```\nprocedure defined on line 5"
+        );
+        assert_eq!(responses[7]["result"], Value::Null);
+    }
+
+    #[test]
+    fn test_completion() {
+        let responses = session(TEXT, vec![request(1, "textDocument/completion", at(0, 0))]);
+        let items = responses[2]["result"].as_array().unwrap();
+        let find = |label: &str| items.iter().find(|item| item["label"] == label);
+
+        assert_eq!(find("square").unwrap()["detail"], "(square x)");
+        assert_eq!(find("unless").unwrap()["detail"], "(unless c *body)");
+        assert_eq!(find("unless").unwrap()["kind"], COMPLETION_KEYWORD);
+        assert_eq!(find("map").unwrap()["detail"], "(map fn lst)");
+        assert_eq!(find("car").unwrap()["kind"], COMPLETION_FUNCTION);
+        assert_eq!(find("#t").unwrap()["kind"], COMPLETION_VARIABLE);
+        assert_eq!(
+            items
+                .iter()
+                .filter(|item| item["label"] == "unless")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let responses = session(
+            TEXT,
+            vec![request(
+                1,
+                "textDocument/documentSymbol",
+                json!({ "textDocument": { "uri": URI } }),
+            )],
+        );
+        let symbols = responses[2]["result"].as_array().unwrap();
+        let names = symbols
+            .iter()
+            .map(|symbol| symbol["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["square", "answer", "unless", "outer"]);
+        assert_eq!(symbols[1]["kind"], SYMBOL_VARIABLE);
+        assert_eq!(symbols[3]["children"][0]["name"], "inner");
+        assert_eq!(
+            symbols[3]["range"],
+            json!({ "start": { "line": 3, "character": 0 }, "end": { "line": 5, "character": 26 } })
+        );
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let (responses, is_clean_exit) = run_script(&[
+            request(1, "textDocument/rename", json!({})),
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null }), // a response, ignored
+            notification("exit", Value::Null),
+        ]);
+        assert!(!is_clean_exit);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0]["error"]["code"], METHOD_NOT_FOUND);
+
+        let mut input = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+        write_message(&mut input, &request(3, "shutdown", Value::Null)).unwrap();
+        write_message(&mut input, &request(4, "shutdown", Value::Null)).unwrap();
+        let mut output = Vec::new();
+        assert!(!Server::new()
+            .run(&mut input.as_slice(), &mut output)
+            .unwrap());
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&PARSE_ERROR.to_string()));
+        assert!(output.contains(&INVALID_REQUEST.to_string()));
+    }
+}
@@ -0,0 +1,200 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::proc::Proc;
+use crate::span::Span;
+
+/// Number of innermost and outermost frames kept in a `Backtrace`. Frames in
+/// between are omitted so that deep recursions don't produce huge backtraces.
+const MAX_FRAMES_AT_EACH_END: usize = 10;
+
+/// A procedure call recorded in the call stack.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// Name of the called procedure.
+    pub name: String,
+    /// Span of the expression that made the call.
+    pub span: Option<Span>,
+    /// Number of tail calls collapsed into this frame.
+    pub tail_calls: usize,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at {}", self.name)?;
+
+        match (self.span, self.span.and_then(|span| span.path())) {
+            (Some(span), Some(path)) => write!(f, " ({}:{})", path, span.begin)?,
+            (Some(span), None) => write!(f, " ({})", span.begin)?,
+            (None, _) => {}
+        }
+
+        match self.tail_calls {
+            0 => Ok(()),
+            1 => write!(f, " [1 tail call]"),
+            n => write!(f, " [{} tail calls]", n),
+        }
+    }
+}
+
+/// The call stack at the point where an error was raised, innermost frame first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Backtrace {
+    pub frames: Vec<Frame>,
+    /// Number of frames omitted between the innermost and the outermost ones.
+    pub omitted: usize,
+}
+
+impl Backtrace {
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, frame) in self.frames.iter().enumerate() {
+            if index == MAX_FRAMES_AT_EACH_END && self.omitted > 0 {
+                writeln!(f, "  ... {} frames omitted ...", self.omitted)?;
+            }
+            writeln!(f, "  {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Frames of the procedures being evaluated, shared by all contexts of an evaluator.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CallStack(Rc<RefCell<Vec<ActiveFrame>>>);
+
+/// A frame of the call stack, rendered into a `Frame` only when a backtrace is
+/// captured.
+#[derive(Debug)]
+struct ActiveFrame {
+    name: Option<Rc<str>>,
+    anonymous_name: &'static str,
+    span: Option<Span>,
+    tail_calls: usize,
+}
+
+impl ActiveFrame {
+    fn to_frame(&self) -> Frame {
+        Frame {
+            name: self
+                .name
+                .as_deref()
+                .unwrap_or(self.anonymous_name)
+                .to_string(),
+            span: self.span,
+            tail_calls: self.tail_calls,
+        }
+    }
+}
+
+impl CallStack {
+    pub fn push(&self, proc: &Proc, span: Option<Span>) {
+        self.0.borrow_mut().push(ActiveFrame {
+            name: proc.shared_name(),
+            anonymous_name: proc.anonymous_name(),
+            span,
+            tail_calls: 0,
+        });
+    }
+
+    pub fn count_tail_call(&self) {
+        if let Some(frame) = self.0.borrow_mut().last_mut() {
+            frame.tail_calls += 1;
+        }
+    }
+
+    pub fn pop(&self) {
+        self.0.borrow_mut().pop();
+    }
+
+    pub fn depth(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn innermost_span(&self) -> Option<Span> {
+        self.0.borrow().last().and_then(|frame| frame.span)
+    }
+
+    pub fn capture(&self) -> Backtrace {
+        let frames = self.0.borrow();
+        let count = frames.len();
+
+        if count <= MAX_FRAMES_AT_EACH_END * 2 {
+            return Backtrace {
+                frames: frames.iter().rev().map(ActiveFrame::to_frame).collect(),
+                omitted: 0,
+            };
+        }
+
+        let innermost = frames[count - MAX_FRAMES_AT_EACH_END..].iter().rev();
+        let outermost = frames[..MAX_FRAMES_AT_EACH_END].iter().rev();
+        Backtrace {
+            frames: innermost
+                .chain(outermost)
+                .map(ActiveFrame::to_frame)
+                .collect(),
+            omitted: count - MAX_FRAMES_AT_EACH_END * 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::Loc;
+
+    fn frame(name: &str, line: usize) -> Frame {
+        Frame {
+            name: name.to_string(),
+            span: Some(Span::new(Loc::new(line, 1, 0), Loc::new(line, 2, 1))),
+            tail_calls: 0,
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let backtrace = Backtrace {
+            frames: vec![
+                frame("car", 2),
+                Frame {
+                    tail_calls: 3,
+                    ..frame("loop", 1)
+                },
+            ],
+            omitted: 0,
+        };
+        assert_eq!(
+            backtrace.to_string(),
+            "  at car (2:1)\n  at loop (1:1) [3 tail calls]\n"
+        );
+    }
+
+    #[test]
+    fn test_capture_deep_stack() {
+        let stack = CallStack::default();
+        let proc = Proc::Native {
+            name: "f".into(),
+            func: |_, _, _| Ok(crate::expr::NIL),
+            is_special_form: false,
+        };
+        for line in 1..=100 {
+            stack.push(&proc, frame("f", line).span);
+        }
+
+        let backtrace = stack.capture();
+        assert_eq!(backtrace.omitted, 80);
+        assert_eq!(backtrace.frames.len(), 20);
+        assert_eq!(backtrace.frames[0].name, "f");
+        assert_eq!(backtrace.frames[0].span.unwrap().begin.line, 100);
+        assert_eq!(backtrace.frames[19].span.unwrap().begin.line, 1);
+
+        let text = backtrace.to_string();
+        assert_eq!(text.lines().count(), 21);
+        assert_eq!(text.lines().nth(10), Some("  ... 80 frames omitted ..."));
+    }
+}
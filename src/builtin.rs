@@ -1,8 +1,12 @@
 pub mod quote;
 
+mod foreign;
+mod gc;
 mod num;
 mod primitive;
 mod str;
+mod test;
+mod trace;
 
 use std::rc::Rc;
 
@@ -14,13 +18,20 @@ pub fn load_builtin(env: &Rc<Env>) {
     env.define_native_proc("car", primitive::car);
     env.define_native_proc("cdr", primitive::cdr);
     env.define_native_proc("cons", primitive::cons);
-    env.define_native_proc("define", primitive::define);
-    env.define_native_proc("defmacro", primitive::defmacro);
+    env.define_special_form("define", primitive::define);
+    env.define_special_form("defmacro", primitive::defmacro);
     env.define_native_proc("eq?", primitive::eq);
+    env.define_native_proc("error", primitive::error);
     env.define_native_proc("eval", primitive::eval_);
-    env.define_native_proc("if", primitive::if_);
-    env.define_native_proc("lambda", primitive::lambda);
-    env.define_native_proc("set!", primitive::set);
+    env.define_special_form("if", primitive::if_);
+    env.define_special_form("lambda", primitive::lambda);
+    env.define_special_form("set!", primitive::set);
+
+    // foreign
+    env.define_native_proc("foreign-type", foreign::type_name);
+
+    // gc
+    env.define_native_proc("gc-stats", gc::stats);
 
     // num
     env.define_native_proc("num?", num::is_num);
@@ -38,4 +49,14 @@ pub fn load_builtin(env: &Rc<Env>) {
     env.define_native_proc("str-compare", str::compare);
     env.define_native_proc("str-length", str::length);
     env.define_native_proc("str-slice", str::slice);
+
+    // test
+    env.define_special_form("deftest", test::deftest);
+    env.define_native_proc("assert-equal", test::assert_equal);
+    env.define_special_form("assert-error", test::assert_error);
+    env.define_special_form("assert-true", test::assert_true);
+
+    // trace
+    env.define_native_proc("trace", trace::trace);
+    env.define_native_proc("untrace", trace::untrace);
 }
@@ -0,0 +1,48 @@
+use crate::{
+    eval::{EvalContext, EvalResult},
+    expr::Expr,
+    list::List,
+    utils::{eval_into_foreign, get_exact_1_arg},
+};
+
+pub fn type_name(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let object = eval_into_foreign(proc_name, get_exact_1_arg(proc_name, args)?, context)?;
+
+    Ok(Expr::from(object.type_name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Evaluator;
+    use crate::foreign::ForeignObject;
+    use crate::macros::list;
+    use std::rc::Rc;
+
+    struct Handle;
+
+    impl ForeignObject for Handle {
+        fn type_name(&self) -> &str {
+            "handle"
+        }
+    }
+
+    #[test]
+    fn test_type_name() {
+        let evaluator = Evaluator::new();
+        let context = evaluator.context();
+        let type_name = |args| type_name("", &args, context);
+
+        // (foreign-type <handle>) => "handle"
+        assert_eq!(
+            type_name(list!(Expr::Foreign(Rc::new(Handle)))),
+            Ok(Expr::from("handle"))
+        );
+
+        // (foreign-type 1) => error
+        assert!(type_name(list!(1)).is_err());
+
+        // (foreign-type) => error
+        assert!(type_name(list!()).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{
+    eval::{Arity, EvalContext, EvalError, EvalErrorKind, EvalResult},
+    expr::{intern, Expr},
+    list::List,
+};
+
+pub fn stats(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    if !args.is_nil() {
+        return Err(EvalError::from(EvalErrorKind::ArityMismatch {
+            proc_name: proc_name.to_string(),
+            expected: Arity::Exactly(0),
+            actual: args.len(),
+        }));
+    }
+
+    let Some(heap) = context.env.gc_heap() else {
+        return Err(EvalError::new(
+            EvalErrorKind::Unavailable {
+                proc_name: proc_name.to_string(),
+                what: "garbage collector".to_string(),
+            },
+            context.call_span(),
+        ));
+    };
+
+    let stats = heap.stats();
+    let field = |name: &str, value: f64| Expr::from(vec![intern(name), Expr::from(value)]);
+
+    Ok(Expr::from(vec![
+        field("envs-live", stats.envs_live as f64),
+        field("envs-reclaimed", stats.envs_reclaimed as f64),
+        field("bindings-freed", stats.bindings_freed as f64),
+        field("time-spent-ms", stats.time_spent.as_secs_f64() * 1000.0),
+        field("collections", stats.collections as f64),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Evaluator;
+    use crate::macros::list;
+
+    #[test]
+    fn test_stats() {
+        let evaluator = Evaluator::new();
+        let context = evaluator.context();
+
+        // (gc-stats) => ((envs-live 1) (envs-reclaimed 0) ...)
+        assert_eq!(
+            stats("", &list!(), context).unwrap().to_string(),
+            "((envs-live 1) (envs-reclaimed 0) (bindings-freed 0) (time-spent-ms 0) (collections 0))"
+        );
+
+        evaluator.collect_garbage();
+        let Ok(Expr::List(result, _)) = stats("", &list!(), context) else {
+            panic!("gc-stats must return a list");
+        };
+        assert_eq!(result.iter().last().unwrap().to_string(), "(collections 1)");
+
+        // (gc-stats 1) => error
+        assert!(stats("", &list!(1), context).is_err());
+    }
+
+    #[test]
+    fn test_stats_without_heap() {
+        let evaluator = Evaluator::new();
+        let context = evaluator.context().clone();
+        drop(evaluator);
+
+        let error = stats("gc-stats", &list!(), &context).unwrap_err();
+        assert_eq!(
+            *error.kind,
+            EvalErrorKind::Unavailable {
+                proc_name: "gc-stats".to_string(),
+                what: "garbage collector".to_string(),
+            }
+        );
+        assert_eq!(
+            error.message(),
+            "gc-stats: no garbage collector is available."
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use crate::{
-    eval::{eval, eval_tail, EvalContext, EvalError, EvalResult},
+    eval::{eval, eval_tail, Arity, EvalContext, EvalError, EvalErrorKind, EvalResult},
     expr::{Expr, NIL},
     list::List,
     proc::Proc,
-    utils::{get_2_or_3_args, get_exact_1_arg, get_exact_2_args, make_formal_args},
+    utils::{eval_into_str, get_2_or_3_args, get_exact_1_arg, get_exact_2_args, make_formal_args},
 };
 
 pub fn atom(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
@@ -15,52 +15,53 @@ pub fn atom(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
 pub fn car(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let expr = get_exact_1_arg(proc_name, args)?;
 
-    if let Expr::List(List::Cons(cons), _) = eval(expr, context)? {
-        Ok(cons.car.as_ref().clone())
-    } else {
-        Err(EvalError {
-            message: format!("{proc_name}: `{expr}` does not evaluate to a list."),
-            span: expr.span(),
-        })
+    match eval(expr, context)? {
+        Expr::List(List::Cons(cons), _) => Ok(cons.car.as_ref().clone()),
+        value => Err(EvalError::new(
+            EvalErrorKind::type_mismatch(proc_name, "non-empty list", &value),
+            expr.span(),
+        )),
     }
 }
 
 pub fn cdr(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let expr = get_exact_1_arg(proc_name, args)?;
 
-    if let Expr::List(List::Cons(cons), _) = eval(expr, context)? {
-        Ok(cons.cdr.as_ref().clone().into())
-    } else {
-        Err(EvalError {
-            message: format!("{proc_name}: `{expr}` does not evaluate to a list."),
-            span: expr.span(),
-        })
+    match eval(expr, context)? {
+        Expr::List(List::Cons(cons), _) => Ok(cons.cdr.as_ref().clone().into()),
+        value => Err(EvalError::new(
+            EvalErrorKind::type_mismatch(proc_name, "non-empty list", &value),
+            expr.span(),
+        )),
     }
 }
 
 pub fn cons(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
-    let (car, cdr) = get_exact_2_args(proc_name, args)?;
+    let (car, cdr_expr) = get_exact_2_args(proc_name, args)?;
 
     let car = eval(car, context)?;
-    let Expr::List(cdr, _) = eval(cdr, context)? else {
-        return Err(EvalError {
-            message: format!("{proc_name}: `{cdr}` does not evaluate to a list."),
-            span: cdr.span(),
-        });
-    };
-
-    Ok(crate::list::cons(car, cdr).into())
+    match eval(cdr_expr, context)? {
+        Expr::List(cdr, _) => Ok(crate::list::cons(car, cdr).into()),
+        value => Err(EvalError::new(
+            EvalErrorKind::type_mismatch(proc_name, "list", &value),
+            cdr_expr.span(),
+        )),
+    }
 }
 
 pub fn define(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let mut iter = args.iter();
     match iter.next() {
-        Some(Expr::Sym(name, span)) => {
+        Some(Expr::Sym(name, _)) => {
             let Some(expr) = iter.next() else {
-                return Err(EvalError {
-                    message: format!("{proc_name}: define expects a expression after symbol"),
-                    span: *span,
-                });
+                return Err(EvalError::new(
+                    EvalErrorKind::ArityMismatch {
+                        proc_name: proc_name.to_string(),
+                        expected: Arity::Exactly(2),
+                        actual: 1,
+                    },
+                    context.call_span(),
+                ));
             };
 
             context.env.define(name, eval(expr, context)?);
@@ -68,17 +69,17 @@ pub fn define(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult
         }
         Some(Expr::List(List::Cons(cons), _)) => {
             let Expr::Sym(name, _) = cons.car.as_ref() else {
-                return Err(EvalError {
-                    message: format!("{proc_name}: expects a symbol for a procedure name"),
-                    span: cons.car.span(),
-                });
+                return Err(EvalError::new(
+                    EvalErrorKind::type_mismatch(proc_name, "symbol", &cons.car),
+                    cons.car.span(),
+                ));
             };
 
             context.env.define(
                 name,
                 Expr::Proc(
                     Proc::Closure {
-                        name: Some(name.to_string()),
+                        name: Some(name.as_str().into()),
                         formal_args: make_formal_args(&cons.cdr)?,
                         body: Box::new(iter.into()),
                         outer_context: context.clone(),
@@ -88,9 +89,18 @@ pub fn define(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult
             );
             Ok(NIL)
         }
-        _ => Err(EvalError::from(format!(
-            "{proc_name}: invalid definition form -- expected a symbol or a list."
-        ))),
+        Some(expr) => Err(EvalError::new(
+            EvalErrorKind::type_mismatch(proc_name, "symbol or non-empty list", expr),
+            expr.span(),
+        )),
+        None => Err(EvalError::new(
+            EvalErrorKind::ArityMismatch {
+                proc_name: proc_name.to_string(),
+                expected: Arity::AtLeast(2),
+                actual: 0,
+            },
+            context.call_span(),
+        )),
     }
 }
 
@@ -100,14 +110,24 @@ pub fn defmacro(proc_name: &str, args: &List, context: &EvalContext) -> EvalResu
     let (macro_name, formal_args) = match expr {
         // (defmacro name (args) body)
         Some(Expr::Sym(macro_name, _)) => {
-            let expr = iter.next();
-            let Some(Expr::List(list, _)) = expr else {
-                return Err(EvalError {
-                    message: format!(
-                        "{proc_name}: expected a list of formal arguments after a macro name."
-                    ),
-                    span: expr.map(|e| e.span()).unwrap_or(None),
-                });
+            let list = match iter.next() {
+                Some(Expr::List(list, _)) => list,
+                Some(expr) => {
+                    return Err(EvalError::new(
+                        EvalErrorKind::type_mismatch(proc_name, "list", expr),
+                        expr.span(),
+                    ));
+                }
+                None => {
+                    return Err(EvalError::new(
+                        EvalErrorKind::ArityMismatch {
+                            proc_name: proc_name.to_string(),
+                            expected: Arity::AtLeast(2),
+                            actual: 1,
+                        },
+                        context.call_span(),
+                    ));
+                }
             };
 
             (macro_name, make_formal_args(list)?)
@@ -115,21 +135,29 @@ pub fn defmacro(proc_name: &str, args: &List, context: &EvalContext) -> EvalResu
         // (defmacro (name args) body)
         Some(Expr::List(List::Cons(cons), _)) => {
             let Expr::Sym(macro_name, _) = cons.car.as_ref() else {
-                return Err(EvalError {
-                    message: format!(
-                        "{proc_name}: a macro name expected as the first element of the list."
-                    ),
-                    span: cons.car.span(),
-                });
+                return Err(EvalError::new(
+                    EvalErrorKind::type_mismatch(proc_name, "symbol", &cons.car),
+                    cons.car.span(),
+                ));
             };
 
             (macro_name, make_formal_args(&cons.cdr)?)
         }
-        _ => {
-            return Err(EvalError {
-                message: format!("{proc_name}: invalid macro form -- expected a symbol or a list."),
-                span: expr.map(|e| e.span()).unwrap_or(None),
-            });
+        Some(expr) => {
+            return Err(EvalError::new(
+                EvalErrorKind::type_mismatch(proc_name, "symbol or non-empty list", expr),
+                expr.span(),
+            ));
+        }
+        None => {
+            return Err(EvalError::new(
+                EvalErrorKind::ArityMismatch {
+                    proc_name: proc_name.to_string(),
+                    expected: Arity::AtLeast(2),
+                    actual: 0,
+                },
+                context.call_span(),
+            ));
         }
     };
 
@@ -137,7 +165,7 @@ pub fn defmacro(proc_name: &str, args: &List, context: &EvalContext) -> EvalResu
         macro_name,
         Expr::Proc(
             Proc::Macro {
-                name: Some(macro_name.clone()),
+                name: Some(macro_name.as_str().into()),
                 formal_args,
                 body: Box::new(iter.into()),
             },
@@ -154,6 +182,27 @@ pub fn eq(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     Ok((eval(left, context)? == eval(right, context)?).into())
 }
 
+pub fn error(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let mut iter = args.iter();
+    let Some(message) = iter.next() else {
+        return Err(EvalError::from(EvalErrorKind::ArityMismatch {
+            proc_name: proc_name.to_string(),
+            expected: Arity::AtLeast(1),
+            actual: 0,
+        }));
+    };
+
+    let message = eval_into_str(proc_name, message, context)?;
+    let irritants = iter
+        .map(|expr| eval(expr, context))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Err(EvalError::new(
+        EvalErrorKind::UserRaised { message, irritants },
+        args.span(),
+    ))
+}
+
 pub fn eval_(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let expr = get_exact_1_arg(proc_name, args)?;
 
@@ -175,12 +224,24 @@ pub fn if_(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
 pub fn lambda(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let mut iter = args.iter();
 
-    let expr = iter.next();
-    let Some(Expr::List(list, _)) = expr else {
-        return Err(EvalError {
-            message: format!("{proc_name}: expected a list of formal arguments."),
-            span: expr.map(|e| e.span()).unwrap_or(None),
-        });
+    let list = match iter.next() {
+        Some(Expr::List(list, _)) => list,
+        Some(expr) => {
+            return Err(EvalError::new(
+                EvalErrorKind::type_mismatch(proc_name, "list", expr),
+                expr.span(),
+            ));
+        }
+        None => {
+            return Err(EvalError::new(
+                EvalErrorKind::ArityMismatch {
+                    proc_name: proc_name.to_string(),
+                    expected: Arity::AtLeast(1),
+                    actual: 0,
+                },
+                context.call_span(),
+            ));
+        }
     };
 
     Ok(Expr::Proc(
@@ -198,10 +259,10 @@ pub fn set(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let (name_expr, value_expr) = get_exact_2_args(proc_name, args)?;
 
     let Expr::Sym(name, _) = name_expr else {
-        return Err(EvalError {
-            message: format!("{proc_name}: expects a symbol as the first argument"),
-            span: name_expr.span(),
-        });
+        return Err(EvalError::new(
+            EvalErrorKind::type_mismatch(proc_name, "symbol", name_expr),
+            name_expr.span(),
+        ));
     };
 
     context.env.update(name, eval(value_expr, context)?);
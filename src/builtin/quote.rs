@@ -1,4 +1,4 @@
-use crate::eval::{eval, EvalContext, EvalError, EvalResult};
+use crate::eval::{eval, Arity, EvalContext, EvalError, EvalErrorKind, EvalResult};
 use crate::expr::{Expr, NIL};
 use crate::list::List;
 use crate::utils::get_exact_1_arg;
@@ -14,21 +14,23 @@ pub fn quote(proc_name: &str, args: &List, _context: &EvalContext) -> EvalResult
 
 pub fn quasiquote(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let expr = get_exact_1_arg(proc_name, args)?;
-    let mut exprs = quasiquote_expr(proc_name, expr, context)?;
+    let mut exprs = quasiquote_expr(expr, context)?;
     if exprs.len() == 1 {
         Ok(exprs.remove(0))
     } else {
-        Err(EvalError::from(format!(
-            "{proc_name}: expects only 1 argument"
-        )))
+        // e.g. `,@'(1 2)
+        Err(EvalError::new(
+            EvalErrorKind::ArityMismatch {
+                proc_name: proc_name.to_string(),
+                expected: Arity::Exactly(1),
+                actual: exprs.len(),
+            },
+            expr.span(),
+        ))
     }
 }
 
-fn quasiquote_expr(
-    proc_name: &str,
-    expr: &Expr,
-    context: &EvalContext,
-) -> Result<Vec<Expr>, EvalError> {
+fn quasiquote_expr(expr: &Expr, context: &EvalContext) -> Result<Vec<Expr>, EvalError> {
     let Expr::List(list, _) = expr else {
         return Ok(vec![expr.clone()]);
     };
@@ -48,10 +50,14 @@ fn quasiquote_expr(
             if let Some(cdar) = cons.cdar() {
                 exprs.push(eval(cdar, context)?);
             } else {
-                return Err(EvalError {
-                    message: format!("{UNQUOTE}: missing argument"),
-                    span: expr.span(),
-                });
+                return Err(EvalError::new(
+                    EvalErrorKind::ArityMismatch {
+                        proc_name: UNQUOTE.to_string(),
+                        expected: Arity::Exactly(1),
+                        actual: 0,
+                    },
+                    expr.span(),
+                ));
             }
         }
         Some(UNQUOTE_SPLICING) => {
@@ -59,28 +65,30 @@ fn quasiquote_expr(
                 match eval(cdar, context)? {
                     Expr::List(list, _) => {
                         // TODO: implement consuming `into_iter()`
-                        exprs.extend(list.iter().map(|e| e.clone()));
+                        exprs.extend(list.iter().cloned());
                     }
-                    _ => {
-                        return Err(EvalError {
-                            message: format!(
-                                "{UNQUOTE_SPLICING}: `{cdar}` does not evaluate to a list"
-                            ),
-                            span: cdar.span(),
-                        });
+                    value => {
+                        return Err(EvalError::new(
+                            EvalErrorKind::type_mismatch(UNQUOTE_SPLICING, "list", &value),
+                            cdar.span(),
+                        ));
                     }
                 }
             } else {
-                return Err(EvalError {
-                    message: format!("{UNQUOTE_SPLICING}: argument missing"),
-                    span: expr.span(),
-                });
+                return Err(EvalError::new(
+                    EvalErrorKind::ArityMismatch {
+                        proc_name: UNQUOTE_SPLICING.to_string(),
+                        expected: Arity::Exactly(1),
+                        actual: 0,
+                    },
+                    expr.span(),
+                ));
             }
         }
         _ => {
             let mut v = Vec::with_capacity(list.len());
             for expr in list.iter() {
-                v.extend(quasiquote_expr(proc_name, expr, context)?);
+                v.extend(quasiquote_expr(expr, context)?);
             }
             exprs.push(Expr::from(v));
         }
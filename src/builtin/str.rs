@@ -1,5 +1,5 @@
 use crate::{
-    eval::{eval, EvalContext, EvalError, EvalResult},
+    eval::{eval, EvalContext, EvalError, EvalErrorKind, EvalResult},
     expr::Expr,
     list::List,
     utils::{eval_into_int, eval_into_str, get_2_or_3_args, get_exact_1_arg, get_exact_2_args},
@@ -14,16 +14,15 @@ pub fn is_str(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult
 }
 
 pub fn append(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
-    let mut args = args.iter();
     let mut result = String::from("");
-    while let Some(expr) = args.next() {
+    for expr in args.iter() {
         match eval(expr, context)? {
             Expr::Str(text, _) => result += &text,
-            _ => {
-                return Err(EvalError {
-                    message: format!("{proc_name}: `{expr}` does not evaluate to a string."),
-                    span: expr.span(),
-                })
+            value => {
+                return Err(EvalError::new(
+                    EvalErrorKind::type_mismatch(proc_name, "string", &value),
+                    expr.span(),
+                ))
             }
         }
     }
@@ -41,13 +40,12 @@ pub fn compare(proc_name: &str, args: &List, context: &EvalContext) -> EvalResul
 
 pub fn length(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
     let expr = get_exact_1_arg(proc_name, args)?;
-    if let Expr::Str(text, _) = eval(expr, context)? {
-        Ok(Expr::from(text.chars().count() as i32))
-    } else {
-        Err(EvalError {
-            message: format!("{proc_name}: `{expr}` does not evaluate to a string."),
-            span: expr.span(),
-        })
+    match eval(expr, context)? {
+        Expr::Str(text, _) => Ok(Expr::from(text.chars().count() as i32)),
+        value => Err(EvalError::new(
+            EvalErrorKind::type_mismatch(proc_name, "string", &value),
+            expr.span(),
+        )),
     }
 }
 
@@ -61,7 +59,7 @@ pub fn slice(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult
     let end = if let Some(arg3) = opt_arg3 {
         eval_into_int(proc_name, "end index", arg3, context)?
     } else {
-        text_len as i32
+        text_len
     };
 
     let to_index = |pos: i32| -> usize {
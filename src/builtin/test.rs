@@ -0,0 +1,199 @@
+use crate::{
+    eval::{eval, Arity, EvalContext, EvalError, EvalErrorKind, EvalResult},
+    expr::{Expr, NIL},
+    list::List,
+    testing::Test,
+    utils::{eval_into_str, get_exact_1_arg, get_exact_2_args},
+};
+
+/// `(deftest name body...)` defines a test named with a symbol or a string,
+/// without running it.
+pub fn deftest(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let mut iter = args.iter();
+    let name = match iter.next() {
+        Some(Expr::Sym(name, _) | Expr::Str(name, _)) => name.clone(),
+        Some(expr) => {
+            return Err(EvalError::new(
+                EvalErrorKind::type_mismatch(proc_name, "symbol or string", expr),
+                expr.span(),
+            ));
+        }
+        None => {
+            return Err(EvalError::new(
+                EvalErrorKind::ArityMismatch {
+                    proc_name: proc_name.to_string(),
+                    expected: Arity::AtLeast(1),
+                    actual: 0,
+                },
+                context.call_span(),
+            ));
+        }
+    };
+
+    context.tests().register(Test {
+        name,
+        body: iter.into(),
+        span: args.iter().next().and_then(Expr::span),
+    });
+    Ok(NIL)
+}
+
+/// `(assert-equal expected actual)` fails unless both are equal.
+pub fn assert_equal(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let (expected, actual_expr) = get_exact_2_args(proc_name, args)?;
+
+    let expected = eval(expected, context)?;
+    let actual = eval(actual_expr, context)?;
+    if actual == expected {
+        return Ok(NIL);
+    }
+    Err(assertion_failed(
+        format!("{proc_name}: expected {expected}, but got {actual}."),
+        actual_expr,
+    ))
+}
+
+/// `(assert-true expr)` fails if `expr` is false.
+pub fn assert_true(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let expr = get_exact_1_arg(proc_name, args)?;
+
+    let value = eval(expr, context)?;
+    if value.is_truthy() {
+        return Ok(NIL);
+    }
+    Err(assertion_failed(
+        format!("{proc_name}: expected `{expr}` to be true, but got {value}."),
+        expr,
+    ))
+}
+
+/// `(assert-error expr [message])` fails unless evaluating `expr` raises an
+/// error, whose message contains `message` if given.
+pub fn assert_error(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let mut iter = args.iter();
+    let (Some(expr), message, None) = (iter.next(), iter.next(), iter.next()) else {
+        return Err(EvalError::from(EvalErrorKind::ArityMismatch {
+            proc_name: proc_name.to_string(),
+            expected: Arity::Between(1, 2),
+            actual: args.iter().count(),
+        }));
+    };
+    let message = match message {
+        Some(message) => Some(eval_into_str(proc_name, message, context)?),
+        None => None,
+    };
+
+    match (eval(expr, context), message) {
+        (Err(error), Some(message)) if !error.message().contains(&message) => {
+            Err(assertion_failed(
+                format!(
+                    "{proc_name}: expected an error containing \"{message}\", but got: {}",
+                    error.message()
+                ),
+                expr,
+            ))
+        }
+        (Err(_), _) => Ok(NIL),
+        (Ok(value), _) => Err(assertion_failed(
+            format!("{proc_name}: expected `{expr}` to raise an error, but got {value}."),
+            expr,
+        )),
+    }
+}
+
+fn assertion_failed(message: String, expr: &Expr) -> EvalError {
+    EvalError::new(EvalErrorKind::AssertionFailed { message }, expr.span())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Evaluator;
+    use crate::lexer::tokenize;
+    use crate::parser::Parser;
+    use crate::testing::TestOutcome;
+
+    fn eval_all(evaluator: &Evaluator, text: &str) -> Vec<EvalResult> {
+        let mut parser = Parser::with_tokens(tokenize(text).unwrap());
+        let mut results = Vec::new();
+        while let Some(expr) = parser.parse().unwrap() {
+            results.push(evaluator.eval(&expr));
+        }
+        results
+    }
+
+    fn messages(text: &str) -> Vec<String> {
+        eval_all(&Evaluator::with_prelude(), text)
+            .into_iter()
+            .map(|result| match result {
+                Ok(value) => value.to_string(),
+                Err(error) => match *error.kind {
+                    EvalErrorKind::AssertionFailed { message } => format!("failed: {message}"),
+                    kind => format!("error: {kind}"),
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_assertions() {
+        assert_eq!(
+            messages(
+                r#"
+(assert-equal '(1 2) (list 1 (+ 1 1)))
+(assert-equal "a" (str-append "a" "b"))
+(assert-true (< 1 2))
+(assert-true (> 1 2))
+(assert-error (car 1))
+(assert-error (car 1) "non-empty list")
+(assert-error (car 1) "vector")
+(assert-error (+ 1 2))
+(assert-equal 1)"#
+            ),
+            vec![
+                "()",
+                "failed: assert-equal: expected \"a\", but got \"ab\".",
+                "()",
+                "failed: assert-true: expected `(> 1 2)` to be true, but got ().",
+                "()",
+                "()",
+                "failed: assert-error: expected an error containing \"vector\", but got: \
+                 car: expected a non-empty list, but got a number.",
+                "failed: assert-error: expected `(+ 1 2)` to raise an error, but got 3.",
+                "error: assert-equal: expects 2 arguments, but got 1.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deftest() {
+        let text = r#"
+(define x 1)
+(deftest increment (set! x (+ x 1)) (assert-equal 2 x))
+(deftest "calls car" (car x))
+(deftest 1)"#;
+        let load = || {
+            let evaluator = Evaluator::with_prelude();
+            let results = eval_all(&evaluator, text);
+            assert!(results[3].is_err());
+            evaluator
+        };
+
+        let tests = load().tests().tests();
+        let names = tests
+            .iter()
+            .map(|test| test.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["increment", "calls car"]);
+
+        // the definitions don't run the tests
+        let evaluator = load();
+        assert_eq!(eval_all(&evaluator, "x")[0], Ok(Expr::from(1)));
+
+        assert_eq!(tests[0].run(&evaluator), TestOutcome::Passed);
+        // isolated tests don't see each other's changes
+        assert_eq!(tests[0].run(&load()), TestOutcome::Passed);
+        assert!(matches!(tests[0].run(&evaluator), TestOutcome::Failed(_)));
+        assert!(matches!(tests[1].run(&load()), TestOutcome::Errored(_)));
+    }
+}
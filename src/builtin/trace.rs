@@ -0,0 +1,121 @@
+use crate::{
+    eval::{eval, EvalContext, EvalError, EvalErrorKind, EvalResult},
+    expr::{intern, Expr},
+    list::List,
+    proc::Proc,
+};
+
+/// `(trace proc ...)` traces the given procedures and returns their names.
+/// Without arguments, it returns the names of the traced procedures.
+pub fn trace(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let tracer = context.tracer();
+    if args.is_nil() {
+        return Ok(make_names(tracer.traced()));
+    }
+
+    let procs = eval_into_procs(proc_name, args, context)?;
+    for proc in &procs {
+        tracer.trace(proc);
+    }
+    Ok(make_names(procs.iter().map(|proc| proc.name().to_string())))
+}
+
+/// `(untrace proc ...)` stops tracing the given procedures and returns their
+/// names. Without arguments, it stops tracing every procedure.
+pub fn untrace(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult {
+    let tracer = context.tracer();
+    if args.is_nil() {
+        let names = tracer.traced();
+        tracer.untrace_all();
+        return Ok(make_names(names));
+    }
+
+    let procs = eval_into_procs(proc_name, args, context)?;
+    for proc in &procs {
+        tracer.untrace(proc);
+    }
+    Ok(make_names(procs.iter().map(|proc| proc.name().to_string())))
+}
+
+fn eval_into_procs(
+    proc_name: &str,
+    args: &List,
+    context: &EvalContext,
+) -> Result<Vec<Proc>, EvalError> {
+    args.iter()
+        .map(|arg| match eval(arg, context)? {
+            Expr::Proc(proc, _) => match proc {
+                Proc::Closure { name: None, .. } | Proc::Macro { name: None, .. } => {
+                    Err(EvalError::new(
+                        format!("{proc_name}: can't trace a procedure without a name"),
+                        arg.span(),
+                    ))
+                }
+                Proc::Native {
+                    name,
+                    is_special_form: true,
+                    ..
+                } => Err(EvalError::new(
+                    format!("{proc_name}: can't trace the special form `{name}`"),
+                    arg.span(),
+                )),
+                _ => Ok(proc),
+            },
+            value => Err(EvalError::new(
+                EvalErrorKind::type_mismatch(proc_name, "procedure", &value),
+                arg.span(),
+            )),
+        })
+        .collect()
+}
+
+fn make_names(names: impl IntoIterator<Item = String>) -> Expr {
+    Expr::from(names.into_iter().map(intern).collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Evaluator;
+    use crate::lexer::tokenize;
+    use crate::parser::Parser;
+
+    fn eval_all(evaluator: &Evaluator, text: &str) -> Vec<EvalResult> {
+        let mut parser = Parser::with_tokens(tokenize(text).unwrap());
+        let mut results = Vec::new();
+        while let Some(expr) = parser.parse().unwrap() {
+            results.push(evaluator.eval(&expr));
+        }
+        results
+    }
+
+    #[test]
+    fn test_trace_and_untrace() {
+        let evaluator = Evaluator::with_prelude();
+        evaluator.tracer().set_sink(Some(Box::new(|_| {})));
+
+        let results = eval_all(
+            &evaluator,
+            "\
+(define (f x) x)
+(define g f)
+(trace g car)
+(trace)
+(untrace f)
+(trace)
+(untrace)
+(trace)",
+        )
+        .into_iter()
+        .skip(2)
+        .map(|result| result.unwrap().to_string())
+        .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            vec!["(f car)", "(car f)", "(f)", "(car)", "(car)", "()"]
+        );
+
+        let results = eval_all(&evaluator, "(trace 1) (trace (lambda (x) x)) (trace h)");
+        assert!(results.iter().all(|result| result.is_err()));
+    }
+}
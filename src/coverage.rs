@@ -0,0 +1,603 @@
+//! Code coverage.
+//!
+//! Every `Evaluator` has a `Coverage` recorder. While it runs, it counts how
+//! many times each expression read from a source in the `SourceMap` is
+//! evaluated. A `FileCoverage` matches these counts with the expressions of a
+//! source that can be evaluated, and reports them as LCOV or as an annotated
+//! listing.
+//!
+//! # Example
+//!
+//! ```
+//! use rusche::{
+//!     coverage::FileCoverage, eval::Evaluator, lexer::tokenize_source, parser::Parser,
+//!     source::SourceMap,
+//! };
+//!
+//! let evaluator = Evaluator::with_prelude();
+//! let source = SourceMap::add("abs.rsc", "(define (abs x) (if (< x 0) (- x) x))\n(abs 1)\n");
+//! let exprs = Parser::with_tokens(tokenize_source(source).unwrap()).parse_all().0;
+//!
+//! evaluator.coverage().start();
+//! for expr in exprs {
+//!     evaluator.eval(&expr).unwrap();
+//! }
+//! let coverage = FileCoverage::new(source, &evaluator.coverage().stop()).unwrap();
+//!
+//! // `(- x)` never ran
+//! assert_eq!(coverage.branches[0].hits, Some(0));
+//! assert_eq!(coverage.branches[1].hits, Some(1));
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::{
+    expr::Expr,
+    lexer::tokenize_source_all,
+    list::List,
+    parser::Parser,
+    source::{SourceId, SourceMap},
+    span::Span,
+};
+
+type SpanKey = (SourceId, usize, usize);
+
+fn span_key(span: &Span) -> Option<SpanKey> {
+    span.source
+        .map(|source| (source, span.begin.offset, span.end.offset))
+}
+
+/// How many times each expression was evaluated.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CoverageData {
+    hits: HashMap<SpanKey, usize>,
+}
+
+impl CoverageData {
+    /// Returns how many times the expression at `span` was evaluated.
+    pub fn hits(&self, span: &Span) -> usize {
+        span_key(span).map_or(0, |key| self.hits.get(&key).copied().unwrap_or(0))
+    }
+
+    /// Returns the sources that had any expression evaluated, ordered by path.
+    pub fn sources(&self) -> Vec<SourceId> {
+        let mut sources = self
+            .hits
+            .keys()
+            .map(|(source, _, _)| *source)
+            .collect::<Vec<_>>();
+        sources.sort_by_key(|source| (SourceMap::path(*source), format!("{}", source)));
+        sources.dedup();
+        sources
+    }
+}
+
+#[derive(Default)]
+struct CoverageState {
+    /// Checked before every evaluation, so it's kept out of the `RefCell`.
+    is_running: Cell<bool>,
+    data: RefCell<CoverageData>,
+}
+
+/// Records the evaluated expressions while running. Clones share the same
+/// records.
+#[derive(Clone, Default)]
+pub struct Coverage(Rc<CoverageState>);
+
+impl std::fmt::Debug for Coverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Coverage")
+            .field("is_running", &self.is_running())
+            .finish()
+    }
+}
+
+impl Coverage {
+    /// Starts recording, discarding what was recorded before.
+    pub fn start(&self) {
+        self.0.data.take();
+        self.0.is_running.set(true);
+    }
+
+    /// Stops recording and returns what was recorded.
+    pub fn stop(&self) -> CoverageData {
+        self.0.is_running.set(false);
+        self.0.data.take()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.0.is_running.get()
+    }
+
+    /// Returns what was recorded so far, without stopping.
+    pub fn data(&self) -> CoverageData {
+        self.0.data.borrow().clone()
+    }
+
+    /// Called before evaluating the expression at `span`.
+    pub(crate) fn record(&self, span: &Span) {
+        if !self.0.is_running.get() {
+            return;
+        }
+        if let Some(key) = span_key(span) {
+            *self.0.data.borrow_mut().hits.entry(key).or_default() += 1;
+        }
+    }
+}
+
+/// A procedure defined with `(define (name args) body...)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionCoverage {
+    pub name: String,
+    /// Span of the `define` form.
+    pub span: Span,
+    /// Number of calls, i.e. of evaluations of the first expression of the body.
+    pub hits: usize,
+}
+
+/// A branch of an `if` or a clause of a `cond`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BranchCoverage {
+    /// Span of the `if` or `cond` form.
+    pub span: Span,
+    /// Numbers the `if` and `cond` forms of the file, starting at 0.
+    pub block: usize,
+    /// Numbers the branches of the form, starting at 0.
+    pub branch: usize,
+    /// Number of times the branch was taken, or `None` if the form never ran.
+    pub hits: Option<usize>,
+}
+
+/// Coverage of a source file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileCoverage {
+    pub path: String,
+    text: Rc<str>,
+    /// Expressions that can be evaluated, in source order, with their hits.
+    pub exprs: Vec<(Span, usize)>,
+    pub functions: Vec<FunctionCoverage>,
+    pub branches: Vec<BranchCoverage>,
+}
+
+impl FileCoverage {
+    /// Matches `data` with the expressions of `source`. Returns `None` if
+    /// `source` isn't in the `SourceMap`.
+    ///
+    /// The arguments of macros are assumed to be evaluated, except for those
+    /// of `quote`, `quasiquote`, `define`, `lambda`, `defmacro`, `set!`,
+    /// `deftest` and the `let` and `cond` of the prelude, which are known not
+    /// to be.
+    pub fn new(source: SourceId, data: &CoverageData) -> Option<Self> {
+        let file = SourceMap::get(source)?;
+        let (tokens, _) = tokenize_source_all(source);
+        let (exprs, _) = Parser::with_tokens(tokens).parse_all();
+
+        let mut collector = Collector {
+            data,
+            coverage: FileCoverage {
+                path: file.path.clone(),
+                text: file.text.clone(),
+                exprs: Vec::new(),
+                functions: Vec::new(),
+                branches: Vec::new(),
+            },
+            blocks: 0,
+        };
+        for expr in &exprs {
+            collector.collect(expr);
+        }
+        Some(collector.coverage)
+    }
+
+    /// Returns each line with an expression that can be evaluated, along with
+    /// the most times an expression starting on it was evaluated.
+    pub fn lines(&self) -> Vec<(usize, usize)> {
+        let mut lines = BTreeMap::new();
+        for (span, hits) in &self.exprs {
+            let line_hits = lines.entry(span.begin.line).or_insert(0);
+            *line_hits = (*line_hits).max(*hits);
+        }
+        lines.into_iter().collect()
+    }
+
+    /// Formats the coverage as an LCOV tracefile record.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = format!("TN:\nSF:{}\n", self.path);
+
+        for function in &self.functions {
+            let _ = writeln!(lcov, "FN:{},{}", function.span.begin.line, function.name);
+        }
+        for function in &self.functions {
+            let _ = writeln!(lcov, "FNDA:{},{}", function.hits, function.name);
+        }
+        let functions_hit = self.functions.iter().filter(|f| f.hits > 0).count();
+        let _ = writeln!(lcov, "FNF:{}\nFNH:{}", self.functions.len(), functions_hit);
+
+        for branch in &self.branches {
+            let taken = branch
+                .hits
+                .map_or_else(|| "-".to_string(), |hits| hits.to_string());
+            let _ = writeln!(
+                lcov,
+                "BRDA:{},{},{},{}",
+                branch.span.begin.line, branch.block, branch.branch, taken
+            );
+        }
+        let branches_hit = self
+            .branches
+            .iter()
+            .filter(|branch| branch.hits.is_some_and(|hits| hits > 0))
+            .count();
+        let _ = writeln!(lcov, "BRF:{}\nBRH:{}", self.branches.len(), branches_hit);
+
+        let lines = self.lines();
+        for (line, hits) in &lines {
+            let _ = writeln!(lcov, "DA:{},{}", line, hits);
+        }
+        let lines_hit = lines.iter().filter(|(_, hits)| *hits > 0).count();
+        let _ = writeln!(lcov, "LF:{}\nLH:{}", lines.len(), lines_hit);
+
+        lcov.push_str("end_of_record\n");
+        lcov
+    }
+
+    /// Formats the source with the hits of each line in the margin, "#####" for
+    /// lines that never ran, and marks under the expressions that never ran on
+    /// lines that did. The first line names the source, as gcov does.
+    pub fn annotate(&self) -> String {
+        let lines = self.lines().into_iter().collect::<HashMap<_, _>>();
+
+        // outermost expressions that never ran, as the ones in them didn't either
+        let never_ran = self
+            .exprs
+            .iter()
+            .filter(|(_, hits)| *hits == 0)
+            .map(|(span, _)| span)
+            .collect::<Vec<_>>();
+        let never_ran = never_ran
+            .iter()
+            .filter(|span| !never_ran.iter().any(|outer| contains(outer, span)))
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut listing = format!("{:>9}: {:>4}: Source:{}\n", "-", 0, self.path);
+        for (index, text) in self.text.lines().enumerate() {
+            let line = index + 1;
+            let margin = match lines.get(&line) {
+                Some(0) => "#####".to_string(),
+                Some(hits) => hits.to_string(),
+                None => "-".to_string(),
+            };
+            let _ = writeln!(listing, "{:>9}: {:>4}: {}", margin, line, text);
+
+            if lines.get(&line).is_none_or(|hits| *hits == 0) {
+                continue;
+            }
+            let mut marks = String::new();
+            for span in never_ran.iter().filter(|span| span.begin.line == line) {
+                let begin = span.begin.column - 1;
+                let end = if span.end.line == line {
+                    span.end.column - 1
+                } else {
+                    text.chars().count()
+                };
+                let padding = begin.saturating_sub(marks.chars().count());
+                marks.push_str(&" ".repeat(padding));
+                marks.push_str(&"^".repeat(end.saturating_sub(begin).max(1)));
+            }
+            if !marks.is_empty() {
+                let _ = writeln!(listing, "{:>9}: {:>4}: {}", "", "", marks);
+            }
+        }
+        listing
+    }
+}
+
+fn contains(outer: &Span, inner: &Span) -> bool {
+    outer != inner
+        && outer.begin.offset <= inner.begin.offset
+        && inner.end.offset <= outer.end.offset
+}
+
+/// Finds the expressions that can be evaluated, by walking the code the way
+/// the evaluator would.
+struct Collector<'a> {
+    data: &'a CoverageData,
+    coverage: FileCoverage,
+    blocks: usize,
+}
+
+impl Collector<'_> {
+    /// Collects `expr`, which is in a position where it's evaluated.
+    fn collect(&mut self, expr: &Expr) {
+        let span = match expr {
+            Expr::List(List::Nil, _) => return,
+            _ => match expr.span() {
+                Some(span) => span,
+                None => return,
+            },
+        };
+        self.coverage.exprs.push((span, self.data.hits(&span)));
+
+        let Expr::List(List::Cons(cons), _) = expr else {
+            return;
+        };
+        let args = cons.cdr.iter().collect::<Vec<_>>();
+        let head = match cons.car.as_ref() {
+            Expr::Sym(head, _) => head.as_str(),
+            head => {
+                self.collect(head);
+                ""
+            }
+        };
+
+        match (head, &args[..]) {
+            ("quote", _) => {}
+            ("quasiquote", _) => args.iter().for_each(|arg| self.collect_unquoted(arg)),
+            ("define", [Expr::List(List::Cons(signature), _), body @ ..]) => {
+                if let (Expr::Sym(name, _), Some(first)) = (signature.car.as_ref(), body.first()) {
+                    let hits = first.span().map_or(0, |span| self.data.hits(&span));
+                    self.coverage.functions.push(FunctionCoverage {
+                        name: name.clone(),
+                        span,
+                        hits,
+                    });
+                }
+                self.collect_all(body);
+            }
+            ("define" | "set!" | "lambda" | "deftest", [_, rest @ ..]) => self.collect_all(rest),
+            ("defmacro", [Expr::Sym(..), _, body @ ..] | [_, body @ ..]) => self.collect_all(body),
+            ("let", [bindings, body @ ..]) => {
+                if let Expr::List(bindings, _) = bindings {
+                    for binding in bindings.iter() {
+                        if let Expr::List(List::Cons(binding), _) = binding {
+                            binding.cdr.iter().for_each(|value| self.collect(value));
+                        }
+                    }
+                }
+                self.collect_all(body);
+            }
+            ("if", [condition, branches @ ..]) => {
+                self.collect(condition);
+                self.collect_all(branches);
+
+                let if_hits = self.data.hits(&span);
+                let then_hits = branches.first().map_or(0, |expr| self.hits(expr));
+                let else_hits = match branches.get(1) {
+                    Some(expr) => self.hits(expr),
+                    None => if_hits.saturating_sub(then_hits),
+                };
+                self.add_branches(span, if_hits, [then_hits, else_hits]);
+            }
+            ("cond", clauses) => {
+                let mut hits = Vec::new();
+                for clause in clauses {
+                    let Expr::List(List::Cons(clause), _) = clause else {
+                        continue;
+                    };
+                    if !matches!(clause.car.as_ref(), Expr::Sym(name, _) if name == "else") {
+                        self.collect(&clause.car);
+                    }
+                    let body = clause.cdr.iter().collect::<Vec<_>>();
+                    if let Some(first) = body.first() {
+                        hits.push(self.hits(first));
+                    }
+                    body.into_iter().for_each(|expr| self.collect(expr));
+                }
+                let cond_hits = self.data.hits(&span);
+                self.add_branches(span, cond_hits, hits);
+            }
+            _ => self.collect_all(&args),
+        }
+    }
+
+    fn collect_all(&mut self, exprs: &[&Expr]) {
+        exprs.iter().for_each(|expr| self.collect(expr));
+    }
+
+    /// Collects the unquoted parts of a quasiquoted `expr`.
+    fn collect_unquoted(&mut self, expr: &Expr) {
+        let Expr::List(List::Cons(cons), _) = expr else {
+            return;
+        };
+        match cons.car.as_ref() {
+            Expr::Sym(head, _) if head == "unquote" || head == "unquote-splicing" => {
+                cons.cdr.iter().for_each(|arg| self.collect(arg));
+            }
+            car => {
+                self.collect_unquoted(car);
+                cons.cdr.iter().for_each(|item| self.collect_unquoted(item));
+            }
+        }
+    }
+
+    fn hits(&self, expr: &Expr) -> usize {
+        expr.span().map_or(0, |span| self.data.hits(&span))
+    }
+
+    fn add_branches(
+        &mut self,
+        span: Span,
+        form_hits: usize,
+        hits: impl IntoIterator<Item = usize>,
+    ) {
+        let block = self.blocks;
+        self.blocks += 1;
+        for (branch, hits) in hits.into_iter().enumerate() {
+            self.coverage.branches.push(BranchCoverage {
+                span,
+                block,
+                branch,
+                hits: (form_hits > 0).then_some(hits),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, lexer::tokenize_source};
+
+    fn cover(path: &str, text: &str) -> FileCoverage {
+        let evaluator = Evaluator::with_prelude();
+        let source = SourceMap::add(path, text);
+        let exprs = Parser::with_tokens(tokenize_source(source).unwrap())
+            .parse_all()
+            .0;
+
+        evaluator.coverage().start();
+        for expr in exprs {
+            let _ = evaluator.eval(&expr);
+        }
+        let data = evaluator.coverage().stop();
+        assert!(!evaluator.coverage().is_running());
+        // macros of the prelude are covered too
+        assert!(data.sources().contains(&source));
+
+        FileCoverage::new(source, &data).unwrap()
+    }
+
+    const SCRIPT: &str = "\
+; sign
+(define (sign x)
+  (cond ((< x 0) 'negative)
+        ((= x 0) 'zero)
+        (#t 'positive)))
+(define (unused) (car '(1 2)))
+(sign 5)
+(sign -1)
+(if #f (sign 0) `(1 ,(+ 1 1)))
+";
+
+    #[test]
+    fn test_exprs() {
+        let coverage = cover("sign.rsc", SCRIPT);
+
+        let text = SCRIPT;
+        let hits = coverage
+            .exprs
+            .iter()
+            .map(|(span, hits)| (&text[span.begin.offset..span.end.offset], *hits))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hits,
+            vec![
+                (
+                    "(define (sign x)\n  (cond ((< x 0) 'negative)\n        ((= x 0) 'zero)\n        (#t 'positive)))",
+                    1
+                ),
+                (
+                    "(cond ((< x 0) 'negative)\n        ((= x 0) 'zero)\n        (#t 'positive))",
+                    2
+                ),
+                ("(< x 0)", 2),
+                ("x", 2),
+                ("0", 2),
+                ("'negative", 1),
+                ("(= x 0)", 1),
+                ("x", 1),
+                ("0", 1),
+                ("'zero", 0),
+                ("#t", 1),
+                ("'positive", 1),
+                ("(define (unused) (car '(1 2)))", 1),
+                ("(car '(1 2))", 0),
+                ("'(1 2)", 0),
+                ("(sign 5)", 1),
+                ("5", 1),
+                ("(sign -1)", 1),
+                ("-1", 1),
+                ("(if #f (sign 0) `(1 ,(+ 1 1)))", 1),
+                ("#f", 1),
+                ("(sign 0)", 0),
+                ("0", 0),
+                ("`(1 ,(+ 1 1))", 1),
+                ("(+ 1 1)", 1),
+                ("1", 1),
+                ("1", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lcov() {
+        let coverage = cover("sign.rsc", SCRIPT);
+        assert_eq!(
+            coverage.to_lcov(),
+            "\
+TN:
+SF:sign.rsc
+FN:2,sign
+FN:6,unused
+FNDA:2,sign
+FNDA:0,unused
+FNF:2
+FNH:1
+BRDA:3,0,0,1
+BRDA:3,0,1,0
+BRDA:3,0,2,1
+BRDA:9,1,0,0
+BRDA:9,1,1,1
+BRF:5
+BRH:3
+DA:2,1
+DA:3,2
+DA:4,1
+DA:5,1
+DA:6,1
+DA:7,1
+DA:8,1
+DA:9,1
+LF:8
+LH:8
+end_of_record
+"
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        let coverage = cover("sign.rsc", SCRIPT);
+        assert_eq!(
+            coverage.annotate(),
+            "        -:    0: Source:sign.rsc
+        -:    1: ; sign
+        1:    2: (define (sign x)
+        2:    3:   (cond ((< x 0) 'negative)
+        1:    4:         ((= x 0) 'zero)
+         :     :                  ^^^^^
+        1:    5:         (#t 'positive)))
+        1:    6: (define (unused) (car '(1 2)))
+         :     :                  ^^^^^^^^^^^^
+        1:    7: (sign 5)
+        1:    8: (sign -1)
+        1:    9: (if #f (sign 0) `(1 ,(+ 1 1)))
+         :     :        ^^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn test_not_running() {
+        let evaluator = Evaluator::with_prelude();
+        let source = SourceMap::add("idle.rsc", "(+ 1 2)");
+        let exprs = Parser::with_tokens(tokenize_source(source).unwrap())
+            .parse_all()
+            .0;
+        evaluator.eval(&exprs[0]).unwrap();
+
+        let data = evaluator.coverage().stop();
+        assert!(data.sources().is_empty());
+
+        let coverage = FileCoverage::new(source, &data).unwrap();
+        assert_eq!(coverage.lines(), vec![(1, 0)]);
+        assert_eq!(
+            coverage.annotate(),
+            "        -:    0: Source:idle.rsc\n    #####:    1: (+ 1 2)\n"
+        );
+    }
+}
@@ -0,0 +1,697 @@
+//! Lossless concrete syntax tree.
+//!
+//! Unlike `Parser`, which produces `Expr` values, the CST keeps everything in the
+//! source text, including whitespace and comments, so that it can be written back
+//! exactly as it was read. This is what tools like formatters need so that they
+//! don't destroy user comments.
+//!
+//! # Example
+//!
+//! ```
+//! use rusche::cst::Cst;
+//!
+//! let text = "(define x 1) ; the answer\n";
+//! let cst = Cst::parse(text).unwrap();
+//! assert_eq!(cst.to_string(), text);
+//! assert_eq!(cst.to_exprs().len(), 1);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::builtin::quote::{QUASIQUOTE, QUOTE, UNQUOTE, UNQUOTE_SPLICING};
+use crate::expr::{intern, Expr};
+use crate::lexer::{LexError, Lexer};
+use crate::list::{cons, List};
+use crate::macros::list;
+use crate::parser::ParseError;
+use crate::source::{SourceId, SourceMap};
+use crate::span::{Loc, Span};
+use crate::token::Token;
+
+#[derive(Debug, PartialEq)]
+pub enum CstError {
+    Lex(LexError),
+    Parse(ParseError),
+}
+
+impl fmt::Display for CstError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CstError::Lex(error) => write!(f, "{}", error),
+            CstError::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    /// `; ...` up to, but not including, the end of the line.
+    LineComment,
+    /// `#| ... |#`, which may be nested.
+    BlockComment,
+    /// `#!...` on the first line.
+    Shebang,
+}
+
+/// Text between tokens that has no meaning to the parser.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/// A token along with its original text and the trivia preceding it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CstToken {
+    pub token: Token,
+    /// Text of the token as written in the source, e.g. `#xff` for 255.
+    pub text: String,
+    pub leading_trivia: Vec<Trivia>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    /// A number, string, symbol or datum label reference.
+    Atom(CstToken),
+    List {
+        open: CstToken,
+        items: Vec<Node>,
+        /// The `)`, whose leading trivia is the one after the last item.
+        close: CstToken,
+    },
+    /// A quote, datum comment or datum label applied to the node following it.
+    Prefixed { prefix: CstToken, node: Box<Node> },
+}
+
+impl Node {
+    /// The first token of the node, which holds the node's leading trivia.
+    pub fn first_token(&self) -> &CstToken {
+        match self {
+            Node::Atom(token) => token,
+            Node::List { open, .. } => open,
+            Node::Prefixed { prefix, .. } => prefix,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Node::Atom(token) => token.token.span(),
+            Node::List { open, close, .. } => open.token.span().to(close.token.span()),
+            Node::Prefixed { prefix, node } => prefix.token.span().to(node.span()),
+        }
+    }
+
+    /// Whether the node is commented out with `#;`.
+    pub fn is_datum_comment(&self) -> bool {
+        matches!(
+            self,
+            Node::Prefixed {
+                prefix: CstToken {
+                    token: Token::DatumComment(_),
+                    ..
+                },
+                ..
+            }
+        )
+    }
+
+    /// Converts the node to the same expression `Parser` would produce. Returns
+    /// `None` if the node is commented out. A `#n#` whose label is defined
+    /// outside the node is left out as well.
+    pub fn to_expr(&self) -> Option<Expr> {
+        self.to_expr_with(&mut HashMap::new())
+    }
+
+    fn to_expr_with(&self, labels: &mut HashMap<usize, Expr>) -> Option<Expr> {
+        // converted with an explicit stack, since deeply nested lists would
+        // overflow the call stack
+        let mut pending = Vec::new();
+        let mut node = self;
+        loop {
+            let mut expr = loop {
+                match node {
+                    Node::Atom(token) => {
+                        break match &token.token {
+                            Token::Num(value, span) => Some(Expr::Num(*value, Some(*span))),
+                            Token::Str(text, span) => Some(Expr::Str(text.clone(), Some(*span))),
+                            Token::Sym(name, span) => Some(Expr::Sym(name.clone(), Some(*span))),
+                            Token::DatumRef(label, _) => labels.get(label).cloned(),
+                            token => panic!("Unexpected atom token: {}", token),
+                        }
+                    }
+                    Node::List { items, .. } => {
+                        let mut items = items.iter();
+                        let Some(item) = items.next() else {
+                            break Some(Expr::List(List::Nil, Some(node.span())));
+                        };
+                        pending.push(PendingExpr::List {
+                            span: node.span(),
+                            items,
+                            exprs: Vec::new(),
+                        });
+                        node = item;
+                    }
+                    Node::Prefixed {
+                        prefix,
+                        node: inner,
+                    } => {
+                        pending.push(PendingExpr::Prefixed {
+                            prefix: &prefix.token,
+                            span: node.span(),
+                        });
+                        node = inner;
+                    }
+                }
+            };
+
+            loop {
+                match pending.pop() {
+                    None => return expr,
+                    Some(PendingExpr::List {
+                        span,
+                        mut items,
+                        mut exprs,
+                    }) => {
+                        exprs.extend(expr);
+                        if let Some(item) = items.next() {
+                            pending.push(PendingExpr::List { span, items, exprs });
+                            node = item;
+                            break;
+                        }
+                        let mut list = List::Nil;
+                        for expr in exprs.into_iter().rev() {
+                            list = cons(expr, list);
+                        }
+                        expr = Some(Expr::List(list, Some(span)));
+                    }
+                    Some(PendingExpr::Prefixed { prefix, span }) => {
+                        let quote_name = match prefix {
+                            Token::Quote(_) => QUOTE,
+                            Token::Quasiquote(_) => QUASIQUOTE,
+                            Token::Unquote(_) => UNQUOTE,
+                            Token::UnquoteSplicing(_) => UNQUOTE_SPLICING,
+                            Token::DatumLabel(label, _) => {
+                                if let Some(expr) = &expr {
+                                    labels.insert(*label, expr.clone());
+                                }
+                                continue;
+                            }
+                            _ => {
+                                // datum comment; labels defined in it are still defined
+                                expr = None;
+                                continue;
+                            }
+                        };
+                        expr = expr
+                            .map(|expr| Expr::List(list!(intern(quote_name), expr), Some(span)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A list or prefixed node whose expression is waiting for those of its items.
+enum PendingExpr<'a> {
+    List {
+        span: Span,
+        items: std::slice::Iter<'a, Node>,
+        /// Expressions of the items converted so far.
+        exprs: Vec<Expr>,
+    },
+    Prefixed {
+        prefix: &'a Token,
+        span: Span,
+    },
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        // dropped with an explicit stack, since deeply nested nodes would
+        // overflow the call stack
+        let mut pending = Vec::new();
+        self.detach_children(&mut pending);
+        while let Some(mut node) = pending.pop() {
+            node.detach_children(&mut pending);
+        }
+    }
+}
+
+impl Node {
+    /// Moves the child nodes to `pending`, leaving the node without children.
+    fn detach_children(&mut self, pending: &mut Vec<Node>) {
+        match self {
+            Node::Atom(_) => {}
+            Node::List { items, .. } => pending.append(items),
+            Node::Prefixed { node, .. } => {
+                if !matches!(node.as_ref(), Node::Atom(_)) {
+                    let span = node.span();
+                    let placeholder = Node::Atom(CstToken {
+                        token: Token::Sym(String::new(), span),
+                        text: String::new(),
+                        leading_trivia: Vec::new(),
+                    });
+                    pending.push(std::mem::replace(node.as_mut(), placeholder));
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for CstToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trivia in &self.leading_trivia {
+            write!(f, "{}", trivia.text)?;
+        }
+        write!(f, "{}", self.text)
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Atom(token) => write!(f, "{}", token),
+            Node::List { open, items, close } => {
+                write!(f, "{}", open)?;
+                for item in items {
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "{}", close)
+            }
+            Node::Prefixed { prefix, node } => write!(f, "{}{}", prefix, node),
+        }
+    }
+}
+
+/// A whole source text as a list of top-level nodes. Writing it with `Display`
+/// reproduces the text it was parsed from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cst {
+    pub nodes: Vec<Node>,
+    /// Trivia after the last node.
+    pub trailing_trivia: Vec<Trivia>,
+}
+
+impl Cst {
+    pub fn parse(text: &str) -> Result<Self, CstError> {
+        CstBuilder::new(text, None)?.build()
+    }
+
+    /// Parses a source registered in the `SourceMap`.
+    pub fn parse_source(source: SourceId) -> Result<Self, CstError> {
+        let text = SourceMap::text(source).unwrap_or_default();
+        CstBuilder::new(&text, Some(source))?.build()
+    }
+
+    /// Converts the top-level nodes to the expressions `Parser` would produce.
+    pub fn to_exprs(&self) -> Vec<Expr> {
+        self.nodes.iter().filter_map(Node::to_expr).collect()
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in &self.nodes {
+            write!(f, "{}", node)?;
+        }
+        for trivia in &self.trailing_trivia {
+            write!(f, "{}", trivia.text)?;
+        }
+        Ok(())
+    }
+}
+
+struct CstBuilder {
+    tokens: std::vec::IntoIter<CstToken>,
+    trailing_trivia: Vec<Trivia>,
+    /// Datum labels defined so far in the current top-level node.
+    labels: HashSet<usize>,
+    /// Datum labels whose node is being built.
+    open_labels: Vec<usize>,
+}
+
+impl CstBuilder {
+    fn new(text: &str, source: Option<SourceId>) -> Result<Self, CstError> {
+        let mut lexer = Lexer::new(text.chars());
+        if let Some(source) = source {
+            lexer = lexer.with_source(source);
+        }
+
+        let mut tokens = Vec::new();
+        let mut loc = Loc::new(1, 1, 0);
+        while let Some(token) = lexer.get_token().map_err(CstError::Lex)? {
+            let span = token.span();
+            tokens.push(CstToken {
+                text: text[span.begin.offset..span.end.offset].to_string(),
+                leading_trivia: split_trivia(&text[loc.offset..span.begin.offset], loc, source),
+                token,
+            });
+            loc = span.end;
+        }
+
+        Ok(Self {
+            tokens: tokens.into_iter(),
+            trailing_trivia: split_trivia(&text[loc.offset..], loc, source),
+            labels: HashSet::new(),
+            open_labels: Vec::new(),
+        })
+    }
+
+    fn build(mut self) -> Result<Cst, CstError> {
+        let mut nodes = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            nodes.push(self.build_node(token)?);
+            self.labels.clear();
+        }
+        Ok(Cst {
+            nodes,
+            trailing_trivia: self.trailing_trivia,
+        })
+    }
+
+    fn build_node(&mut self, mut token: CstToken) -> Result<Node, CstError> {
+        // built with an explicit stack, since deeply nested lists would overflow
+        // the call stack
+        let mut pending = Vec::new();
+        loop {
+            let mut node = loop {
+                match token.token {
+                    Token::OpenParen(_) => pending.push(PendingNode::List {
+                        open: token,
+                        items: Vec::new(),
+                    }),
+                    Token::CloseParen(span) => match pending.pop() {
+                        Some(PendingNode::List { open, items }) => {
+                            break Node::List {
+                                open,
+                                items,
+                                close: token,
+                            }
+                        }
+                        Some(PendingNode::Prefixed { prefix }) => {
+                            return Err(CstError::Parse(ParseError::LoneQuote(prefix.token)))
+                        }
+                        None => return Err(CstError::Parse(ParseError::DanglingCloseParen(span))),
+                    },
+                    Token::Quote(_)
+                    | Token::Quasiquote(_)
+                    | Token::Unquote(_)
+                    | Token::UnquoteSplicing(_)
+                    | Token::DatumComment(_) => {
+                        pending.push(PendingNode::Prefixed { prefix: token })
+                    }
+                    Token::DatumLabel(label, _) => {
+                        self.open_labels.push(label);
+                        pending.push(PendingNode::Prefixed { prefix: token });
+                    }
+                    Token::DatumRef(label, span) => {
+                        if self.labels.contains(&label) {
+                            break Node::Atom(token);
+                        } else if self.open_labels.contains(&label) {
+                            return Err(CstError::Parse(ParseError::CyclicLabel(label, span)));
+                        } else {
+                            return Err(CstError::Parse(ParseError::UndefinedLabel(label, span)));
+                        }
+                    }
+                    Token::Num(..) | Token::Str(..) | Token::Sym(..) => break Node::Atom(token),
+                }
+                token = self.next_token(&pending)?;
+            };
+
+            loop {
+                match pending.pop() {
+                    None => return Ok(node),
+                    Some(PendingNode::List { open, mut items }) => {
+                        items.push(node);
+                        pending.push(PendingNode::List { open, items });
+                        break;
+                    }
+                    Some(PendingNode::Prefixed { prefix }) => {
+                        if let Token::DatumLabel(label, _) = prefix.token {
+                            self.open_labels.pop();
+                            self.labels.insert(label);
+                        }
+                        node = Node::Prefixed {
+                            prefix,
+                            node: Box::new(node),
+                        };
+                    }
+                }
+            }
+            token = self.next_token(&pending)?;
+        }
+    }
+
+    /// Returns the token after an unfinished node, the innermost of which is the
+    /// last of `pending`.
+    fn next_token(&mut self, pending: &[PendingNode]) -> Result<CstToken, CstError> {
+        self.tokens.next().ok_or_else(|| match pending.last() {
+            Some(PendingNode::List { open, .. }) => {
+                CstError::Parse(ParseError::UnclosedList(open.token.span()))
+            }
+            Some(PendingNode::Prefixed { prefix }) => {
+                CstError::Parse(ParseError::LoneQuote(prefix.token.clone()))
+            }
+            None => unreachable!("a finished node needs no more tokens"),
+        })
+    }
+}
+
+/// A list or prefixed node whose items are being built.
+enum PendingNode {
+    List { open: CstToken, items: Vec<Node> },
+    Prefixed { prefix: CstToken },
+}
+
+/// Splits the text between two tokens, which starts at `loc`, into trivia.
+fn split_trivia(text: &str, mut loc: Loc, source: Option<SourceId>) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let line_len = rest.find('\n').unwrap_or(rest.len());
+        let (kind, len) = if rest.starts_with(';') {
+            (TriviaKind::LineComment, line_len)
+        } else if rest.starts_with("#|") {
+            (TriviaKind::BlockComment, block_comment_len(rest))
+        } else if rest.starts_with("#!") {
+            (TriviaKind::Shebang, line_len)
+        } else {
+            let len = rest.find(|ch: char| !ch.is_whitespace());
+            (
+                TriviaKind::Whitespace,
+                len.filter(|len| *len > 0).unwrap_or(rest.len()),
+            )
+        };
+
+        let (text, remaining) = rest.split_at(len);
+        let begin_loc = loc;
+        for ch in text.chars() {
+            loc.offset += ch.len_utf8();
+            if ch == '\n' {
+                loc.line += 1;
+                loc.column = 1;
+            } else {
+                loc.column += 1;
+            }
+        }
+
+        trivia.push(Trivia {
+            kind,
+            text: text.to_string(),
+            span: Span::new(begin_loc, loc).with_source(source),
+        });
+        rest = remaining;
+    }
+
+    trivia
+}
+
+/// Length in bytes of the (possibly nested) block comment at the start of `text`.
+fn block_comment_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut index = 0;
+
+    while index + 1 < bytes.len() {
+        match (bytes[index], bytes[index + 1]) {
+            (b'#', b'|') => {
+                depth += 1;
+                index += 2;
+            }
+            (b'|', b'#') => {
+                depth -= 1;
+                index += 2;
+                if depth == 0 {
+                    return index;
+                }
+            }
+            _ => index += 1,
+        }
+    }
+
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::parser::Parser;
+
+    const TEXT: &str = r#"#!/usr/bin/env rusche-cli
+; factorial
+(define (fact n) #| base case |#
+  (if (= n 0)
+      1 ; done
+      (* n (fact (- n 1))) #;(unused) ))
+
+'(#xff "a\nb" ,@x) ; end
+"#;
+
+    #[test]
+    fn test_round_trip() {
+        let cst = Cst::parse(TEXT).unwrap();
+        assert_eq!(cst.to_string(), TEXT);
+        assert_eq!(cst.nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_round_trip_examples() {
+        let examples = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in std::fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "rsc") {
+                let text = std::fs::read_to_string(&path).unwrap();
+                assert_eq!(Cst::parse(&text).unwrap().to_string(), text, "{:?}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_exprs() {
+        let cst = Cst::parse(TEXT).unwrap();
+        let (exprs, errors) = Parser::with_tokens(tokenize(TEXT).unwrap()).parse_all();
+        assert!(errors.is_empty());
+        assert_eq!(cst.to_exprs(), exprs);
+        assert_eq!(
+            cst.to_exprs().iter().map(Expr::span).collect::<Vec<_>>(),
+            exprs.iter().map(Expr::span).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_trivia() {
+        let cst = Cst::parse(TEXT).unwrap();
+
+        let leading = &cst.nodes[0].first_token().leading_trivia;
+        let kinds = leading.iter().map(|trivia| trivia.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                TriviaKind::Shebang,
+                TriviaKind::Whitespace,
+                TriviaKind::LineComment,
+                TriviaKind::Whitespace,
+            ]
+        );
+        assert_eq!(leading[2].text, "; factorial");
+        assert_eq!(leading[2].span.begin, Loc::new(2, 1, 26));
+
+        let Node::Prefixed { node, .. } = &cst.nodes[1] else {
+            panic!("expected a quoted list");
+        };
+        let Node::List { items, close, .. } = node.as_ref() else {
+            panic!("expected a quoted list");
+        };
+        assert_eq!(items[0].first_token().text, "#xff");
+        assert_eq!(close.leading_trivia, vec![]);
+        assert_eq!(cst.trailing_trivia.len(), 3); // " ", "; end", "\n"
+
+        let nested = Cst::parse("#| a #| b |# c |# x").unwrap();
+        let trivia = &nested.nodes[0].first_token().leading_trivia;
+        assert_eq!(trivia[0].kind, TriviaKind::BlockComment);
+        assert_eq!(trivia[0].text, "#| a #| b |# c |#");
+    }
+
+    #[test]
+    fn test_datum_comment_node() {
+        let cst = Cst::parse("(a #;b c) #;(d)").unwrap();
+        assert!(cst.nodes[1].is_datum_comment());
+        assert_eq!(cst.to_exprs(), vec![list!(a, intern("c")).into()]);
+    }
+
+    #[test]
+    fn test_datum_labels() {
+        let text = "(#0=(a b) #;#1=c #0# #1#) (#0=d #0#)";
+        let cst = Cst::parse(text).unwrap();
+        assert_eq!(cst.to_string(), text);
+
+        let (exprs, errors) = Parser::with_tokens(tokenize(text).unwrap()).parse_all();
+        assert!(errors.is_empty());
+        assert_eq!(cst.to_exprs(), exprs);
+
+        assert!(matches!(
+            Cst::parse("#0=(a #0#)"),
+            Err(CstError::Parse(ParseError::CyclicLabel(0, _)))
+        ));
+        assert!(matches!(
+            Cst::parse("(#0=a) #0#"),
+            Err(CstError::Parse(ParseError::UndefinedLabel(0, _)))
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |text| Cst::parse(text).unwrap_err();
+        let span = |column, offset| {
+            Span::new(
+                Loc::new(1, column, offset),
+                Loc::new(1, column + 1, offset + 1),
+            )
+        };
+
+        assert_eq!(
+            error("(a (b)"),
+            CstError::Parse(ParseError::UnclosedList(span(1, 0)))
+        );
+        assert_eq!(
+            error("a)"),
+            CstError::Parse(ParseError::DanglingCloseParen(span(2, 1)))
+        );
+        assert_eq!(
+            error("(')"),
+            CstError::Parse(ParseError::LoneQuote(Token::Quote(span(2, 1))))
+        );
+        assert_eq!(
+            error("(a '"),
+            CstError::Parse(ParseError::LoneQuote(Token::Quote(span(4, 3))))
+        );
+        assert!(matches!(
+            error("\"abc"),
+            CstError::Lex(LexError::IncompleteString(_))
+        ));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let depth = 100_000;
+        let text = format!("{}x{}", "('".repeat(depth), ")".repeat(depth));
+
+        let cst = Cst::parse(&text).unwrap();
+        let exprs = cst.to_exprs();
+
+        // each level is a list holding a quoted list
+        let mut expr = &exprs[0];
+        let mut levels = 0;
+        while let Expr::List(list, _) = expr {
+            expr = list.iter().last().unwrap();
+            levels += 1;
+        }
+        assert_eq!(levels, depth * 2);
+        assert_eq!(expr, &Expr::Sym("x".to_string(), None));
+    }
+}
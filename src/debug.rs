@@ -0,0 +1,589 @@
+//! Step debugging.
+//!
+//! Every `Evaluator` has a `Debugger`. Once a pause handler is set, evaluation
+//! pauses at breakpoints, on errors if asked to, and while stepping. The
+//! handler can inspect the environment and the call stack at the pause, and
+//! tells the debugger how to resume.
+//!
+//! Evaluation can pause before a list expression that has a span, i.e. one
+//! that was read from source code, or on entry to a procedure.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{cell::RefCell, rc::Rc};
+//!
+//! use rusche::{
+//!     debug::{Breakpoint, Resume},
+//!     eval::Evaluator,
+//!     lexer::tokenize,
+//!     parser::Parser,
+//! };
+//!
+//! let evaluator = Evaluator::with_prelude();
+//! let seen = Rc::new(RefCell::new(Vec::new()));
+//!
+//! let debugger = evaluator.debugger();
+//! let log = seen.clone();
+//! debugger.set_pause_handler(Some(Box::new(move |pause| {
+//!     log.borrow_mut().push(pause.env.lookup("n").unwrap().to_string());
+//!     Resume::Continue
+//! })));
+//! debugger.add_breakpoint(Breakpoint::Proc("square".to_string()));
+//!
+//! let mut parser = Parser::with_tokens(
+//!     tokenize("(define (square n) (* n n)) (square 3) (square 4)").unwrap(),
+//! );
+//! while let Some(expr) = parser.parse().unwrap() {
+//!     evaluator.eval(&expr).unwrap();
+//! }
+//! assert_eq!(*seen.borrow(), vec!["3", "4"]);
+//! ```
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::{
+    backtrace::Backtrace,
+    env::Env,
+    eval::{EvalContext, EvalError, EvalResult},
+    expr::Expr,
+    span::Span,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Breakpoint {
+    /// Pauses before the first expression evaluated on `line` of a file whose
+    /// path is `path` or ends with `/path`, each time evaluation reaches the
+    /// line from another one.
+    Line { path: String, line: usize },
+    /// Pauses on entry to the procedures named `name`. Closures pause after
+    /// their arguments are bound.
+    Proc(String),
+}
+
+impl Breakpoint {
+    fn matches_line(&self, span: &Span) -> bool {
+        let Breakpoint::Line { path, line } = self else {
+            return false;
+        };
+        *line == span.begin.line
+            && span.path().is_some_and(|span_path| {
+                span_path == *path || span_path.ends_with(&format!("/{}", path))
+            })
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Line { path, line } => write!(f, "{}:{}", path, line),
+            Breakpoint::Proc(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PauseReason<'a> {
+    /// The breakpoint with this id was hit.
+    Breakpoint(usize),
+    /// A step requested by `Debugger::step` or `Resume`.
+    Step,
+    /// The expression failed. The error keeps propagating after the pause.
+    Error(&'a EvalError),
+}
+
+/// Where and why the evaluation paused.
+pub struct Pause<'a> {
+    pub reason: PauseReason<'a>,
+    /// The expression about to be evaluated, or the one that failed. `None` on
+    /// entry to a procedure.
+    pub expr: Option<&'a Expr>,
+    /// Span of `expr`, or of the call on entry to a procedure.
+    pub span: Option<Span>,
+    /// The environment `expr` is evaluated in, or the procedure's own one.
+    pub env: &'a Rc<Env>,
+    /// The call stack, innermost frame first.
+    pub backtrace: Backtrace,
+}
+
+/// How to continue after a pause.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resume {
+    /// Runs until the next breakpoint.
+    Continue,
+    /// Pauses at the next expression, including those in called procedures.
+    StepInto,
+    /// Pauses at the next expression after the current one is evaluated. On
+    /// entry to a procedure, this is the same as `StepOut`.
+    StepOver,
+    /// Pauses at the next expression after the current procedure returns.
+    StepOut,
+}
+
+pub type PauseHandler = Box<dyn FnMut(&Pause) -> Resume>;
+
+enum Step {
+    Into,
+    /// Pauses at an expression outside of `span` with no more than `call_depth`
+    /// calls on the stack. Comparing spans rather than nesting keeps this
+    /// working when `span` was a tail call, which returns before it is invoked.
+    Over {
+        call_depth: usize,
+        span: Span,
+    },
+    /// Pauses at an expression with fewer than `call_depth` calls on the stack.
+    Out {
+        call_depth: usize,
+    },
+}
+
+#[derive(Default)]
+struct DebuggerState {
+    /// Taken out while the handler runs, so that evaluations made by the
+    /// handler are not debugged.
+    handler: Option<PauseHandler>,
+    /// Incremented whenever the handler is set, so that a handler replaced or
+    /// removed while it runs is not put back afterwards.
+    handler_generation: u64,
+    breakpoints: Vec<(usize, Breakpoint)>,
+    next_breakpoint_id: usize,
+    break_on_error: bool,
+    step: Option<Step>,
+    /// Span of the last expression entered.
+    last_span: Option<Span>,
+    /// Whether the error being propagated has paused already.
+    is_error_reported: bool,
+}
+
+/// Breakpoints and stepping state of an evaluator. Clones share the same state.
+#[derive(Clone, Default)]
+pub struct Debugger(Rc<RefCell<DebuggerState>>);
+
+impl fmt::Debug for Debugger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Debugger")
+            .field("breakpoints", &self.0.borrow().breakpoints)
+            .finish()
+    }
+}
+
+impl Debugger {
+    /// Sets the handler called at every pause. Nothing pauses without one.
+    pub fn set_pause_handler(&self, handler: Option<PauseHandler>) {
+        let mut state = self.0.borrow_mut();
+        state.handler = handler;
+        state.handler_generation += 1;
+    }
+
+    /// Adds a breakpoint and returns its id.
+    pub fn add_breakpoint(&self, breakpoint: Breakpoint) -> usize {
+        let mut state = self.0.borrow_mut();
+        state.next_breakpoint_id += 1;
+        let id = state.next_breakpoint_id;
+        state.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    /// Removes the breakpoint with `id`. Returns whether it existed.
+    pub fn remove_breakpoint(&self, id: usize) -> bool {
+        let mut state = self.0.borrow_mut();
+        let count = state.breakpoints.len();
+        state.breakpoints.retain(|(other, _)| *other != id);
+        state.breakpoints.len() < count
+    }
+
+    /// Returns the breakpoints along with their ids, in the order they were added.
+    pub fn breakpoints(&self) -> Vec<(usize, Breakpoint)> {
+        self.0.borrow().breakpoints.clone()
+    }
+
+    /// Pauses at the innermost expression that fails, if enabled.
+    pub fn set_break_on_error(&self, enabled: bool) {
+        self.0.borrow_mut().break_on_error = enabled;
+    }
+
+    pub fn breaks_on_error(&self) -> bool {
+        self.0.borrow().break_on_error
+    }
+
+    /// Pauses at the next expression evaluated.
+    pub fn step(&self) {
+        self.0.borrow_mut().step = Some(Step::Into);
+    }
+
+    /// Stops stepping, e.g. after an evaluation ended before the next step.
+    pub fn cancel_step(&self) {
+        self.0.borrow_mut().step = None;
+    }
+
+    pub(crate) fn is_attached(&self) -> bool {
+        self.0.borrow().handler.is_some()
+    }
+
+    /// Called before evaluating `expr`, which spans `span`.
+    pub(crate) fn enter_expr(&self, expr: &Expr, span: Span, context: &EvalContext) {
+        let reason = {
+            let mut state = self.0.borrow_mut();
+            state.is_error_reported = false;
+
+            // only the first expression evaluated on a line hits a line breakpoint
+            let is_new_line = state.last_span.is_none_or(|last| {
+                last.source != span.source || last.begin.line != span.begin.line
+            });
+            state.last_span = Some(span);
+            let breakpoint = state
+                .breakpoints
+                .iter()
+                .find(|(_, breakpoint)| is_new_line && breakpoint.matches_line(&span))
+                .map(|(id, _)| *id);
+
+            let is_step = match state.step {
+                Some(Step::Into) => true,
+                Some(Step::Over {
+                    call_depth,
+                    span: over,
+                }) => {
+                    let call_depth = context.call_depth().cmp(&call_depth);
+                    call_depth.is_lt() || (call_depth.is_eq() && !contains(&over, &span))
+                }
+                Some(Step::Out { call_depth }) => context.call_depth() < call_depth,
+                None => false,
+            };
+
+            match breakpoint {
+                Some(id) => Some(PauseReason::Breakpoint(id)),
+                None if is_step => Some(PauseReason::Step),
+                None => None,
+            }
+        };
+
+        if let Some(reason) = reason {
+            self.pause(reason, Some(expr), Some(span), context);
+        }
+    }
+
+    pub(crate) fn leave_expr(&self, expr: &Expr, result: &EvalResult, context: &EvalContext) {
+        let error = {
+            let mut state = self.0.borrow_mut();
+            match result {
+                Err(error) if state.break_on_error && !state.is_error_reported => {
+                    state.is_error_reported = true;
+                    Some(error)
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(error) = error {
+            self.pause(PauseReason::Error(error), Some(expr), expr.span(), context);
+        }
+    }
+
+    /// Called on entry to a procedure named `name`.
+    pub(crate) fn enter_proc(&self, name: &str, context: &EvalContext) {
+        if !self.is_attached() {
+            return;
+        }
+        let breakpoint = self
+            .0
+            .borrow()
+            .breakpoints
+            .iter()
+            .find(|(_, breakpoint)| matches!(breakpoint, Breakpoint::Proc(other) if other == name))
+            .map(|(id, _)| *id);
+
+        if let Some(id) = breakpoint {
+            let span = context
+                .capture_backtrace()
+                .frames
+                .first()
+                .and_then(|frame| frame.span);
+            self.pause(PauseReason::Breakpoint(id), None, span, context);
+        }
+    }
+
+    fn pause(
+        &self,
+        reason: PauseReason,
+        expr: Option<&Expr>,
+        span: Option<Span>,
+        context: &EvalContext,
+    ) {
+        let (mut handler, generation) = {
+            let mut state = self.0.borrow_mut();
+            let Some(handler) = state.handler.take() else {
+                return;
+            };
+            (handler, state.handler_generation)
+        };
+
+        let pause = Pause {
+            reason,
+            expr,
+            span,
+            env: &context.env,
+            backtrace: context.capture_backtrace(),
+        };
+        let resume = handler(&pause);
+
+        let mut state = self.0.borrow_mut();
+        if state.handler_generation == generation {
+            state.handler = Some(handler);
+        }
+        let call_depth = context.call_depth();
+        state.step = match (resume, expr.and(span)) {
+            (Resume::Continue, _) => None,
+            (Resume::StepInto, _) => Some(Step::Into),
+            (Resume::StepOver, Some(span)) => Some(Step::Over { call_depth, span }),
+            // paused on procedure entry, so stepping over the rest means stepping out
+            (Resume::StepOver | Resume::StepOut, _) if call_depth > 0 => {
+                Some(Step::Out { call_depth })
+            }
+            (Resume::StepOver | Resume::StepOut, _) => None, // nothing to step out of
+        };
+    }
+}
+
+fn contains(outer: &Span, inner: &Span) -> bool {
+    outer.source == inner.source
+        && outer.begin.offset <= inner.begin.offset
+        && inner.end.offset <= outer.end.offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, lexer::tokenize_source, parser::Parser, source::SourceMap};
+
+    const SCRIPT: &str = "\
+(define (square n)
+  (* n n))
+(define (sum-squares a b)
+  (+ (square a)
+     (square b)))
+(sum-squares 1 2)
+";
+
+    /// Evaluates `text`, resuming with `resumes` in turn at each pause, and
+    /// returns a description of every pause: the expression, or the procedure
+    /// on entry, followed by the value of `names` at the pause.
+    fn debug(
+        text: &str,
+        setup: impl FnOnce(&Debugger),
+        resumes: Vec<Resume>,
+    ) -> (Vec<String>, Vec<EvalResult>) {
+        let evaluator = Evaluator::with_prelude();
+        let debugger = evaluator.debugger();
+        setup(debugger);
+
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        let log = pauses.clone();
+        let mut resumes = resumes.into_iter();
+        debugger.set_pause_handler(Some(Box::new(move |pause| {
+            let what = match (&pause.reason, pause.expr) {
+                (PauseReason::Error(error), Some(expr)) => {
+                    format!("{} ! {}", expr, error.message())
+                }
+                (_, Some(expr)) => expr.to_string(),
+                (_, None) => format!("-> {}", pause.backtrace.frames[0].name),
+            };
+            log.borrow_mut().push(what);
+            resumes.next().unwrap_or(Resume::Continue)
+        })));
+
+        let source = SourceMap::add("dir/test.rsc", text);
+        let mut parser = Parser::with_tokens(tokenize_source(source).unwrap());
+        let mut results = Vec::new();
+        while let Some(expr) = parser.parse().unwrap() {
+            results.push(evaluator.eval(&expr));
+        }
+        let pauses = pauses.borrow().clone();
+        (pauses, results)
+    }
+
+    #[test]
+    fn test_proc_breakpoint() {
+        let evaluator = Evaluator::with_prelude();
+        let locals = Rc::new(RefCell::new(Vec::new()));
+        let log = locals.clone();
+        evaluator
+            .debugger()
+            .set_pause_handler(Some(Box::new(move |pause| {
+                let mut names = pause.env.names();
+                names.sort();
+                log.borrow_mut()
+                    .push((names, pause.env.lookup("a").unwrap().to_string()));
+                assert!(pause.env.base().is_some_and(|base| base.base().is_none()));
+                Resume::Continue
+            })));
+        let id = evaluator
+            .debugger()
+            .add_breakpoint(Breakpoint::Proc("sum-squares".to_string()));
+
+        let source = SourceMap::add("test.rsc", SCRIPT);
+        for expr in Parser::with_tokens(tokenize_source(source).unwrap())
+            .parse_all()
+            .0
+        {
+            evaluator.eval(&expr).unwrap();
+        }
+        assert_eq!(
+            *locals.borrow(),
+            vec![(vec!["a".to_string(), "b".to_string()], "1".to_string())]
+        );
+
+        assert!(evaluator.debugger().remove_breakpoint(id));
+        assert!(!evaluator.debugger().remove_breakpoint(id));
+        assert!(evaluator.debugger().breakpoints().is_empty());
+    }
+
+    #[test]
+    fn test_line_breakpoint() {
+        let (pauses, _) = debug(
+            SCRIPT,
+            |debugger| {
+                debugger.add_breakpoint(Breakpoint::Line {
+                    path: "test.rsc".to_string(),
+                    line: 4,
+                });
+            },
+            vec![],
+        );
+        // the first expression evaluated on the line only
+        assert_eq!(pauses, vec!["(+ (square a) (square b))"]);
+
+        let (pauses, _) = debug(
+            SCRIPT,
+            |debugger| {
+                debugger.add_breakpoint(Breakpoint::Line {
+                    path: "other.rsc".to_string(),
+                    line: 4,
+                });
+            },
+            vec![],
+        );
+        assert!(pauses.is_empty());
+    }
+
+    #[test]
+    fn test_handler_removes_itself() {
+        let evaluator = Evaluator::with_prelude();
+        let debugger = evaluator.debugger().clone();
+        let pauses = Rc::new(RefCell::new(0));
+        debugger.set_pause_handler(Some(Box::new({
+            let debugger = debugger.clone();
+            let pauses = pauses.clone();
+            move |_| {
+                *pauses.borrow_mut() += 1;
+                debugger.set_pause_handler(None);
+                Resume::StepInto
+            }
+        })));
+        debugger.step();
+
+        let source = SourceMap::add("test.rsc", "(+ (* 1 2) 3)");
+        for expr in Parser::with_tokens(tokenize_source(source).unwrap())
+            .parse_all()
+            .0
+        {
+            evaluator.eval(&expr).unwrap();
+        }
+        assert_eq!(*pauses.borrow(), 1);
+        assert!(!debugger.is_attached());
+    }
+
+    #[test]
+    fn test_step_into() {
+        let (pauses, _) = debug(
+            "(define (f x) (* x 2))\n(+ (f 1) 1)",
+            |debugger| debugger.step(),
+            vec![Resume::StepInto; 10],
+        );
+        assert_eq!(
+            pauses,
+            vec!["(define (f x) (* x 2))", "(+ (f 1) 1)", "(f 1)", "(* x 2)"]
+        );
+    }
+
+    #[test]
+    fn test_step_skips_quoted_data() {
+        let (pauses, _) = debug(
+            "(cons '(1 2) `(3 ,(car '(4))))",
+            |debugger| debugger.step(),
+            vec![Resume::StepInto; 10],
+        );
+        // only the unquoted expression is stepped into
+        assert_eq!(
+            pauses,
+            vec![
+                "(cons (quote (1 2)) (quasiquote (3 (unquote (car (quote (4)))))))",
+                "(car (quote (4)))"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let breakpoint = |debugger: &Debugger| {
+            debugger.add_breakpoint(Breakpoint::Line {
+                path: "test.rsc".to_string(),
+                line: 4,
+            });
+        };
+
+        // `(+ ...)` is a tail call, but stepping over it still skips its arguments
+        let (pauses, _) = debug(SCRIPT, breakpoint, vec![Resume::StepOver]);
+        assert_eq!(pauses, vec!["(+ (square a) (square b))"]);
+
+        let (pauses, _) = debug(
+            SCRIPT,
+            breakpoint,
+            vec![Resume::StepInto, Resume::StepOver, Resume::StepOver],
+        );
+        assert_eq!(
+            pauses,
+            vec!["(+ (square a) (square b))", "(square a)", "(square b)"]
+        );
+
+        let (pauses, _) = debug(
+            SCRIPT,
+            breakpoint,
+            vec![Resume::StepInto, Resume::StepInto, Resume::StepOut],
+        );
+        assert_eq!(
+            pauses,
+            vec![
+                "(+ (square a) (square b))",
+                "(square a)",
+                "(* n n)",
+                "(square b)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_step_over_proc_entry() {
+        let (pauses, _) = debug(
+            SCRIPT,
+            |debugger| {
+                debugger.add_breakpoint(Breakpoint::Proc("square".to_string()));
+            },
+            vec![Resume::StepOver, Resume::Continue],
+        );
+        assert_eq!(pauses, vec!["-> square", "(square b)", "-> square"]);
+    }
+
+    #[test]
+    fn test_break_on_error() {
+        let (pauses, results) = debug(
+            "(define (f x) (+ x (car x)))\n(f 1)",
+            |debugger| debugger.set_break_on_error(true),
+            vec![],
+        );
+        assert_eq!(pauses.len(), 1);
+        assert!(pauses[0].starts_with("(car x) ! car:"), "{}", pauses[0]);
+        assert!(results[1].is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use std::fmt::Write;
+
+use crate::cst::CstError;
+use crate::eval::EvalError;
+use crate::lexer::LexError;
+use crate::parser::ParseError;
+use crate::source::SourceMap;
+use crate::span::Span;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// A part of the source code that a `Diagnostic` points at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub is_primary: bool,
+}
+
+/// An error message with the locations in the source code it refers to.
+///
+/// # Example
+///
+/// ```
+/// use rusche::{
+///     diagnostic::{Diagnostic, Renderer},
+///     span::{Loc, Span},
+/// };
+///
+/// let source = "(define x (+ 1 y))";
+/// let diagnostic = Diagnostic::new("Undefined symbol: `y`")
+///     .with_primary(Span::new(Loc::new(1, 16, 15), Loc::new(1, 17, 16)), "");
+///
+/// let text = Renderer::new("test.rsc", source).render(&diagnostic);
+/// assert_eq!(
+///     text,
+///     concat!(
+///         "error: Undefined symbol: `y`\n",
+///         " --> test.rsc:1:16\n",
+///         "  |\n",
+///         "1 | (define x (+ 1 y))\n",
+///         "  |                ^\n",
+///     )
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub labels: Vec<Label>,
+    /// Additional text shown after the source snippet, e.g. a backtrace.
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new<T: Into<String>>(message: T) -> Self {
+        Self {
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_primary<T: Into<String>>(self, span: Span, message: T) -> Self {
+        self.with_label(span, message, true)
+    }
+
+    pub fn with_secondary<T: Into<String>>(self, span: Span, message: T) -> Self {
+        self.with_label(span, message, false)
+    }
+
+    pub fn with_note<T: Into<String>>(mut self, note: T) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    fn with_label<T: Into<String>>(mut self, span: Span, message: T, is_primary: bool) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            is_primary,
+        });
+        self
+    }
+
+    /// Renders the diagnostic with the source registered in the `SourceMap` for its
+    /// primary span. Falls back to the message alone if the source is unknown.
+    pub fn render(&self, use_color: bool) -> String {
+        let source = self
+            .span()
+            .and_then(|span| span.source)
+            .and_then(SourceMap::get);
+
+        match source {
+            Some(source) => Renderer::new(&source.path, &source.text)
+                .with_color(use_color)
+                .render(self),
+            None => Renderer::new("", "")
+                .with_color(use_color)
+                .render(&Diagnostic {
+                    labels: Vec::new(),
+                    ..self.clone()
+                }),
+        }
+    }
+
+    /// Returns the span of the first primary label, if any.
+    pub fn span(&self) -> Option<Span> {
+        self.labels
+            .iter()
+            .find(|label| label.is_primary)
+            .map(|label| label.span)
+    }
+}
+
+impl From<&EvalError> for Diagnostic {
+    fn from(error: &EvalError) -> Self {
+        let mut diagnostic = Diagnostic::new(error.message());
+        if let Some(span) = error.span {
+            diagnostic = diagnostic.with_primary(span, "");
+        }
+        if let Some(backtrace) = error.backtrace.as_ref().filter(|bt| !bt.is_empty()) {
+            diagnostic = diagnostic.with_note(format!("backtrace:\n{}", backtrace));
+        }
+        diagnostic
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(error: &LexError) -> Self {
+        match error {
+            LexError::IncompleteString(span) => {
+                Diagnostic::new(error.to_string()).with_primary(*span, "string starts here")
+            }
+            LexError::InvalidNumber(span) => {
+                Diagnostic::new(error.to_string()).with_primary(*span, "")
+            }
+            LexError::InvalidEscape(span) => Diagnostic::new(error.to_string())
+                .with_primary(*span, "unknown or malformed escape"),
+            LexError::DigitSeparator(span) => Diagnostic::new(error.to_string())
+                .with_primary(*span, "")
+                .with_note("remove the `_`s, e.g. write `1000` instead of `1_000`"),
+            LexError::IncompleteBlockComment(span) => {
+                Diagnostic::new(error.to_string()).with_primary(*span, "comment starts here")
+            }
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        match error {
+            ParseError::NeedMoreToken => Diagnostic::new(error.to_string()),
+            ParseError::UnclosedList(span) => {
+                Diagnostic::new(error.to_string()).with_primary(*span, "unclosed `(` opened here")
+            }
+            ParseError::LoneQuote(token) => Diagnostic::new(error.to_string())
+                .with_primary(token.span(), "this quote has nothing to quote"),
+            ParseError::DanglingCloseParen(span) => {
+                Diagnostic::new(error.to_string()).with_primary(*span, "unmatched `)`")
+            }
+            ParseError::UndefinedLabel(_, span) => {
+                Diagnostic::new(error.to_string()).with_primary(*span, "no such label")
+            }
+            ParseError::CyclicLabel(_, span) => Diagnostic::new(error.to_string())
+                .with_primary(*span, "cyclic reference")
+                .with_note("lists are immutable, so they can't contain themselves"),
+        }
+    }
+}
+
+impl From<&CstError> for Diagnostic {
+    fn from(error: &CstError) -> Self {
+        match error {
+            CstError::Lex(error) => Diagnostic::from(error),
+            CstError::Parse(error) => Diagnostic::from(error),
+        }
+    }
+}
+
+/// Renders diagnostics along with the lines of source code they point at.
+pub struct Renderer<'a> {
+    path: &'a str,
+    source: &'a str,
+    use_color: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(path: &'a str, source: &'a str) -> Self {
+        Self {
+            path,
+            source,
+            use_color: false,
+        }
+    }
+
+    /// Enables or disables ANSI colors in the rendered text.
+    pub fn with_color(mut self, use_color: bool) -> Self {
+        self.use_color = use_color;
+        self
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut text = String::new();
+
+        let _ = writeln!(
+            text,
+            "{}error{}: {}{}{}",
+            self.color(RED),
+            self.color(RESET),
+            self.color(BOLD),
+            diagnostic.message,
+            self.color(RESET)
+        );
+
+        self.render_snippet(diagnostic, &mut text);
+
+        for note in &diagnostic.notes {
+            text.push_str(note);
+            if !note.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+
+        text
+    }
+
+    fn render_snippet(&self, diagnostic: &Diagnostic, text: &mut String) {
+        let Some(span) = diagnostic
+            .span()
+            .or(diagnostic.labels.first().map(|l| l.span))
+        else {
+            return;
+        };
+
+        let mut labels = diagnostic.labels.iter().collect::<Vec<_>>();
+        labels.sort_by_key(|label| (label.span.begin.line, label.span.begin.column));
+
+        let gutter_width = labels
+            .iter()
+            .map(|label| label.span.begin.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(gutter_width);
+
+        let _ = writeln!(
+            text,
+            "{}{}-->{} {}:{}",
+            gutter,
+            self.color(BLUE),
+            self.color(RESET),
+            self.path,
+            span.begin
+        );
+        let _ = writeln!(
+            text,
+            "{} {}|{}",
+            gutter,
+            self.color(BLUE),
+            self.color(RESET)
+        );
+
+        let mut last_line = None;
+        for label in labels {
+            let line_number = label.span.begin.line;
+            let Some(line) = self.source.lines().nth(line_number.saturating_sub(1)) else {
+                continue;
+            };
+
+            if last_line != Some(line_number) {
+                if last_line.is_some_and(|last_line| line_number > last_line + 1) {
+                    let _ = writeln!(text, "{}...{}", self.color(BLUE), self.color(RESET));
+                }
+                let _ = writeln!(
+                    text,
+                    "{}{:>width$} |{} {}",
+                    self.color(BLUE),
+                    line_number,
+                    self.color(RESET),
+                    line,
+                    width = gutter_width
+                );
+                last_line = Some(line_number);
+            }
+
+            let _ = writeln!(
+                text,
+                "{} {}|{} {}",
+                gutter,
+                self.color(BLUE),
+                self.color(RESET),
+                self.underline(line, label)
+            );
+        }
+    }
+
+    fn underline(&self, line: &str, label: &Label) -> String {
+        let begin = label.span.begin.column.saturating_sub(1);
+        let end = if label.span.end.line == label.span.begin.line {
+            label.span.end.column.saturating_sub(1)
+        } else {
+            line.chars().count()
+        };
+
+        // keep tabs so that the underline lines up with the source line
+        let indent = line
+            .chars()
+            .take(begin)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        let (marker, color) = if label.is_primary {
+            ('^', RED)
+        } else {
+            ('-', BLUE)
+        };
+        let markers = marker.to_string().repeat(end.saturating_sub(begin).max(1));
+
+        let mut text = format!("{}{}{}", indent, self.color(color), markers);
+        if !label.message.is_empty() {
+            let _ = write!(text, " {}", label.message);
+        }
+        text.push_str(self.color(RESET));
+        text
+    }
+
+    fn color(&self, code: &'static str) -> &'static str {
+        if self.use_color {
+            code
+        } else {
+            ""
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::Loc;
+    use crate::token::Token;
+
+    fn span(line: usize, begin: usize, end: usize) -> Span {
+        Span::new(
+            Loc::new(line, begin, begin - 1),
+            Loc::new(line, end, end - 1),
+        )
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let diagnostic = Diagnostic::new("something went wrong");
+        let text = Renderer::new("test.rsc", "").render(&diagnostic);
+        assert_eq!(text, "error: something went wrong\n");
+    }
+
+    #[test]
+    fn test_render_secondary_labels() {
+        let source = "(define (f x)\n  (g x)\n\n  (h x))";
+        let diagnostic = Diagnostic::new("`h` does not evaluate to a callable.")
+            .with_primary(span(4, 4, 5), "called here")
+            .with_secondary(span(1, 10, 11), "defined here");
+
+        let text = Renderer::new("test.rsc", source).render(&diagnostic);
+        assert_eq!(
+            text,
+            concat!(
+                "error: `h` does not evaluate to a callable.\n",
+                " --> test.rsc:4:4\n",
+                "  |\n",
+                "1 | (define (f x)\n",
+                "  |          - defined here\n",
+                "...\n",
+                "4 |   (h x))\n",
+                "  |    ^ called here\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_with_source_map() {
+        let source = SourceMap::add("lib.rsc", "(define x 1)\n(car x)");
+        let diagnostic =
+            Diagnostic::new("oops").with_primary(span(2, 6, 7).with_source(Some(source)), "");
+        assert_eq!(
+            diagnostic.render(false),
+            concat!(
+                "error: oops\n",
+                " --> lib.rsc:2:6\n",
+                "  |\n",
+                "2 | (car x)\n",
+                "  |      ^\n",
+            )
+        );
+
+        let diagnostic = Diagnostic::new("oops").with_primary(span(2, 6, 7), "");
+        assert_eq!(diagnostic.render(false), "error: oops\n");
+    }
+
+    #[test]
+    fn test_render_with_tabs() {
+        let source = "\t(foo)";
+        let diagnostic = Diagnostic::new("oops").with_primary(span(1, 3, 6), "");
+        let text = Renderer::new("test.rsc", source).render(&diagnostic);
+        assert!(text.ends_with("1 | \t(foo)\n  | \t ^^^\n"));
+    }
+
+    #[test]
+    fn test_render_with_color() {
+        let diagnostic = Diagnostic::new("oops").with_primary(span(1, 1, 2), "");
+        let text = Renderer::new("test.rsc", "x")
+            .with_color(true)
+            .render(&diagnostic);
+        assert!(text.starts_with("\x1b[1;31merror\x1b[0m: \x1b[1moops\x1b[0m\n"));
+        assert!(text.contains("\x1b[1;31m^\x1b[0m"));
+    }
+
+    #[test]
+    fn test_from_errors() {
+        let error = EvalError::new("oops", Some(span(1, 1, 2)));
+        assert_eq!(Diagnostic::from(&error).span(), Some(span(1, 1, 2)));
+
+        let error = LexError::InvalidNumber(span(2, 1, 4));
+        let diagnostic = Diagnostic::from(&error);
+        assert_eq!(diagnostic.message, "invalid number");
+        assert_eq!(diagnostic.span(), Some(span(2, 1, 4)));
+
+        let error = ParseError::DanglingCloseParen(span(1, 5, 6));
+        let diagnostic = Diagnostic::from(&error);
+        assert_eq!(diagnostic.message, "unexpected `)` without matching `(`");
+        assert_eq!(diagnostic.span(), Some(span(1, 5, 6)));
+
+        let error = ParseError::LoneQuote(Token::Quote(span(3, 2, 3)));
+        let diagnostic = Diagnostic::from(&error);
+        assert_eq!(diagnostic.message, "expected an expression after `'`");
+        assert_eq!(diagnostic.span(), Some(span(3, 2, 3)));
+
+        let error = ParseError::CyclicLabel(0, span(2, 1, 2));
+        let diagnostic = Diagnostic::from(&error);
+        assert_eq!(
+            diagnostic.message,
+            "datum label `#0#` refers to a list that contains it"
+        );
+        assert_eq!(diagnostic.span(), Some(span(2, 1, 2)));
+
+        let diagnostic = Diagnostic::from(&ParseError::NeedMoreToken);
+        assert_eq!(diagnostic.message, "incomplete expression");
+        assert_eq!(diagnostic.span(), None);
+    }
+}
@@ -3,23 +3,28 @@ use std::collections::HashMap;
 use std::rc::{Rc, Weak};
 
 use crate::expr::Expr;
+use crate::gc::Heap;
+use crate::observer::ObserverSlot;
 use crate::proc::{NativeFunc, Proc};
 
 #[derive(Debug)]
 pub struct Env {
     base: Option<Rc<Env>>,
     vars: RefCell<HashMap<String, Expr>>,
-    all_envs: Weak<RefCell<Vec<Weak<Env>>>>,
+    heap: Weak<Heap>,
     is_reachable: Cell<bool>,
+    /// Only the root env has one, as only global variables are observed.
+    observer: Option<ObserverSlot>,
 }
 
 impl Env {
-    pub(crate) fn root(all_envs: Weak<RefCell<Vec<Weak<Env>>>>) -> Rc<Self> {
+    pub(crate) fn root(heap: Weak<Heap>, observer: ObserverSlot) -> Rc<Self> {
         Rc::new(Self {
             base: None,
             vars: RefCell::new(HashMap::new()),
-            all_envs,
+            heap,
             is_reachable: Cell::new(false),
+            observer: Some(observer),
         })
     }
 
@@ -27,12 +32,14 @@ impl Env {
         let derived_env = Rc::new(Self {
             base: Some(base.clone()),
             vars: RefCell::new(HashMap::new()),
-            all_envs: base.all_envs.clone(),
+            heap: base.heap.clone(),
             is_reachable: Cell::new(false),
+            observer: None,
         });
 
-        if let Some(all_envs) = base.all_envs.upgrade() {
-            all_envs.borrow_mut().push(Rc::downgrade(&derived_env));
+        if let Some(heap) = base.heap.upgrade() {
+            heap.track(&derived_env);
+            heap.collect_if_needed();
         }
 
         derived_env
@@ -42,7 +49,11 @@ impl Env {
     where
         IntoExpr: Into<Expr>,
     {
-        self.vars.borrow_mut().insert(name.into(), expr.into());
+        let expr = expr.into();
+        if let Some(observer) = &self.observer {
+            observer.notify_define(name, &expr);
+        }
+        self.vars.borrow_mut().insert(name.into(), expr);
     }
 
     pub fn update<IntoExpr>(&self, name: &str, expr: IntoExpr) -> bool
@@ -50,16 +61,21 @@ impl Env {
         IntoExpr: Into<Expr>,
     {
         let mut env = self;
-        loop {
-            if let Some(value) = env.vars.borrow_mut().get_mut(name) {
-                *value = expr.into();
-                return true;
-            }
+        while !env.vars.borrow().contains_key(name) {
             let Some(base) = &env.base else {
                 return false;
             };
             env = base;
         }
+
+        let expr = expr.into();
+        if let Some(observer) = &env.observer {
+            observer.notify_update(name, &expr);
+        }
+        if let Some(value) = env.vars.borrow_mut().get_mut(name) {
+            *value = expr;
+        }
+        true
     }
 
     pub fn lookup(&self, name: &str) -> Option<Expr> {
@@ -75,13 +91,35 @@ impl Env {
         }
     }
 
+    /// The environment this one was derived from, `None` for the root environment.
+    pub fn base(&self) -> Option<&Rc<Env>> {
+        self.base.as_ref()
+    }
+
+    /// Names defined in this environment, not including its base environments.
+    pub fn names(&self) -> Vec<String> {
+        self.vars.borrow().keys().cloned().collect()
+    }
+
+    /// Defines a native procedure, which evaluates each of its arguments once.
     pub fn define_native_proc(&self, name: &str, func: NativeFunc) {
+        self.define_native(name, func, /*is_special_form*/ false);
+    }
+
+    /// Defines a native procedure that gets its arguments as written, like `if`
+    /// or `define`.
+    pub fn define_special_form(&self, name: &str, func: NativeFunc) {
+        self.define_native(name, func, /*is_special_form*/ true);
+    }
+
+    fn define_native(&self, name: &str, func: NativeFunc, is_special_form: bool) {
         self.define(
             name,
             Expr::Proc(
                 Proc::Native {
-                    name: name.to_owned(),
+                    name: name.into(),
                     func,
+                    is_special_form,
                 },
                 None,
             ),
@@ -95,22 +133,28 @@ impl Env {
         self.is_reachable.set(false);
     }
 
-    pub(crate) fn gc_mark(&self) {
-        if self.is_reachable.get() {
-            return;
-        }
+    /// Marks this env as reachable. Returns `false` if it was already marked.
+    pub(crate) fn gc_mark(&self) -> bool {
+        !self.is_reachable.replace(true)
+    }
 
-        self.is_reachable.set(true);
+    /// Clears all variables and returns how many of them were dropped.
+    pub(crate) fn gc_sweep(&self) -> usize {
+        // Drop the values after releasing the borrow, as dropping them may drop other envs.
+        let vars = std::mem::take(&mut *self.vars.borrow_mut());
+        vars.len()
+    }
 
-        self.vars.borrow().values().for_each(|expr| {
-            if let Expr::Proc(Proc::Closure { outer_context, .. }, _) = expr {
-                outer_context.env.gc_mark();
-            }
-        });
+    pub(crate) fn gc_heap(&self) -> Option<Rc<Heap>> {
+        self.heap.upgrade()
     }
 
-    pub(crate) fn gc_sweep(&self) {
-        self.vars.borrow_mut().clear();
+    pub(crate) fn gc_base(&self) -> Option<&Rc<Env>> {
+        self.base.as_ref()
+    }
+
+    pub(crate) fn gc_for_each_value(&self, func: impl FnMut(&Expr)) {
+        self.vars.borrow().values().for_each(func);
     }
 
     pub(crate) fn is_reachable(&self) -> bool {
@@ -125,7 +169,7 @@ mod tests {
 
     #[test]
     fn test_set() {
-        let env = Env::root(Weak::new());
+        let env = Env::root(Weak::new(), ObserverSlot::default());
         assert_eq!(env.vars.borrow().len(), 0);
         env.define("one", 1);
         assert_eq!(env.vars.borrow().get("one"), Some(&num(1)));
@@ -133,16 +177,26 @@ mod tests {
 
     #[test]
     fn test_update() {
-        let env = Env::root(Weak::new());
+        let env = Env::root(Weak::new(), ObserverSlot::default());
         assert_eq!(env.update("name", 1), false);
 
         env.define("name", 0);
         assert_eq!(env.update("name", 1), true);
     }
 
+    #[test]
+    fn test_names() {
+        let base = Env::root(Weak::new(), ObserverSlot::default());
+        base.define("one", 1);
+        let env = Env::derive_from(&base);
+        env.define("two", 2);
+        assert_eq!(base.names(), vec!["one"]);
+        assert_eq!(env.names(), vec!["two"]);
+    }
+
     #[test]
     fn test_lookup() {
-        let env = Env::root(Weak::new());
+        let env = Env::root(Weak::new(), ObserverSlot::default());
         assert_eq!(env.lookup("one"), None);
         env.define("one", num(1));
         assert_eq!(env.lookup("one"), Some(num(1)));
@@ -150,7 +204,7 @@ mod tests {
 
     #[test]
     fn test_derive_update() {
-        let base = Env::root(Weak::new());
+        let base = Env::root(Weak::new(), ObserverSlot::default());
         let derived = Env::derive_from(&base);
 
         base.define("one", 1);
@@ -166,7 +220,7 @@ mod tests {
 
     #[test]
     fn test_derive_lookup() {
-        let base = Env::root(Weak::new());
+        let base = Env::root(Weak::new(), ObserverSlot::default());
         let derived = Env::derive_from(&base);
 
         assert_eq!(derived.lookup("two"), None);
@@ -180,7 +234,7 @@ mod tests {
 
     #[test]
     fn test_clone() {
-        let original = Env::root(Weak::new());
+        let original = Env::root(Weak::new(), ObserverSlot::default());
         let cloned = original.clone();
 
         original.define("one", 1);
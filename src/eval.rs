@@ -1,102 +1,331 @@
-use std::{
-    cell::{Cell, RefCell},
-    fmt,
-    rc::{Rc, Weak},
-};
+use std::{fmt, rc::Rc};
 
 use crate::{
+    backtrace::{Backtrace, CallStack},
     builtin::load_builtin,
+    coverage::Coverage,
+    debug::Debugger,
     env::Env,
     expr::Expr,
+    gc::{GcObserver, GcStats, Heap},
+    limits::{InterruptHandle, Limits},
     list::{Cons, List},
+    observer::{EvalObserver, ObserverSlot},
     prelude::load_prelude,
     proc::Proc,
+    profile::Profiler,
     span::Span,
+    testing::TestRegistry,
+    trace::Tracer,
 };
 
+/// Number of arguments a procedure accepts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    Between(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exactly(n) => count == n,
+            Arity::AtLeast(min) => count >= min,
+            Arity::Between(min, max) => (min..=max).contains(&count),
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exactly(1) => write!(f, "1 argument"),
+            Arity::Exactly(n) => write!(f, "{} arguments", n),
+            Arity::AtLeast(1) => write!(f, "at least 1 argument"),
+            Arity::AtLeast(n) => write!(f, "at least {} arguments", n),
+            Arity::Between(min, max) => write!(f, "{} to {} arguments", min, max),
+        }
+    }
+}
+
+/// What went wrong in an `EvalError`.
+///
+/// # Example
+///
+/// ```
+/// use rusche::eval::{Arity, EvalErrorKind};
+///
+/// let kind = EvalErrorKind::ArityMismatch {
+///     proc_name: "car".to_string(),
+///     expected: Arity::Exactly(1),
+///     actual: 2,
+/// };
+/// assert_eq!(kind.to_string(), "car: expects 1 argument, but got 2.");
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvalErrorKind {
+    /// A symbol that is not bound in the environment was evaluated.
+    UnboundVariable { name: String },
+    /// A procedure was called with a wrong number of arguments.
+    ArityMismatch {
+        proc_name: String,
+        expected: Arity,
+        actual: usize,
+    },
+    /// A value of an unexpected type was given to a procedure.
+    TypeMismatch {
+        proc_name: String,
+        expected: String,
+        actual: String,
+    },
+    /// The first element of a call evaluated to something other than a procedure.
+    NotCallable { callee: String },
+    /// An index was outside of the valid range of a sequence.
+    IndexOutOfRange {
+        proc_name: String,
+        index: i64,
+        len: usize,
+    },
+    /// Something a procedure needs is not available, e.g. the garbage collector
+    /// of an evaluator that was dropped.
+    Unavailable { proc_name: String, what: String },
+    /// An error raised by the program itself, e.g. with `error`.
+    UserRaised {
+        message: String,
+        irritants: Vec<Expr>,
+    },
+    /// An assertion of a test failed, e.g. with `assert-equal`.
+    AssertionFailed { message: String },
+    /// The evaluation was stopped by the host.
+    Interrupted,
+    /// The evaluation exceeded a limit set by the host.
+    ResourceLimit { resource: String, limit: usize },
+    /// Any other error, such as a malformed special form.
+    Other(String),
+}
+
+impl EvalErrorKind {
+    /// Creates a `TypeMismatch` for a procedure that expected `expected` but got
+    /// `actual`.
+    pub fn type_mismatch(proc_name: &str, expected: &str, actual: &Expr) -> Self {
+        EvalErrorKind::TypeMismatch {
+            proc_name: proc_name.to_string(),
+            expected: expected.to_string(),
+            actual: actual.type_name().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for EvalErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalErrorKind::UnboundVariable { name } => write!(f, "Undefined symbol: `{}`", name),
+            EvalErrorKind::ArityMismatch {
+                proc_name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: expects {}, but got {}.",
+                proc_name, expected, actual
+            ),
+            EvalErrorKind::TypeMismatch {
+                proc_name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: expected {} {}, but got {} {}.",
+                proc_name,
+                article(expected),
+                expected,
+                article(actual),
+                actual
+            ),
+            EvalErrorKind::NotCallable { callee } => {
+                write!(f, "`{}` does not evaluate to a callable.", callee)
+            }
+            EvalErrorKind::IndexOutOfRange {
+                proc_name,
+                index,
+                len,
+            } => write!(
+                f,
+                "{}: index {} is out of range for length {}.",
+                proc_name, index, len
+            ),
+            EvalErrorKind::Unavailable { proc_name, what } => {
+                write!(f, "{}: no {} is available.", proc_name, what)
+            }
+            EvalErrorKind::UserRaised { message, irritants } => {
+                write!(f, "{}", message)?;
+                for irritant in irritants {
+                    write!(f, " {}", irritant)?;
+                }
+                Ok(())
+            }
+            EvalErrorKind::AssertionFailed { message } => write!(f, "{}", message),
+            EvalErrorKind::Interrupted => write!(f, "Evaluation interrupted."),
+            EvalErrorKind::ResourceLimit { resource, limit } => {
+                write!(f, "{} limit of {} exceeded.", resource, limit)
+            }
+            EvalErrorKind::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+fn article(noun: &str) -> &'static str {
+    match noun.chars().next() {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => "an",
+        _ => "a",
+    }
+}
+
+impl From<String> for EvalErrorKind {
+    fn from(message: String) -> Self {
+        EvalErrorKind::Other(message)
+    }
+}
+
+impl From<&str> for EvalErrorKind {
+    fn from(message: &str) -> Self {
+        EvalErrorKind::Other(message.to_string())
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct EvalError {
-    pub message: String,
+    pub kind: Box<EvalErrorKind>,
     pub span: Option<Span>,
+    /// Call stack at the point where the error was raised, if it was raised in a
+    /// procedure call.
+    pub backtrace: Option<Box<Backtrace>>,
+    /// Whether the error was reported to the observer, so that it's reported
+    /// only once as it propagates.
+    pub(crate) is_reported: bool,
+}
+
+impl EvalError {
+    pub fn new<T: Into<EvalErrorKind>>(kind: T, span: Option<Span>) -> Self {
+        Self {
+            kind: Box::new(kind.into()),
+            span,
+            backtrace: None,
+            is_reported: false,
+        }
+    }
+
+    /// Returns the error message without the location.
+    pub fn message(&self) -> String {
+        self.kind.to_string()
+    }
 }
 
 impl fmt::Display for EvalError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Some(span) = &self.span {
-            write!(f, "{}: {}", span, self.message)
-        } else {
-            write!(f, "{}", self.message)
+        match (&self.span, self.span.and_then(|span| span.path())) {
+            (Some(span), Some(path)) => write!(f, "{}:{}: {}", path, span.begin, self.kind),
+            (Some(span), None) => write!(f, "{}: {}", span.begin, self.kind),
+            (None, _) => write!(f, "{}", self.kind),
         }
     }
 }
 
 impl From<String> for EvalError {
     fn from(message: String) -> Self {
-        Self {
-            message,
-            span: None,
-        }
+        Self::new(message, None)
     }
 }
 
-pub type EvalResult = Result<Expr, EvalError>;
+impl From<EvalErrorKind> for EvalError {
+    fn from(kind: EvalErrorKind) -> Self {
+        Self::new(kind, None)
+    }
+}
 
-#[cfg(debug_assertions)]
-const TRACE_CALL_STACK: bool = false;
+pub type EvalResult = Result<Expr, EvalError>;
 
 #[derive(Clone, Debug)]
 pub struct EvalContext {
     pub env: Rc<Env>,
-    call_depth: Rc<Cell<usize>>,
-
-    #[cfg(debug_assertions)]
-    call_stack: Rc<RefCell<Vec<String>>>,
+    call_stack: CallStack,
+    debugger: Debugger,
+    profiler: Profiler,
+    tracer: Tracer,
+    observer: ObserverSlot,
+    coverage: Coverage,
+    tests: TestRegistry,
+    limits: Limits,
 }
 
 impl EvalContext {
     pub fn derive_from(base: &EvalContext) -> Self {
         Self {
             env: Env::derive_from(&base.env),
-            call_depth: base.call_depth.clone(),
-            #[cfg(debug_assertions)]
             call_stack: base.call_stack.clone(),
+            debugger: base.debugger.clone(),
+            profiler: base.profiler.clone(),
+            tracer: base.tracer.clone(),
+            observer: base.observer.clone(),
+            coverage: base.coverage.clone(),
+            tests: base.tests.clone(),
+            limits: base.limits.clone(),
         }
     }
 
-    pub(crate) fn push_call(&self, proc: &Proc) {
-        #[cfg(not(debug_assertions))]
-        let _ = proc;
-
-        let depth = self.call_depth.get();
-        self.call_depth.set(depth + 1);
+    pub(crate) fn push_call(&self, proc: &Proc, span: Option<Span>) {
+        self.call_stack.push(proc, span);
+    }
 
-        #[cfg(debug_assertions)]
-        {
-            self.call_stack.borrow_mut().push(proc.badge());
-            if TRACE_CALL_STACK {
-                println!("{:03}{} -> {}", depth, " ".repeat(depth), proc.badge());
-            }
-        }
+    pub(crate) fn count_tail_call(&self) {
+        self.call_stack.count_tail_call();
     }
 
     pub(crate) fn pop_call(&self) {
-        self.call_depth.set(self.call_depth.get() - 1);
+        self.call_stack.pop();
+    }
 
-        #[cfg(debug_assertions)]
-        {
-            let badge = self.call_stack.borrow_mut().pop();
+    pub(crate) fn is_in_proc(&self) -> bool {
+        self.call_stack.depth() > 0
+    }
 
-            if TRACE_CALL_STACK {
-                if let Some(badge) = badge {
-                    let depth = self.call_depth.get();
-                    println!("{:03}{} <- {}", depth, " ".repeat(depth), badge);
-                }
-            }
-        }
+    pub(crate) fn call_depth(&self) -> usize {
+        self.call_stack.depth()
     }
 
-    pub(crate) fn is_in_proc(&self) -> bool {
-        self.call_depth.get() > 0
+    /// Span of the innermost procedure call, for errors raised by natives about
+    /// the call as a whole rather than one of its arguments.
+    pub(crate) fn call_span(&self) -> Option<Span> {
+        self.call_stack.innermost_span()
+    }
+
+    pub(crate) fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub(crate) fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    pub(crate) fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+
+    pub(crate) fn observer(&self) -> &ObserverSlot {
+        &self.observer
+    }
+
+    pub(crate) fn tests(&self) -> &TestRegistry {
+        &self.tests
+    }
+
+    pub(crate) fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub(crate) fn capture_backtrace(&self) -> Backtrace {
+        self.call_stack.capture()
     }
 }
 
@@ -109,13 +338,55 @@ pub fn eval_tail(expr: &Expr, context: &EvalContext) -> EvalResult {
 }
 
 fn eval_internal(expr: &Expr, context: &EvalContext, is_tail: bool) -> EvalResult {
+    let mut result = eval_instrumented(expr, context, is_tail);
+    if let Err(error) = &mut result {
+        if error.backtrace.is_none() && context.is_in_proc() {
+            error.backtrace = Some(Box::new(context.capture_backtrace()));
+        }
+        context.observer.report_error(error);
+    }
+    result
+}
+
+/// Evaluates `expr` under the interrupt check, the coverage and the debugger.
+fn eval_instrumented(expr: &Expr, context: &EvalContext, is_tail: bool) -> EvalResult {
+    context.limits.check_interrupt(expr.span())?;
+
+    if let Some(span) = expr.span() {
+        context.coverage.record(&span);
+    }
+
+    // expressions read from source code are where the debugger can pause, except
+    // for quoted data, which are constants
+    let span = match expr {
+        Expr::List(List::Cons(cons), Some(span))
+            if context.debugger.is_attached() && !is_quoted_data(cons) =>
+        {
+            *span
+        }
+        _ => return eval_expr(expr, context, is_tail),
+    };
+
+    context.debugger.enter_expr(expr, span, context);
+    let result = eval_expr(expr, context, is_tail);
+    context.debugger.leave_expr(expr, &result, context);
+    result
+}
+
+fn is_quoted_data(cons: &Cons) -> bool {
+    use crate::builtin::quote::{QUASIQUOTE, QUOTE};
+
+    matches!(cons.car.as_ref(), Expr::Sym(text, _) if text == QUOTE || text == QUASIQUOTE)
+}
+
+fn eval_expr(expr: &Expr, context: &EvalContext, is_tail: bool) -> EvalResult {
     match expr {
         Expr::Sym(name, span) => match context.env.lookup(name) {
             Some(expr) => Ok(expr.clone()),
-            None => Err(EvalError {
-                message: format!("Undefined symbol: `{}`", name),
-                span: *span,
-            }),
+            None => Err(EvalError::new(
+                EvalErrorKind::UnboundVariable { name: name.clone() },
+                *span,
+            )),
         },
         Expr::List(List::Cons(cons), _) => {
             use crate::builtin::quote::{quasiquote, quote, QUASIQUOTE, QUOTE};
@@ -123,13 +394,15 @@ fn eval_internal(expr: &Expr, context: &EvalContext, is_tail: bool) -> EvalResul
             let result = match cons.car.as_ref() {
                 Expr::Sym(text, _) if text == QUOTE => quote(text, &cons.cdr, context),
                 Expr::Sym(text, _) if text == QUASIQUOTE => quasiquote(text, &cons.cdr, context),
-                _ => eval_s_expr(cons, context, is_tail),
+                _ => eval_s_expr(cons, context, expr.span(), is_tail),
             };
 
             match result {
                 Err(EvalError {
-                    message,
+                    kind,
                     span: None,
+                    backtrace,
+                    is_reported,
                 }) => {
                     // If the result is an error without a span, let's try to provide a span.
                     // First, let's check if we can get a span from arguments list. If not, we'll
@@ -139,7 +412,12 @@ fn eval_internal(expr: &Expr, context: &EvalContext, is_tail: bool) -> EvalResul
                     } else {
                         expr.span()
                     };
-                    Err(EvalError { message, span })
+                    Err(EvalError {
+                        kind,
+                        span,
+                        backtrace,
+                        is_reported,
+                    })
                 }
                 _ => result,
             }
@@ -148,7 +426,12 @@ fn eval_internal(expr: &Expr, context: &EvalContext, is_tail: bool) -> EvalResul
     }
 }
 
-fn eval_s_expr(s_expr: &Cons, context: &EvalContext, is_tail: bool) -> EvalResult {
+fn eval_s_expr(
+    s_expr: &Cons,
+    context: &EvalContext,
+    span: Option<Span>,
+    is_tail: bool,
+) -> EvalResult {
     if let Expr::Proc(proc, _) = eval(&s_expr.car, context)? {
         let args = &s_expr.cdr;
 
@@ -157,157 +440,214 @@ fn eval_s_expr(s_expr: &Cons, context: &EvalContext, is_tail: bool) -> EvalResul
                 proc: proc.clone(),
                 args: args.as_ref().clone(),
                 context: context.clone(),
+                span,
             })
         } else {
-            let mut res = proc.invoke(args, context)?;
-            while let Expr::TailCall {
-                proc,
-                args,
-                context,
-            } = &res
-            {
-                res = proc.invoke(args, context)?;
+            let traced_calls = context.tracer.active_calls();
+            let observed_calls = context.observer.active_calls();
+            let mut res = proc.invoke_at(args, context, span)?;
+            if !matches!(res, Expr::TailCall { .. }) {
+                return Ok(res);
             }
-            Ok(res)
+
+            // Keep a frame for the original call while running its tail calls, so that
+            // backtraces still show it, collapsed into a single frame.
+            context.push_call(&proc, span);
+            context.profiler.resume(&proc, span);
+            let result = loop {
+                let Expr::TailCall {
+                    proc,
+                    args,
+                    context: tail_context,
+                    span,
+                } = &res
+                else {
+                    break Ok(res);
+                };
+
+                context.count_tail_call();
+                match proc.invoke_at(args, tail_context, *span) {
+                    Ok(next) => res = next,
+                    Err(error) => break Err(error),
+                }
+            };
+            context.profiler.leave();
+            context.pop_call();
+            context.tracer.leave_calls(traced_calls, &result);
+            context.observer.notify_returns(observed_calls, &result);
+            result
         }
     } else {
-        Err(EvalError {
-            message: format!("`{}` does not evaluate to a callable.", s_expr.car),
-            span: s_expr.car.span(),
-        })
+        Err(EvalError::new(
+            EvalErrorKind::NotCallable {
+                callee: s_expr.car.to_string(),
+            },
+            s_expr.car.span(),
+        ))
     }
 }
 
 pub struct Evaluator {
-    all_envs: Rc<RefCell<Vec<Weak<Env>>>>,
+    heap: Rc<Heap>,
     context: EvalContext,
 }
 
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Evaluator {
     pub fn new() -> Self {
-        let all_envs = Rc::new(RefCell::new(Vec::new()));
-        let root_env = Env::root(Rc::downgrade(&all_envs));
+        let heap = Heap::new();
+        let observer = ObserverSlot::default();
+        let root_env = Env::root(Rc::downgrade(&heap), observer.clone());
 
-        all_envs.borrow_mut().push(Rc::downgrade(&root_env));
+        heap.set_root_env(&root_env);
+        let profiler = Profiler::new(Rc::downgrade(&heap));
 
         Self {
-            all_envs,
+            heap,
             context: EvalContext {
                 env: root_env,
-                call_depth: Rc::new(Cell::new(0)),
-                #[cfg(debug_assertions)]
-                call_stack: Rc::new(RefCell::new(Vec::new())),
+                call_stack: CallStack::default(),
+                debugger: Debugger::default(),
+                profiler,
+                tracer: Tracer::default(),
+                observer,
+                coverage: Coverage::default(),
+                tests: TestRegistry::default(),
+                limits: Limits::default(),
             },
         }
     }
 
     pub fn with_builtin() -> Self {
         let evaluator = Self::new();
-        load_builtin(&evaluator.root_env());
+        load_builtin(evaluator.root_env());
         evaluator
     }
 
     pub fn with_prelude() -> Self {
         let evaluator = Self::with_builtin();
-        load_prelude(&evaluator.context());
+        load_prelude(evaluator.context());
         evaluator
     }
 
     pub fn root_env(&self) -> &Rc<Env> {
-        return &self.context.env;
+        &self.context.env
     }
 
     pub fn context(&self) -> &EvalContext {
         &self.context
     }
 
-    pub fn eval(&self, expr: &Expr) -> EvalResult {
-        let result = eval(expr, &self.context());
+    /// Returns the debugger, which pauses evaluations once a pause handler is set.
+    pub fn debugger(&self) -> &Debugger {
+        &self.context.debugger
+    }
 
-        // TODO: Collect garbage if needed
+    /// Returns the profiler, which records procedure calls once started.
+    pub fn profiler(&self) -> &Profiler {
+        &self.context.profiler
+    }
 
-        result
+    /// Returns the tracer, which prints the calls to traced procedures. The
+    /// trace goes to stderr unless a sink is set with `Tracer::set_sink`.
+    pub fn tracer(&self) -> &Tracer {
+        &self.context.tracer
     }
 
-    pub fn count_unreachable_envs(&self) -> usize {
-        self.all_envs.borrow().iter().for_each(|env| {
-            if let Some(env) = env.upgrade() {
-                env.gc_prepare();
-            }
-        });
+    /// Returns the coverage recorder, which counts evaluated expressions once
+    /// started.
+    pub fn coverage(&self) -> &Coverage {
+        &self.context.coverage
+    }
 
-        self.root_env().gc_mark();
+    /// Returns the tests defined with `deftest` so far.
+    pub fn tests(&self) -> &TestRegistry {
+        &self.context.tests
+    }
 
-        self.all_envs.borrow().iter().fold(0, |acc, env| {
-            if let Some(env) = env.upgrade() {
-                if !env.is_reachable() {
-                    return acc + 1;
-                }
-            }
-            acc
-        })
+    /// Installs `observer`, which is told about calls, returns, definitions
+    /// and updates of globals, and errors, or removes the installed one.
+    pub fn set_observer(&self, observer: Option<Box<dyn EvalObserver>>) {
+        self.context.observer.set(observer);
+    }
+
+    /// Returns a handle that interrupts the evaluations of this evaluator.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.context.limits.interrupt_handle()
     }
 
-    pub fn collect_garbage(&self) {
-        #[cfg(debug_assertions)]
-        println!("GC: begin garbage collection");
+    /// Limits how deep procedure calls may nest; deeper calls fail with a
+    /// `ResourceLimit` error. Tail calls don't nest. Pass `None` to remove
+    /// the limit (the default).
+    pub fn set_max_call_depth(&self, max_call_depth: Option<usize>) {
+        self.context.limits.set_max_call_depth(max_call_depth);
+    }
+
+    pub fn eval(&self, expr: &Expr) -> EvalResult {
+        let result = eval(expr, self.context());
 
-        self.all_envs.borrow().iter().for_each(|env| {
-            if let Some(env) = env.upgrade() {
-                env.gc_prepare();
+        if let Err(error) = &result {
+            if *error.kind == EvalErrorKind::Interrupted {
+                self.context.limits.clear_interrupt();
             }
-        });
+        }
 
-        self.root_env().gc_mark();
+        self.heap.collect_if_needed();
 
-        #[cfg(debug_assertions)]
-        let mut reachable_env_count = 0;
+        result
+    }
 
-        // GC sweep
-        let reachable_envs = self
-            .all_envs
-            .borrow()
-            .iter()
-            .filter(|env| {
-                let Some(env) = env.upgrade() else {
-                    return false;
-                };
-                if !env.is_reachable() {
-                    env.gc_sweep();
-                    #[cfg(debug_assertions)]
-                    {
-                        reachable_env_count += 1;
-                    }
-                    return false;
-                }
-                true
-            })
-            .cloned()
-            .collect();
-        *self.all_envs.borrow_mut() = reachable_envs;
+    /// Enables or disables automatic garbage collection (enabled by default).
+    ///
+    /// When enabled, garbage is collected whenever the number of envs created since
+    /// the last collection grows past the threshold, both between and during
+    /// evaluations.
+    pub fn set_auto_gc(&self, enabled: bool) {
+        self.heap.set_auto_gc_enabled(enabled);
+    }
+
+    /// Sets the minimum number of tracked envs that triggers an automatic collection.
+    pub fn set_gc_threshold(&self, threshold: usize) {
+        self.heap.set_gc_threshold(threshold);
+    }
+
+    /// Sets how much the heap may grow after a collection before the next automatic
+    /// one, relative to the number of envs that survived. Values below 1.0 are
+    /// treated as 1.0.
+    pub fn set_gc_growth_factor(&self, growth_factor: f64) {
+        self.heap.set_gc_growth_factor(growth_factor);
+    }
+
+    /// Sets a callback that receives the statistics of every collection, automatic
+    /// or not. Pass `None` to remove it.
+    pub fn set_gc_observer(&self, observer: Option<GcObserver>) {
+        self.heap.set_observer(observer);
+    }
+
+    /// Returns the statistics of every collection since this evaluator was created.
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    pub fn count_unreachable_envs(&self) -> usize {
+        self.heap.count_unreachable_envs()
+    }
 
-        #[cfg(debug_assertions)]
-        println!(
-            "GC: end garbage collection: {} envs recliamed",
-            reachable_env_count
-        );
+    pub fn collect_garbage(&self) -> GcStats {
+        self.heap.collect()
     }
 }
 
 impl Drop for Evaluator {
     fn drop(&mut self) {
-        self.all_envs.borrow().iter().for_each(|env| {
-            env.upgrade().map(|env| env.gc_sweep());
-        });
+        self.heap.sweep_all();
 
         // at this point, we should only have `context.env`
-        debug_assert_eq!(
-            1,
-            self.all_envs
-                .borrow()
-                .iter()
-                .filter(|env| env.upgrade().is_some())
-                .count()
-        );
+        debug_assert_eq!(1, self.heap.count_live_envs());
     }
 }
@@ -1,17 +1,20 @@
 use std::{
-    any::Any,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
     fmt::{self},
     rc::Rc,
 };
 
 use crate::{
     eval::EvalContext,
+    foreign::ForeignObject,
     list::{cons, List, ListIter},
+    number::write_number,
     proc::Proc,
     span::Span,
 };
 
-pub type Foreign = Rc<dyn Any>;
+pub type Foreign = Rc<dyn ForeignObject>;
 
 #[derive(Clone, Debug)]
 pub enum Expr {
@@ -28,6 +31,7 @@ pub enum Expr {
         proc: Proc,
         args: List,
         context: EvalContext,
+        span: Option<Span>,
     },
 }
 
@@ -35,17 +39,11 @@ pub const NIL: Expr = Expr::List(List::Nil, None);
 
 impl Expr {
     pub fn is_atom(&self) -> bool {
-        match self {
-            Expr::List(List::Cons(_), _) => false,
-            _ => true,
-        }
+        !matches!(self, Expr::List(List::Cons(_), _))
     }
 
     pub fn is_nil(&self) -> bool {
-        match self {
-            Expr::List(List::Nil, _) => true,
-            _ => false,
-        }
+        matches!(self, Expr::List(List::Nil, _))
     }
 
     pub fn is_truthy(&self) -> bool {
@@ -58,11 +56,24 @@ impl Expr {
             | Expr::Str(_, span)
             | Expr::Sym(_, span)
             | Expr::Proc(_, span)
-            | Expr::List(_, span) => span.clone(),
+            | Expr::List(_, span) => *span,
             Expr::Foreign(_) => None,
             Expr::TailCall { .. } => None,
         }
     }
+
+    /// Name of the type of the expression, as used in error messages.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Expr::Num(_, _) => "number",
+            Expr::Str(_, _) => "string",
+            Expr::Sym(_, _) => "symbol",
+            Expr::Proc(_, _) => "procedure",
+            Expr::List(_, _) => "list",
+            Expr::Foreign(object) => object.type_name(),
+            Expr::TailCall { .. } => "tail call",
+        }
+    }
 }
 
 impl PartialEq for Expr {
@@ -73,6 +84,7 @@ impl PartialEq for Expr {
             (Expr::Sym(lhs, _), Expr::Sym(rhs, _)) => lhs == rhs,
             (Expr::Proc(lhs, _), Expr::Proc(rhs, _)) => lhs == rhs,
             (Expr::List(lhs, _), Expr::List(rhs, _)) => lhs == rhs,
+            (Expr::Foreign(lhs), Expr::Foreign(rhs)) => lhs == rhs,
             _ => false,
         }
     }
@@ -80,18 +92,200 @@ impl PartialEq for Expr {
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expr::Num(value, _) => write!(f, "{}", value),
-            Expr::Str(text, _) => write!(f, "\"{}\"", text), // TODO: escape control chars
-            Expr::Sym(name, _) => write!(f, "{}", name),
-            Expr::Proc(proc, _) => write!(f, "<{}>", proc.fingerprint()),
-            Expr::List(list, _) => write!(f, "{}", list),
-            Expr::Foreign(object) => write!(f, "<foreign: {:p}>", object),
+        self.write().fmt(f)
+    }
+}
+
+impl Expr {
+    /// Returns an adapter that prints the expression for humans, e.g. strings
+    /// without quotes and escapes.
+    pub fn display(&self) -> Printed<'_> {
+        Printed {
+            expr: self,
+            mode: PrintMode::Display,
+        }
+    }
+
+    /// Returns an adapter that prints the expression so that it reads back as an
+    /// equal expression, as far as the type has a readable syntax. This is what
+    /// `Display` for `Expr` does as well.
+    pub fn write(&self) -> Printed<'_> {
+        Printed {
+            expr: self,
+            mode: PrintMode::Write,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PrintMode {
+    Display,
+    Write,
+}
+
+/// Prints an `Expr` in the mode chosen by `Expr::display` or `Expr::write`.
+pub struct Printed<'a> {
+    expr: &'a Expr,
+    mode: PrintMode,
+}
+
+impl fmt::Display for Printed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The items of foreign objects are printed with nested calls, so the labels
+        // are kept in a thread local for them to find. Only foreign objects can be
+        // shared, so nothing is searched for if there are none.
+        if DATUM_LABELS.with_borrow(Option::is_some) || !contains_foreign(self.expr) {
+            return self.fmt_expr(f);
+        }
+        let _scope = LabelScope::enter(find_shared_objects(self.expr));
+        self.fmt_expr(f)
+    }
+}
+
+impl Printed<'_> {
+    fn fmt_expr(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.expr, self.mode) {
+            (Expr::Num(value, _), _) => write_number(f, *value),
+            (Expr::Str(text, _), PrintMode::Display) => write!(f, "{}", text),
+            (Expr::Str(text, _), PrintMode::Write) => write_escaped_str(f, text),
+            (Expr::Sym(name, _), _) => write!(f, "{}", name),
+            (Expr::Proc(proc, _), _) => write!(f, "<{}>", proc.fingerprint()),
+            (Expr::List(list, _), _) => {
+                write!(f, "(")?;
+                for (index, expr) in list.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " ")?;
+                    }
+                    let printed = Printed {
+                        expr,
+                        mode: self.mode,
+                    };
+                    printed.fmt_expr(f)?;
+                }
+                write!(f, ")")
+            }
+            (Expr::Foreign(object), _) => {
+                match next_label(object) {
+                    Some(DatumLabel::Reference(label)) => return write!(f, "#{}#", label),
+                    Some(DatumLabel::Definition(label)) => write!(f, "#{}=", label)?,
+                    None => {}
+                }
+                match self.mode {
+                    PrintMode::Display => object.display(f),
+                    PrintMode::Write => object.write(f),
+                }
+            }
 
             // TailCall is a special case and should not be displayed.
-            Expr::TailCall { proc, .. } => panic!("Unexpected TailCall: {:?}", proc),
+            (Expr::TailCall { proc, .. }, _) => panic!("Unexpected TailCall: {:?}", proc),
+        }
+    }
+}
+
+thread_local! {
+    /// Labels of the shared foreign objects in the expression being printed.
+    /// A label is assigned when the object is printed for the first time.
+    static DATUM_LABELS: RefCell<Option<DatumLabels>> = const { RefCell::new(None) };
+}
+
+struct DatumLabels {
+    labels: HashMap<*const (), Option<usize>>,
+    next_label: usize,
+}
+
+enum DatumLabel {
+    /// `#n=`, printed before the first occurrence of a shared object.
+    Definition(usize),
+    /// `#n#`, printed instead of the later occurrences.
+    Reference(usize),
+}
+
+/// Installs the labels for the duration of a top level print.
+struct LabelScope;
+
+impl LabelScope {
+    fn enter(shared: HashSet<*const ()>) -> Self {
+        let labels = DatumLabels {
+            labels: shared.into_iter().map(|ptr| (ptr, None)).collect(),
+            next_label: 0,
+        };
+        DATUM_LABELS.set(Some(labels));
+        LabelScope
+    }
+}
+
+impl Drop for LabelScope {
+    fn drop(&mut self) {
+        DATUM_LABELS.set(None);
+    }
+}
+
+fn object_ptr(object: &Foreign) -> *const () {
+    Rc::as_ptr(object) as *const ()
+}
+
+fn next_label(object: &Foreign) -> Option<DatumLabel> {
+    DATUM_LABELS.with_borrow_mut(|state| {
+        let state = state.as_mut()?;
+        let label = state.labels.get_mut(&object_ptr(object))?;
+        match label {
+            Some(label) => Some(DatumLabel::Reference(*label)),
+            None => {
+                *label = Some(state.next_label);
+                state.next_label += 1;
+                Some(DatumLabel::Definition(state.next_label - 1))
+            }
+        }
+    })
+}
+
+fn contains_foreign(expr: &Expr) -> bool {
+    match expr {
+        Expr::List(list, _) => list.iter().any(contains_foreign),
+        Expr::Foreign(_) => true,
+        _ => false,
+    }
+}
+
+/// Finds the foreign objects reachable from `expr` more than once, either
+/// because they are shared or because they contain themselves. Lists are
+/// values, so only foreign objects can be shared.
+fn find_shared_objects(expr: &Expr) -> HashSet<*const ()> {
+    fn visit(expr: &Expr, seen: &mut HashSet<*const ()>, shared: &mut HashSet<*const ()>) {
+        match expr {
+            Expr::List(list, _) => list.iter().for_each(|item| visit(item, seen, shared)),
+            Expr::Foreign(object) => {
+                if !seen.insert(object_ptr(object)) {
+                    shared.insert(object_ptr(object));
+                    return;
+                }
+                object.trace(&mut |item| visit(item, seen, shared));
+            }
+            _ => {}
         }
     }
+
+    let mut seen = HashSet::new();
+    let mut shared = HashSet::new();
+    visit(expr, &mut seen, &mut shared);
+    shared
+}
+
+/// Writes `text` as a string literal that reads back as the same string.
+pub(crate) fn write_escaped_str(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in text.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            ch if ch.is_control() => write!(f, "\\x{:x};", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+    write!(f, "\"")
 }
 
 impl From<List> for Expr {
@@ -112,7 +306,7 @@ impl From<Vec<Expr>> for Expr {
 
 impl<'a> From<ListIter<'a>> for Expr {
     fn from(value: ListIter) -> Self {
-        value.map(|expr| expr.clone()).collect::<Vec<_>>().into()
+        value.cloned().collect::<Vec<_>>().into()
     }
 }
 
@@ -193,11 +387,46 @@ mod tests {
         assert_eq!(format!("{}", num(1)), "1");
         assert_eq!(format!("{}", num(1.2)), "1.2");
         assert_eq!(format!("{}", num(2.0)), "2");
+        assert_eq!(format!("{}", num(f64::INFINITY)), "+inf.0");
+        assert_eq!(format!("{}", num(f64::NEG_INFINITY)), "-inf.0");
+        assert_eq!(format!("{}", num(f64::NAN)), "+nan.0");
     }
 
     #[test]
     fn test_display_str() {
         assert_eq!(format!("{}", Expr::from("str")), "\"str\"");
+        assert_eq!(
+            format!("{}", Expr::from("\"a\"\\\n\t\x07λ")),
+            r#""\"a\"\\\n\t\x7;λ""#
+        );
+    }
+
+    #[test]
+    fn test_display_and_write() {
+        let expr: Expr = list!(1, "a\"b", list!(intern("c"), "d\n")).into();
+        assert_eq!(expr.display().to_string(), "(1 a\"b (c d\n))");
+        assert_eq!(expr.write().to_string(), r#"(1 "a\"b" (c "d\n"))"#);
+        assert_eq!(expr.write().to_string(), expr.to_string());
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        use crate::lexer::tokenize;
+        use crate::parser::Parser;
+
+        let expr: Expr = list!(
+            -1.5,
+            f64::INFINITY,
+            "tab\t \\ \u{7}",
+            list!(intern("quote"), intern("sym")),
+            list!()
+        )
+        .into();
+        let text = expr.write().to_string();
+        let parsed = Parser::with_tokens(tokenize(&text).unwrap())
+            .parse()
+            .unwrap();
+        assert_eq!(parsed, Some(expr));
     }
 
     #[test]
@@ -237,6 +466,87 @@ mod tests {
         assert_eq!(format!("{}", Expr::from(v)), "(1 2 (3 4))");
     }
 
+    #[test]
+    fn test_foreign() {
+        struct Handle;
+
+        impl ForeignObject for Handle {
+            fn type_name(&self) -> &str {
+                "handle"
+            }
+        }
+
+        let handle = Expr::Foreign(Rc::new(Handle));
+        assert!(format!("{}", handle).starts_with("<handle: 0x"));
+        assert_eq!(handle, handle.clone());
+        assert_ne!(handle, Expr::Foreign(Rc::new(Handle)));
+    }
+
+    #[test]
+    fn test_datum_labels() {
+        use std::cell::RefCell;
+
+        struct Bag(RefCell<Vec<Expr>>);
+
+        impl ForeignObject for Bag {
+            fn type_name(&self) -> &str {
+                "bag"
+            }
+
+            fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "#bag")?;
+                write!(f, "{}", Expr::from(self.0.borrow().clone()))
+            }
+
+            fn trace(&self, visit: &mut dyn FnMut(&Expr)) {
+                self.0.borrow().iter().for_each(visit);
+            }
+        }
+
+        let bag = Rc::new(Bag(RefCell::new(vec![num(1)])));
+        let shared = Expr::Foreign(bag.clone());
+        let other = Expr::Foreign(Rc::new(Bag(RefCell::new(vec![]))));
+
+        let expr: Expr = list!(shared.clone(), other.clone(), shared.clone()).into();
+        assert_eq!(expr.to_string(), "(#0=#bag(1) #bag() #0#)");
+
+        bag.0.borrow_mut().push(shared.clone());
+        assert_eq!(shared.to_string(), "#0=#bag(1 #0#)");
+        assert_eq!(shared.display().to_string(), "#0=#bag(1 #0#)");
+
+        let expr = Expr::from(vec![other, shared.clone(), shared.clone()]);
+        assert_eq!(expr.to_string(), "(#bag() #0=#bag(1 #0#) #0#)");
+
+        bag.0.borrow_mut().clear(); // breaks the cycle
+    }
+
+    #[test]
+    fn test_shared_objects_are_searched_once() {
+        use std::cell::Cell;
+
+        struct Probe(Cell<usize>);
+
+        impl ForeignObject for Probe {
+            fn type_name(&self) -> &str {
+                "probe"
+            }
+
+            fn trace(&self, _visit: &mut dyn FnMut(&Expr)) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let probe = Rc::new(Probe(Cell::new(0)));
+        let mut expr = Expr::Foreign(probe.clone());
+        for _ in 0..100 {
+            expr = Expr::from(vec![expr]);
+        }
+
+        let text = expr.to_string();
+        assert!(text.starts_with(&format!("{}<probe: 0x", "(".repeat(100))));
+        assert_eq!(probe.0.get(), 1);
+    }
+
     #[test]
     fn test_expr_from_bool() {
         assert_eq!(Expr::from(true), num(1));
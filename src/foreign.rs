@@ -0,0 +1,256 @@
+use std::any::Any;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::expr::Expr;
+
+/// A host-defined value that can be stored in an `Expr::Foreign`.
+///
+/// Only `type_name` is required. The remaining methods have defaults that treat
+/// the object as an opaque value compared by identity.
+///
+/// # Example
+///
+/// ```
+/// use std::{cell::RefCell, fmt, rc::Rc};
+/// use rusche::{expr::Expr, foreign::ForeignObject};
+///
+/// struct Counter(RefCell<i32>);
+///
+/// impl ForeignObject for Counter {
+///     fn type_name(&self) -> &str {
+///         "counter"
+///     }
+///
+///     fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "<counter: {}>", self.0.borrow())
+///     }
+/// }
+///
+/// let expr = Expr::Foreign(Rc::new(Counter(RefCell::new(3))));
+/// assert_eq!(expr.to_string(), "<counter: 3>");
+/// ```
+pub trait ForeignObject: Any {
+    /// Name of the type, as returned by the `foreign-type` builtin.
+    fn type_name(&self) -> &str;
+
+    /// Writes a human-readable representation of the object. Items held by the
+    /// object should be printed with `Expr::display`, e.g. strings without quotes.
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}: {:p}>", self.type_name(), self)
+    }
+
+    /// Writes a machine-readable representation of the object.
+    ///
+    /// Falls back to `display` unless the type has a readable syntax. Items held
+    /// by the object should be printed with `Expr::write`. Both adapters give
+    /// datum labels to shared and cyclic objects.
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(f)
+    }
+
+    /// Checks if `other` is equal to this object. Used by `eq?`.
+    ///
+    /// Types that override this should also override `hash_value`.
+    fn equals(&self, other: &dyn ForeignObject) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+
+    /// Feeds this object into `state`, consistently with `equals`.
+    fn hash_value(&self, mut state: &mut dyn Hasher) {
+        (self as *const Self).cast::<()>().hash(&mut state);
+    }
+
+    /// Reports every `Expr` held by this object.
+    ///
+    /// The garbage collector uses this to find the closures stored in the object.
+    /// Closures that are not reported are never swept, but reference cycles that go
+    /// through them are never reclaimed either. The printer uses it to find shared
+    /// and cyclic objects.
+    fn trace(&self, _visit: &mut dyn FnMut(&Expr)) {}
+}
+
+impl dyn ForeignObject {
+    pub fn is<T: ForeignObject>(&self) -> bool {
+        (self as &dyn Any).is::<T>()
+    }
+
+    pub fn downcast_ref<T: ForeignObject>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref::<T>()
+    }
+
+    pub fn downcast<T: ForeignObject>(self: Rc<Self>) -> Result<Rc<T>, Rc<dyn ForeignObject>> {
+        if self.is::<T>() {
+            let object: Rc<dyn Any> = self;
+            Ok(object.downcast::<T>().expect("type checked above"))
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for dyn ForeignObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(f)
+    }
+}
+
+impl fmt::Debug for dyn ForeignObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<foreign {}: {:p}>", self.type_name(), self)
+    }
+}
+
+impl PartialEq for dyn ForeignObject {
+    fn eq(&self, other: &Self) -> bool {
+        self.equals(other)
+    }
+}
+
+impl Eq for dyn ForeignObject {}
+
+impl Hash for dyn ForeignObject {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash_value(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::Printed;
+    use std::{cell::RefCell, collections::HashSet};
+
+    struct Opaque;
+
+    impl ForeignObject for Opaque {
+        fn type_name(&self) -> &str {
+            "opaque"
+        }
+    }
+
+    #[derive(PartialEq, Hash)]
+    struct Point(i32, i32);
+
+    impl ForeignObject for Point {
+        fn type_name(&self) -> &str {
+            "point"
+        }
+
+        fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "<point {} {}>", self.0, self.1)
+        }
+
+        fn equals(&self, other: &dyn ForeignObject) -> bool {
+            other.downcast_ref::<Point>() == Some(self)
+        }
+
+        fn hash_value(&self, mut state: &mut dyn Hasher) {
+            self.hash(&mut state);
+        }
+    }
+
+    struct Bag(RefCell<Vec<Expr>>);
+
+    impl Bag {
+        fn fmt_items(
+            &self,
+            f: &mut fmt::Formatter<'_>,
+            print: fn(&Expr) -> Printed<'_>,
+        ) -> fmt::Result {
+            write!(f, "#bag(")?;
+            for (index, item) in self.0.borrow().iter().enumerate() {
+                if index > 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "{}", print(item))?;
+            }
+            write!(f, ")")
+        }
+    }
+
+    impl ForeignObject for Bag {
+        fn type_name(&self) -> &str {
+            "bag"
+        }
+
+        fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.fmt_items(f, Expr::display)
+        }
+
+        fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.fmt_items(f, Expr::write)
+        }
+
+        fn trace(&self, visit: &mut dyn FnMut(&Expr)) {
+            self.0.borrow().iter().for_each(visit);
+        }
+    }
+
+    #[test]
+    fn test_default_display() {
+        let object: Rc<dyn ForeignObject> = Rc::new(Opaque);
+        assert!(object.to_string().starts_with("<opaque: 0x"));
+
+        let object: Rc<dyn ForeignObject> = Rc::new(Point(1, 2));
+        assert_eq!(object.to_string(), "<point 1 2>");
+    }
+
+    #[test]
+    fn test_print_modes_of_items() {
+        let text = Expr::Str("a \"b\"".to_string(), None);
+        let bag = Rc::new(Bag(RefCell::new(vec![text.clone()])));
+        let object = Expr::Foreign(bag.clone());
+        bag.0.borrow_mut().push(object.clone());
+
+        assert_eq!(object.display().to_string(), "#0=#bag(a \"b\" #0#)");
+        assert_eq!(object.write().to_string(), "#0=#bag(\"a \\\"b\\\"\" #0#)");
+
+        bag.0.borrow_mut().clear(); // breaks the cycle
+    }
+
+    #[test]
+    fn test_default_equals() {
+        let a: Rc<dyn ForeignObject> = Rc::new(Opaque);
+        let b: Rc<dyn ForeignObject> = Rc::new(Opaque);
+        assert!(*a == *a.clone());
+        assert!(*a != *b);
+    }
+
+    #[test]
+    fn test_custom_equals_and_hash() {
+        let a: Rc<dyn ForeignObject> = Rc::new(Point(1, 2));
+        let b: Rc<dyn ForeignObject> = Rc::new(Point(1, 2));
+        let c: Rc<dyn ForeignObject> = Rc::new(Point(2, 1));
+        assert!(*a == *b);
+        assert!(*a != *c);
+
+        let set = HashSet::from([a, b, c]);
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_downcast() {
+        let object: Rc<dyn ForeignObject> = Rc::new(Point(1, 2));
+        assert!(object.is::<Point>());
+        assert!(!object.is::<Opaque>());
+        assert!(object.downcast_ref::<Opaque>().is_none());
+
+        let Err(object) = object.downcast::<Opaque>() else {
+            panic!("downcast to a wrong type must fail");
+        };
+        let Ok(point) = object.downcast::<Point>() else {
+            panic!("downcast to the right type must succeed");
+        };
+        assert_eq!((point.0, point.1), (1, 2));
+    }
+
+    #[test]
+    fn test_trace() {
+        let bag = Bag(RefCell::new(vec![Expr::from(1), Expr::from("two")]));
+        let mut traced = Vec::new();
+        bag.trace(&mut |expr| traced.push(expr.clone()));
+        assert_eq!(traced, vec![Expr::from(1), Expr::from("two")]);
+    }
+}
@@ -0,0 +1,504 @@
+//! Canonical formatting of rusche code.
+//!
+//! The formatter works on the `Cst`, so comments are kept. Lists that fit in the
+//! remaining width are written on a single line. Otherwise each item goes on its
+//! own line, indented according to the form:
+//!
+//! - body forms like `define`, `lambda` and `let` keep their distinguished
+//!   arguments on the first line and indent their bodies by `indent` columns;
+//! - `cond` and `begin` indent all their clauses or expressions the same way;
+//! - other calls align their arguments with the first argument, unless it
+//!   doesn't fit next to the head; then all arguments are indented instead;
+//! - lists not starting with a symbol, e.g. `let` bindings, align all items.
+//!
+//! Body forms with more than one expression in their bodies are always broken
+//! into multiple lines. At most one blank line is kept between expressions.
+//!
+//! # Example
+//!
+//! ```
+//! use rusche::format::{format, FormatOptions};
+//!
+//! let text = "(define (f x)   (* x   2))";
+//! assert_eq!(format(text, &FormatOptions::default()).unwrap(), "(define (f x) (* x 2))\n");
+//!
+//! let options = FormatOptions { width: 16, ..Default::default() };
+//! assert_eq!(format(text, &options).unwrap(), "(define (f x)\n  (* x 2))\n");
+//! ```
+
+use crate::cst::{Cst, CstError, CstToken, Node, Trivia, TriviaKind};
+use crate::token::Token;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatOptions {
+    /// Maximum line width. Lines may still exceed it, e.g. for long atoms.
+    pub width: usize,
+    /// Number of columns the bodies of forms like `define` are indented by.
+    pub indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            indent: 2,
+        }
+    }
+}
+
+/// Formats rusche source code.
+pub fn format(text: &str, options: &FormatOptions) -> Result<String, CstError> {
+    Ok(format_cst(&Cst::parse(text)?, options))
+}
+
+/// Formats a syntax tree. The result always ends with a single newline, unless
+/// it's empty.
+pub fn format_cst(cst: &Cst, options: &FormatOptions) -> String {
+    let mut printer = Printer::new(options);
+
+    for node in &cst.nodes {
+        printer.write_leading_trivia(&node.first_token().leading_trivia, 0);
+        printer.break_line(0);
+        printer.write_node(node);
+    }
+    printer.write_comments(&cst.trailing_trivia, 0);
+
+    let mut text = printer.finish();
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text
+}
+
+/// Number of arguments that stay on the first line of a body form, or `None`
+/// if `name` is not a body form. `is_named_let` tells whether the second item of
+/// the form is a symbol, as in `(let loop (...) ...)`.
+pub(crate) fn distinguished_args(name: &str, is_named_let: bool) -> Option<usize> {
+    match name {
+        "begin" | "cond" => Some(0),
+        "define" | "defmacro" | "deftest" | "lambda" | "let*" | "letrec" | "when" | "unless"
+        | "while" | "case" => Some(1),
+        "let" if is_named_let => Some(2),
+        "let" => Some(1),
+        "do" => Some(2),
+        _ => None,
+    }
+}
+
+fn is_symbol(node: Option<&Node>) -> bool {
+    matches!(node, Some(Node::Atom(token)) if matches!(token.token, Token::Sym(..)))
+}
+
+/// Number of expressions in the body of a body form, e.g. 2 for
+/// `(define (f) (g) (h))`. Body forms with multiple expressions are never
+/// written on a single line.
+fn body_len(items: &[Node]) -> usize {
+    match items.first() {
+        Some(Node::Atom(CstToken {
+            token: Token::Sym(name, _),
+            ..
+        })) => distinguished_args(name, is_symbol(items.get(1)))
+            .map_or(0, |count| items.len().saturating_sub(count + 1)),
+        _ => 0,
+    }
+}
+
+/// A comment along with where it was placed relative to the preceding token.
+struct Comment<'a> {
+    trivia: &'a Trivia,
+    /// Whether the comment is on the same line as the preceding token.
+    is_trailing: bool,
+    has_blank_line_before: bool,
+}
+
+/// Extracts the comments from trivia. Also returns whether there is a blank line
+/// after the last comment.
+fn comments(trivia: &[Trivia]) -> (Vec<Comment<'_>>, bool) {
+    let mut comments = Vec::new();
+    let mut newlines = 0;
+
+    for trivia in trivia {
+        match trivia.kind {
+            TriviaKind::Whitespace => newlines += trivia.text.matches('\n').count(),
+            _ => {
+                comments.push(Comment {
+                    trivia,
+                    is_trailing: newlines == 0,
+                    has_blank_line_before: newlines > 1,
+                });
+                newlines = 0;
+            }
+        }
+    }
+
+    (comments, newlines > 1)
+}
+
+/// Whether the comment forces a line break, i.e. it's a line comment or a block
+/// comment spanning multiple lines.
+fn breaks_line(trivia: &Trivia) -> bool {
+    match trivia.kind {
+        TriviaKind::Whitespace => false,
+        TriviaKind::BlockComment => trivia.text.contains('\n'),
+        TriviaKind::LineComment | TriviaKind::Shebang => true,
+    }
+}
+
+/// Writes the single-line block comments in the trivia, each followed by a space.
+fn flat_comments(trivia: &[Trivia]) -> Option<String> {
+    if trivia.iter().any(breaks_line) {
+        return None;
+    }
+    Some(
+        trivia
+            .iter()
+            .filter(|trivia| trivia.kind == TriviaKind::BlockComment)
+            .map(|trivia| format!("{} ", trivia.text))
+            .collect(),
+    )
+}
+
+/// Writes the node on a single line, or returns `None` if it can't be. The
+/// trivia before the node's first token is left to the caller.
+fn flat(node: &Node) -> Option<String> {
+    match node {
+        Node::Atom(token) if token.text.contains('\n') => None,
+        Node::Atom(token) => Some(token.text.clone()),
+        Node::List { items, .. } if body_len(items) > 1 => None,
+        Node::List { items, close, .. } => {
+            let items = items
+                .iter()
+                .map(|item| Some(flat_comments(&item.first_token().leading_trivia)? + &flat(item)?))
+                .collect::<Option<Vec<_>>>()?;
+            let close_comments = flat_comments(&close.leading_trivia)?;
+            let close_comments = match close_comments.trim_end() {
+                "" => String::new(),
+                comments => format!(" {}", comments),
+            };
+            Some(format!("({}{})", items.join(" "), close_comments))
+        }
+        Node::Prefixed { prefix, node } => Some(format!(
+            "{}{}{}",
+            prefix.text,
+            flat_comments(&node.first_token().leading_trivia)?,
+            flat(node)?
+        )),
+    }
+}
+
+struct Printer<'a> {
+    options: &'a FormatOptions,
+    text: String,
+    column: usize,
+    /// Whether the current line ends with a line comment, so nothing else can be
+    /// written on it.
+    is_line_closed: bool,
+    /// Whether a blank line should be kept before the next line.
+    has_pending_blank_line: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(options: &'a FormatOptions) -> Self {
+        Self {
+            options,
+            text: String::new(),
+            column: 0,
+            is_line_closed: false,
+            has_pending_blank_line: false,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.trim_line_end();
+        self.text
+    }
+
+    fn write(&mut self, text: &str) {
+        self.text.push_str(text);
+        match text.rfind('\n') {
+            Some(index) => self.column = text[index + 1..].chars().count(),
+            None => self.column += text.chars().count(),
+        }
+    }
+
+    fn trim_line_end(&mut self) {
+        let len = self.text.trim_end_matches([' ', '\t']).len();
+        self.text.truncate(len);
+    }
+
+    /// Starts a new line indented by `indent` columns, unless nothing has been
+    /// written yet.
+    fn break_line(&mut self, indent: usize) {
+        if !self.text.is_empty() {
+            self.trim_line_end();
+            self.text.push('\n');
+            if self.has_pending_blank_line {
+                self.text.push('\n');
+            }
+            self.text.push_str(&" ".repeat(indent));
+            self.column = indent;
+        }
+        self.is_line_closed = false;
+        self.has_pending_blank_line = false;
+    }
+
+    /// Writes the comments in the trivia preceding a node, and remembers whether
+    /// the node should be preceded by a blank line.
+    fn write_leading_trivia(&mut self, trivia: &[Trivia], indent: usize) {
+        let has_blank_line = self.write_comments(trivia, indent);
+        self.has_pending_blank_line = has_blank_line && !self.text.is_empty();
+    }
+
+    /// Writes comments. Trailing comments stay on the line of the preceding
+    /// token; the others get their own lines. Returns whether there is a blank
+    /// line after the last comment.
+    fn write_comments(&mut self, trivia: &[Trivia], indent: usize) -> bool {
+        let (comments, has_blank_line_after) = comments(trivia);
+
+        for comment in comments {
+            if comment.is_trailing && !self.text.is_empty() && !self.is_line_closed {
+                self.write(" ");
+            } else {
+                self.has_pending_blank_line = comment.has_blank_line_before;
+                self.break_line(indent);
+            }
+            self.write(&comment.trivia.text);
+            self.is_line_closed = comment.trivia.kind != TriviaKind::BlockComment
+                || comment.trivia.text.contains('\n');
+        }
+
+        has_blank_line_after
+    }
+
+    fn write_node(&mut self, node: &Node) {
+        if let Some(text) = flat(node) {
+            if self.column + text.chars().count() <= self.options.width {
+                self.write(&text);
+                return;
+            }
+        }
+
+        match node {
+            Node::Atom(token) => self.write(&token.text),
+            Node::Prefixed { prefix, node } => {
+                self.write(&prefix.text);
+                let indent = self.column;
+                self.write_token_trivia(node.first_token(), indent);
+                self.write_node(node);
+            }
+            Node::List { items, close, .. } => self.write_list(items, close),
+        }
+    }
+
+    /// Writes the comments preceding a token nested in a node, e.g. between a
+    /// quote and the quoted expression.
+    fn write_token_trivia(&mut self, token: &CstToken, indent: usize) {
+        self.write_comments(&token.leading_trivia, indent);
+        if self.is_line_closed {
+            self.break_line(indent);
+        }
+    }
+
+    fn write_list(&mut self, items: &[Node], close: &CstToken) {
+        let base = self.column;
+        self.write("(");
+
+        let head = match items.first() {
+            Some(Node::Atom(CstToken {
+                token: Token::Sym(name, _),
+                ..
+            })) => Some(name.as_str()),
+            _ => None,
+        };
+
+        // number of items on the first line, and the indentation of the others
+        let (inline_count, indent) = match head {
+            Some(name) => match distinguished_args(name, is_symbol(items.get(1))) {
+                Some(count) => (count + 1, base + self.options.indent),
+                None => {
+                    let align = base + name.chars().count() + 2;
+                    let fits = |node: &Node| {
+                        flat(node)
+                            .is_some_and(|text| align + text.chars().count() <= self.options.width)
+                    };
+                    match items.get(1) {
+                        // the first argument doesn't fit next to the head
+                        Some(arg @ Node::List { .. }) if !fits(arg) => {
+                            (1, base + self.options.indent)
+                        }
+                        _ => (2, align),
+                    }
+                }
+            },
+            None => (1, base + 1),
+        };
+
+        // a closer left alone by a comment lines up with the last item
+        let mut last_item_column = base + 1;
+        for (index, item) in items.iter().enumerate() {
+            let first_token = item.first_token();
+            if index == 0 {
+                self.write_token_trivia(first_token, base + 1);
+            } else {
+                self.write_leading_trivia(&first_token.leading_trivia, indent);
+                if index < inline_count && !self.is_line_closed && !self.has_pending_blank_line {
+                    self.write(" ");
+                } else {
+                    self.break_line(indent);
+                }
+            }
+            last_item_column = self.column;
+            self.write_node(item);
+        }
+
+        self.write_comments(&close.leading_trivia, indent);
+        if self.is_line_closed {
+            self.break_line(last_item_column);
+        }
+        self.write(")");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_with_width(text: &str, width: usize) -> String {
+        let options = FormatOptions {
+            width,
+            ..Default::default()
+        };
+        format(text, &options).unwrap()
+    }
+
+    #[test]
+    fn test_flat() {
+        assert_eq!(
+            format_with_width("(a   b\n  (c  'd))", 80),
+            "(a b (c 'd))\n"
+        );
+        assert_eq!(format_with_width("  x  y  ", 80), "x\ny\n");
+        assert_eq!(format_with_width("", 80), "");
+    }
+
+    #[test]
+    fn test_body_forms() {
+        let text = "(define (fact n) (if (= n 0) 1 (* n (fact (- n 1)))))";
+        assert_eq!(
+            format_with_width(text, 30),
+            "\
+(define (fact n)
+  (if (= n 0)
+      1
+      (* n (fact (- n 1)))))
+"
+        );
+
+        let text = "(let loop ((i 0) (acc '())) (loop (+ i 1) (cons i acc)))";
+        assert_eq!(
+            format_with_width(text, 30),
+            "\
+(let loop ((i 0) (acc '()))
+  (loop (+ i 1) (cons i acc)))
+"
+        );
+
+        let text = "(let ((first-value 1) (second-value 2)) (+ first-value second-value))";
+        assert_eq!(
+            format_with_width(text, 30),
+            "\
+(let ((first-value 1)
+      (second-value 2))
+  (+ first-value second-value))
+"
+        );
+    }
+
+    #[test]
+    fn test_cond() {
+        let text = "(cond ((< n 0) \"negative\") ((= n 0) \"zero\") (else \"positive\"))";
+        assert_eq!(
+            format_with_width(text, 30),
+            "\
+(cond
+  ((< n 0) \"negative\")
+  ((= n 0) \"zero\")
+  (else \"positive\"))
+"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let text = "\
+#!/usr/bin/env rusche-cli
+; leading
+
+
+(define (f x) ; trailing
+  ; own line
+  (g x #| block |# 1) ; last
+  )
+; end
+";
+        assert_eq!(
+            format_with_width(text, 80),
+            "\
+#!/usr/bin/env rusche-cli
+; leading
+
+(define (f x) ; trailing
+  ; own line
+  (g x #| block |# 1) ; last
+  )
+; end
+"
+        );
+
+        // a lone closer lines up with the last item
+        assert_eq!(format_with_width("(x ;; c\n)", 80), "(x ;; c\n )\n");
+        assert_eq!(
+            format_with_width("(foo a ; c\n)", 80),
+            "(foo a ; c\n     )\n"
+        );
+        assert_eq!(format_with_width("( ; c\n)", 80), "( ; c\n )\n");
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let text = "(define a 1)\n\n\n\n(define b 2)\n(define c 3)\n";
+        assert_eq!(
+            format_with_width(text, 80),
+            "(define a 1)\n\n(define b 2)\n(define c 3)\n"
+        );
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let examples = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in std::fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "rsc") {
+                let text = std::fs::read_to_string(&path).unwrap();
+                let formatted = format(&text, &FormatOptions::default()).unwrap();
+                assert_eq!(
+                    format(&formatted, &FormatOptions::default()).unwrap(),
+                    formatted,
+                    "{:?}",
+                    path
+                );
+
+                // formatting must not change the meaning of the code
+                let exprs = |text: &str| {
+                    Cst::parse(text)
+                        .unwrap()
+                        .to_exprs()
+                        .iter()
+                        .map(|expr| expr.to_string())
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(exprs(&formatted), exprs(&text), "{:?}", path);
+            }
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::env::Env;
+use crate::expr::{Expr, Foreign};
+use crate::proc::Proc;
+
+const DEFAULT_GC_THRESHOLD: usize = 1000;
+const DEFAULT_GC_GROWTH_FACTOR: f64 = 2.0;
+
+/// Statistics reported by the garbage collector.
+///
+/// Depending on where it comes from, it describes either a single collection or
+/// every collection since the evaluator was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GcStats {
+    /// Number of envs alive after the collection.
+    pub envs_live: usize,
+    /// Number of unreachable envs that were cleared.
+    pub envs_reclaimed: usize,
+    /// Number of variable bindings dropped from the cleared envs.
+    pub bindings_freed: usize,
+    /// Time spent collecting garbage.
+    pub time_spent: Duration,
+    /// Number of collections.
+    pub collections: usize,
+}
+
+/// Callback invoked with the statistics of each collection.
+pub type GcObserver = Box<GcObserverFn>;
+
+type GcObserverFn = dyn Fn(&GcStats);
+
+/// Keeps track of every env of an evaluator and reclaims the ones that are only
+/// kept alive by reference cycles (e.g. an env holding a closure defined in it).
+///
+/// The collector cannot see references held by the host or by the Rust stack of a
+/// running evaluation. Instead, it counts the references it can see from other envs
+/// and treats any env or foreign object with more strong references than that as a
+/// root. This makes it safe to collect garbage in the middle of an evaluation.
+pub(crate) struct Heap {
+    envs: RefCell<Vec<Weak<Env>>>,
+    root_env: RefCell<Weak<Env>>,
+    is_auto_gc_enabled: Cell<bool>,
+    gc_threshold: Cell<usize>,
+    gc_growth_factor: Cell<f64>,
+    next_gc_at: Cell<usize>,
+    is_collecting: Cell<bool>,
+    /// Number of envs tracked so far, including the collected ones.
+    envs_allocated: Cell<usize>,
+    total_stats: Cell<GcStats>,
+    /// Shared so that it can be cloned out before it runs, in case it installs
+    /// another observer.
+    observer: RefCell<Option<Rc<GcObserverFn>>>,
+}
+
+impl Heap {
+    pub(crate) fn new() -> Rc<Self> {
+        Rc::new(Self {
+            envs: RefCell::new(Vec::new()),
+            root_env: RefCell::new(Weak::new()),
+            is_auto_gc_enabled: Cell::new(true),
+            gc_threshold: Cell::new(DEFAULT_GC_THRESHOLD),
+            gc_growth_factor: Cell::new(DEFAULT_GC_GROWTH_FACTOR),
+            next_gc_at: Cell::new(DEFAULT_GC_THRESHOLD),
+            is_collecting: Cell::new(false),
+            envs_allocated: Cell::new(0),
+            total_stats: Cell::new(GcStats::default()),
+            observer: RefCell::new(None),
+        })
+    }
+
+    pub(crate) fn set_root_env(&self, env: &Rc<Env>) {
+        *self.root_env.borrow_mut() = Rc::downgrade(env);
+        self.track(env);
+    }
+
+    pub(crate) fn track(&self, env: &Rc<Env>) {
+        self.envs.borrow_mut().push(Rc::downgrade(env));
+        self.envs_allocated.set(self.envs_allocated.get() + 1);
+    }
+
+    pub(crate) fn envs_allocated(&self) -> usize {
+        self.envs_allocated.get()
+    }
+
+    pub(crate) fn set_auto_gc_enabled(&self, enabled: bool) {
+        self.is_auto_gc_enabled.set(enabled);
+    }
+
+    pub(crate) fn set_gc_threshold(&self, threshold: usize) {
+        self.gc_threshold.set(threshold);
+        self.next_gc_at.set(threshold);
+    }
+
+    pub(crate) fn set_gc_growth_factor(&self, growth_factor: f64) {
+        self.gc_growth_factor.set(growth_factor.max(1.0));
+    }
+
+    pub(crate) fn set_observer(&self, observer: Option<GcObserver>) {
+        *self.observer.borrow_mut() = observer.map(Rc::from);
+    }
+
+    /// Returns the statistics of every collection so far, with `envs_live`
+    /// counting the envs that are alive right now.
+    pub(crate) fn stats(&self) -> GcStats {
+        GcStats {
+            envs_live: self.count_live_envs(),
+            ..self.total_stats.get()
+        }
+    }
+
+    /// Collects garbage if automatic collection is enabled and the number of tracked
+    /// envs has grown past the threshold.
+    pub(crate) fn collect_if_needed(&self) {
+        if self.is_auto_gc_enabled.get() && self.envs.borrow().len() >= self.next_gc_at.get() {
+            self.collect();
+        }
+    }
+
+    /// Clears the unreachable envs and returns the statistics of this collection.
+    pub(crate) fn collect(&self) -> GcStats {
+        if self.is_collecting.replace(true) {
+            return GcStats::default();
+        }
+
+        let begin_time = Instant::now();
+
+        let envs = self.live_envs();
+        self.mark(&envs);
+
+        let (reachable_envs, unreachable_envs): (Vec<_>, Vec<_>) =
+            envs.into_iter().partition(|env| env.is_reachable());
+
+        let bindings_freed = unreachable_envs.iter().map(|env| env.gc_sweep()).sum();
+        let envs_reclaimed = unreachable_envs.len();
+        drop(unreachable_envs);
+
+        *self.envs.borrow_mut() = reachable_envs.iter().map(Rc::downgrade).collect();
+
+        let next_gc_at = (reachable_envs.len() as f64 * self.gc_growth_factor.get()) as usize;
+        self.next_gc_at.set(next_gc_at.max(self.gc_threshold.get()));
+
+        let stats = GcStats {
+            envs_live: reachable_envs.len(),
+            envs_reclaimed,
+            bindings_freed,
+            time_spent: begin_time.elapsed(),
+            collections: 1,
+        };
+
+        let total_stats = self.total_stats.get();
+        self.total_stats.set(GcStats {
+            envs_live: stats.envs_live,
+            envs_reclaimed: total_stats.envs_reclaimed + stats.envs_reclaimed,
+            bindings_freed: total_stats.bindings_freed + stats.bindings_freed,
+            time_spent: total_stats.time_spent + stats.time_spent,
+            collections: total_stats.collections + 1,
+        });
+
+        self.is_collecting.set(false);
+
+        let observer = self.observer.borrow().clone();
+        if let Some(observer) = observer {
+            observer(&stats);
+        }
+
+        stats
+    }
+
+    pub(crate) fn count_unreachable_envs(&self) -> usize {
+        let envs = self.live_envs();
+        self.mark(&envs);
+        envs.iter().filter(|env| !env.is_reachable()).count()
+    }
+
+    pub(crate) fn count_live_envs(&self) -> usize {
+        self.envs
+            .borrow()
+            .iter()
+            .filter(|env| env.strong_count() > 0)
+            .count()
+    }
+
+    /// Clears every env, reachable or not.
+    pub(crate) fn sweep_all(&self) {
+        self.live_envs().iter().for_each(|env| {
+            env.gc_sweep();
+        });
+    }
+
+    fn live_envs(&self) -> Vec<Rc<Env>> {
+        self.envs
+            .borrow()
+            .iter()
+            .filter_map(|env| env.upgrade())
+            .collect()
+    }
+
+    fn mark(&self, envs: &[Rc<Env>]) {
+        envs.iter().for_each(|env| env.gc_prepare());
+
+        let mut counter = RefCounter::default();
+        envs.iter().for_each(|env| counter.count_env(env));
+
+        let mut marker = Marker::default();
+
+        if let Some(root_env) = self.root_env.borrow().upgrade() {
+            marker.mark_env(&root_env);
+        }
+
+        // `envs` itself holds a strong reference to each env.
+        envs.iter()
+            .filter(|env| Rc::strong_count(env) - 1 > counter.count_refs_to_env(env))
+            .for_each(|env| marker.mark_env(env));
+
+        // `counter` itself holds a strong reference to each foreign object.
+        counter
+            .foreign_objects
+            .values()
+            .filter(|(object, refs)| Rc::strong_count(object) - 1 > *refs)
+            .for_each(|(object, _)| object.trace(&mut |expr| marker.visit_expr(expr)));
+
+        marker.run();
+    }
+}
+
+/// Visits the envs and foreign objects directly referenced by an `Expr`.
+trait ExprVisitor {
+    fn visit_env(&mut self, env: &Rc<Env>);
+
+    fn visit_foreign(&mut self, object: &Foreign);
+
+    fn visit_expr(&mut self, expr: &Expr)
+    where
+        Self: Sized,
+    {
+        match expr {
+            Expr::Proc(proc, _) => self.visit_proc(proc),
+            Expr::List(list, _) => list.iter().for_each(|expr| self.visit_expr(expr)),
+            Expr::Foreign(object) => self.visit_foreign(object),
+            Expr::TailCall {
+                proc,
+                args,
+                context,
+                ..
+            } => {
+                self.visit_proc(proc);
+                args.iter().for_each(|expr| self.visit_expr(expr));
+                self.visit_env(&context.env);
+            }
+            Expr::Num(_, _) | Expr::Str(_, _) | Expr::Sym(_, _) => {}
+        }
+    }
+
+    fn visit_proc(&mut self, proc: &Proc)
+    where
+        Self: Sized,
+    {
+        match proc {
+            Proc::Closure {
+                body,
+                outer_context,
+                ..
+            } => {
+                body.iter().for_each(|expr| self.visit_expr(expr));
+                self.visit_env(&outer_context.env);
+            }
+            Proc::Macro { body, .. } => body.iter().for_each(|expr| self.visit_expr(expr)),
+            Proc::Native { .. } => {}
+        }
+    }
+}
+
+/// Counts the strong references that envs and foreign objects hold to each other.
+#[derive(Default)]
+struct RefCounter {
+    env_refs: HashMap<*const Env, usize>,
+    foreign_objects: HashMap<*const (), (Foreign, usize)>,
+}
+
+impl RefCounter {
+    fn count_env(&mut self, env: &Env) {
+        if let Some(base) = env.gc_base() {
+            self.visit_env(base);
+        }
+        env.gc_for_each_value(|expr| self.visit_expr(expr));
+    }
+
+    fn count_refs_to_env(&self, env: &Rc<Env>) -> usize {
+        self.env_refs.get(&Rc::as_ptr(env)).copied().unwrap_or(0)
+    }
+}
+
+impl ExprVisitor for RefCounter {
+    fn visit_env(&mut self, env: &Rc<Env>) {
+        *self.env_refs.entry(Rc::as_ptr(env)).or_default() += 1;
+    }
+
+    fn visit_foreign(&mut self, object: &Foreign) {
+        let key = Rc::as_ptr(object) as *const ();
+        if let Some((_, refs)) = self.foreign_objects.get_mut(&key) {
+            *refs += 1;
+        } else {
+            self.foreign_objects.insert(key, (object.clone(), 1));
+            object.trace(&mut |expr| self.visit_expr(expr));
+        }
+    }
+}
+
+/// Marks every env reachable from the envs passed to `mark_env`.
+///
+/// Envs found while walking an `Expr` are queued rather than marked recursively,
+/// so long chains of closures do not exhaust the stack.
+#[derive(Default)]
+struct Marker {
+    pending_envs: Vec<Rc<Env>>,
+    visited_foreign_objects: HashSet<*const ()>,
+}
+
+impl Marker {
+    fn run(&mut self) {
+        while let Some(env) = self.pending_envs.pop() {
+            self.mark_env(&env);
+        }
+    }
+
+    fn mark_env(&mut self, env: &Env) {
+        if !env.gc_mark() {
+            return;
+        }
+        if let Some(base) = env.gc_base() {
+            self.visit_env(base);
+        }
+        env.gc_for_each_value(|expr| self.visit_expr(expr));
+    }
+}
+
+impl ExprVisitor for Marker {
+    fn visit_env(&mut self, env: &Rc<Env>) {
+        if !env.is_reachable() {
+            self.pending_envs.push(env.clone());
+        }
+    }
+
+    fn visit_foreign(&mut self, object: &Foreign) {
+        // Foreign objects may contain themselves, so visit each of them only once.
+        if self
+            .visited_foreign_objects
+            .insert(Rc::as_ptr(object) as *const ())
+        {
+            object.trace(&mut |expr| self.visit_expr(expr));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{EvalContext, Evaluator};
+    use crate::list::List;
+    use crate::observer::ObserverSlot;
+
+    #[test]
+    fn test_collect_if_needed() {
+        let heap = Heap::new();
+        let root_env = Env::root(Rc::downgrade(&heap), ObserverSlot::default());
+        heap.set_root_env(&root_env);
+        heap.set_gc_threshold(4);
+
+        // derived envs dropped right away leave dead entries behind
+        Env::derive_from(&root_env);
+        Env::derive_from(&root_env);
+        assert_eq!(heap.envs.borrow().len(), 3);
+
+        // the 4th env triggers a collection, which only keeps the root and itself
+        let env = Env::derive_from(&root_env);
+        assert_eq!(heap.envs.borrow().len(), 2);
+        assert_eq!(heap.count_live_envs(), 2);
+        drop(env);
+
+        heap.set_auto_gc_enabled(false);
+        Env::derive_from(&root_env);
+        Env::derive_from(&root_env);
+        assert_eq!(heap.envs.borrow().len(), 4);
+    }
+
+    #[test]
+    fn test_collect_keeps_envs_referenced_from_outside() {
+        let heap = Heap::new();
+        let root_env = Env::root(Rc::downgrade(&heap), ObserverSlot::default());
+        heap.set_root_env(&root_env);
+
+        let env = Env::derive_from(&root_env);
+        env.define("x", 1);
+
+        assert_eq!(heap.count_unreachable_envs(), 0);
+        assert_eq!(heap.collect().envs_reclaimed, 0);
+        assert_eq!(env.lookup("x"), Some(Expr::from(1)));
+    }
+
+    #[test]
+    fn test_stats() {
+        let evaluator = Evaluator::new();
+        let heap = evaluator.root_env().gc_heap().unwrap();
+
+        let observed = Rc::new(Cell::new(GcStats::default()));
+        heap.set_observer(Some(Box::new({
+            let observed = observed.clone();
+            move |stats| observed.set(*stats)
+        })));
+
+        // an env that only keeps itself alive
+        let context = EvalContext::derive_from(evaluator.context());
+        let closure = Proc::Closure {
+            name: None,
+            formal_args: Vec::new(),
+            body: Box::new(List::Nil),
+            outer_context: context.clone(),
+        };
+        context.env.define("self", Expr::Proc(closure, None));
+        context.env.define("x", 1);
+        drop(context);
+
+        let stats = heap.collect();
+        assert_eq!(stats.envs_live, 1);
+        assert_eq!(stats.envs_reclaimed, 1);
+        assert_eq!(stats.bindings_freed, 2);
+        assert_eq!(stats.collections, 1);
+        assert_eq!(observed.get(), stats);
+
+        heap.collect();
+        let total_stats = heap.stats();
+        assert_eq!(total_stats.envs_live, 1);
+        assert_eq!(total_stats.envs_reclaimed, 1);
+        assert_eq!(total_stats.collections, 2);
+        assert_eq!(observed.get().envs_reclaimed, 0);
+    }
+
+    #[test]
+    fn test_observer_removes_itself() {
+        let heap = Heap::new();
+        let calls = Rc::new(Cell::new(0));
+        heap.set_observer(Some(Box::new({
+            let heap = Rc::downgrade(&heap);
+            let calls = calls.clone();
+            move |_| {
+                calls.set(calls.get() + 1);
+                heap.upgrade().unwrap().set_observer(None);
+            }
+        })));
+
+        heap.collect();
+        heap.collect();
+        assert_eq!(calls.get(), 1);
+    }
+}
@@ -1,5 +1,8 @@
+use crate::number::{looks_like_number, parse_number};
+use crate::source::{SourceId, SourceMap};
 use crate::span::{Loc, Span};
 use crate::token::Token;
+use std::fmt;
 use std::iter::{Iterator, Peekable};
 
 const TOKEN_DELIMITERS: &str = " \t\r\n()';\"";
@@ -8,6 +11,21 @@ const TOKEN_DELIMITERS: &str = " \t\r\n()';\"";
 pub enum LexError {
     IncompleteString(Span),
     InvalidNumber(Span),
+    IncompleteBlockComment(Span),
+    InvalidEscape(Span),
+    DigitSeparator(Span),
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::IncompleteString(_) => write!(f, "incomplete string"),
+            LexError::InvalidNumber(_) => write!(f, "invalid number"),
+            LexError::IncompleteBlockComment(_) => write!(f, "unterminated block comment"),
+            LexError::InvalidEscape(_) => write!(f, "invalid escape sequence"),
+            LexError::DigitSeparator(_) => write!(f, "digit separators are not supported"),
+        }
+    }
 }
 
 type LexResult = Result<Option<Token>, LexError>;
@@ -18,6 +36,7 @@ where
 {
     iter: Peekable<Iter>,
     loc: Loc,
+    source: Option<SourceId>,
 }
 
 impl<Iter> Lexer<Iter>
@@ -25,53 +44,100 @@ where
     Iter: Iterator<Item = char>,
 {
     pub fn new(iter: Iter) -> Self {
+        Self::with_loc(iter, Loc::new(1, 1, 0))
+    }
+
+    /// Creates a lexer whose first character is at `loc`.
+    ///
+    /// Useful when the text is a continuation of previously tokenized text,
+    /// e.g. the next line of a multi-line REPL input.
+    pub fn with_loc(iter: Iter, loc: Loc) -> Self {
         Self {
             iter: iter.peekable(),
-            loc: Loc::new(1, 1),
+            loc,
+            source: None,
         }
     }
 
+    /// Marks the spans of all tokens as belonging to `source`.
+    pub fn with_source(mut self, source: SourceId) -> Self {
+        self.source = Some(source);
+        self
+    }
+
     pub fn get_token(&mut self) -> LexResult {
-        loop {
+        let begin_loc = loop {
             self.skip_spaces();
-            if !self.skip_comment() {
-                break;
+            if self.skip_comment() {
+                continue;
             }
-        }
 
-        let begin_loc = self.loc;
+            let begin_loc = self.loc;
+            if self.next_char_if(|&ch| ch == '#').is_none() {
+                break begin_loc;
+            }
+
+            match self.iter.peek() {
+                Some('|') => {
+                    self.next_char();
+                    self.skip_block_comment(begin_loc)?;
+                }
+                Some(';') => {
+                    self.next_char();
+                    return Ok(Some(Token::DatumComment(self.span_from(begin_loc))));
+                }
+                Some('!') if begin_loc.offset == 0 => {
+                    // a shebang line, so that scripts can be made executable
+                    while self.next_char().is_some_and(|ch| ch != '\n') {}
+                }
+                Some('r') => {
+                    self.next_char();
+                    return self.read_raw_string(begin_loc);
+                }
+                Some(ch) if ch.is_ascii_digit() => return self.read_datum_label(begin_loc),
+                _ => return self.read_atom(String::from('#'), begin_loc),
+            }
+        };
 
         match self.next_char() {
-            Some('(') => Ok(Some(Token::OpenParen(begin_loc))),
-            Some(')') => Ok(Some(Token::CloseParen(begin_loc))),
+            Some('(') => Ok(Some(Token::OpenParen(self.span_from(begin_loc)))),
+            Some(')') => Ok(Some(Token::CloseParen(self.span_from(begin_loc)))),
 
-            Some('\'') => Ok(Some(Token::Quote(begin_loc))),
-            Some('`') => Ok(Some(Token::Quasiquote(begin_loc))),
+            Some('\'') => Ok(Some(Token::Quote(self.span_from(begin_loc)))),
+            Some('`') => Ok(Some(Token::Quasiquote(self.span_from(begin_loc)))),
             Some(',') => {
                 if self.next_char_if(|ch| *ch == '@').is_some() {
-                    Ok(Some(Token::UnquoteSplicing(begin_loc)))
+                    Ok(Some(Token::UnquoteSplicing(self.span_from(begin_loc))))
                 } else {
-                    Ok(Some(Token::Unquote(begin_loc)))
+                    Ok(Some(Token::Unquote(self.span_from(begin_loc))))
                 }
             }
 
             // string
             Some('"') => self.read_string(begin_loc),
 
-            // number
-            Some(ch) if ch.is_ascii_digit() => self.read_number(ch, begin_loc),
+            // number or symbol; we allow all other characters to be a symbol
+            Some(ch) => self.read_atom(String::from(ch), begin_loc),
 
-            // number or symbol
-            Some(ch) if ch == '+' || ch == '-' => match self.iter.peek() {
-                Some(&next_ch) if next_ch.is_ascii_digit() => self.read_number(ch, begin_loc),
-                _ => self.read_symbol(ch, begin_loc),
-            },
+            None => Ok(None),
+        }
+    }
 
-            // we allow all other characters to be a symbol
-            Some(ch) => self.read_symbol(ch, begin_loc),
+    /// Reads all remaining tokens. Unlike `get_token`, a malformed token doesn't
+    /// stop the lexer; it is skipped and its error is collected instead.
+    pub fn read_all(mut self) -> (Vec<Token>, Vec<LexError>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
 
-            None => Ok(None),
+        loop {
+            match self.get_token() {
+                Ok(Some(token)) => tokens.push(token),
+                Ok(None) => break,
+                Err(error) => errors.push(error),
+            }
         }
+
+        (tokens, errors)
     }
 
     fn skip_spaces(&mut self) {
@@ -79,67 +145,185 @@ where
     }
 
     fn skip_comment(&mut self) -> bool {
-        if self.iter.next_if_eq(&';').is_some() {
-            let _ = self.iter.find(|&ch| ch == '\n');
-            self.advance_loc(&Some('\n'));
+        if self.next_char_if(|&ch| ch == ';').is_some() {
+            while self.next_char().is_some_and(|ch| ch != '\n') {}
             true
         } else {
             false
         }
     }
 
-    fn read_string(&mut self, begin_loc: Loc) -> LexResult {
-        let mut text = String::new();
-        let mut escaped = false;
+    /// Skips a block comment whose `#|` has been consumed. Block comments nest.
+    fn skip_block_comment(&mut self, begin_loc: Loc) -> Result<(), LexError> {
+        let mut depth = 1;
         while let Some(ch) = self.next_char() {
-            match (ch, escaped) {
-                ('\n', _) => return Err(LexError::IncompleteString(begin_loc.span_to(self.loc))),
-                (ch, true) => {
-                    escaped = false;
-                    match ch {
-                        'n' => text.push('\n'),
-                        'r' => text.push('\r'),
-                        't' => text.push('\t'),
-                        _ => text.push(ch),
+            match ch {
+                '|' if self.next_char_if(|&ch| ch == '#').is_some() => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
                     }
                 }
-                ('"', false) => return Ok(Some(Token::Str(text, begin_loc.span_to(self.loc)))),
-                ('\\', false) => escaped = true,
-                (ch, false) => text.push(ch),
+                '#' if self.next_char_if(|&ch| ch == '|').is_some() => depth += 1,
+                _ => {}
             }
         }
-        Err(LexError::IncompleteString(begin_loc.span_to(self.loc)))
+        Err(LexError::IncompleteBlockComment(self.span_from(begin_loc)))
     }
 
-    fn read_number(&mut self, first_char: char, begin_loc: Loc) -> LexResult {
-        let mut digits = String::new();
+    /// Reads a string whose opening `"` has been consumed. Strings may span
+    /// multiple lines. A malformed escape sequence doesn't end the string; it is
+    /// reported once the whole string has been read.
+    fn read_string(&mut self, begin_loc: Loc) -> LexResult {
+        let mut text = String::new();
+        let mut escape_error = None;
 
-        if first_char.is_ascii_digit() {
-            digits.push(first_char);
+        loop {
+            let escape_loc = self.loc;
+            match self.next_char() {
+                Some('"') => break,
+                Some('\\') => {
+                    if let Err(error) = self.read_escape(escape_loc, &mut text) {
+                        escape_error.get_or_insert(error);
+                    }
+                }
+                Some(ch) => text.push(ch),
+                None => return Err(LexError::IncompleteString(self.span_from(begin_loc))),
+            }
         }
 
-        while let Some(ch) = self.next_char_if(|ch| !TOKEN_DELIMITERS.contains(*ch)) {
-            digits.push(ch);
+        match escape_error {
+            Some(error) => Err(error),
+            None => Ok(Some(Token::Str(text, self.span_from(begin_loc)))),
         }
+    }
+
+    /// Reads an escape sequence whose `\` has been consumed and appends the
+    /// character it stands for to `text`.
+    fn read_escape(&mut self, escape_loc: Loc, text: &mut String) -> Result<(), LexError> {
+        let Some(ch) = self.next_char() else {
+            return Ok(()); // let `read_string` report the incomplete string
+        };
+
+        let escaped = match ch {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'a' => '\x07',
+            'b' => '\x08',
+            '0' => '\0',
+            '"' | '\\' => ch,
+
+            // \x41;
+            'x' => {
+                let digits = self.read_while(|ch| ch.is_ascii_hexdigit());
+                if self.next_char_if(|&ch| ch == ';').is_none() {
+                    return Err(LexError::InvalidEscape(self.span_from(escape_loc)));
+                }
+                self.char_from_hex(&digits, escape_loc)?
+            }
 
-        let sign = if first_char == '-' { -1.0 } else { 1.0 };
-        let span = begin_loc.span_to(self.loc);
+            // \u{1F600}
+            'u' => {
+                if self.next_char_if(|&ch| ch == '{').is_none() {
+                    return Err(LexError::InvalidEscape(self.span_from(escape_loc)));
+                }
+                let digits = self.read_while(|ch| ch.is_ascii_hexdigit());
+                if self.next_char_if(|&ch| ch == '}').is_none() {
+                    return Err(LexError::InvalidEscape(self.span_from(escape_loc)));
+                }
+                self.char_from_hex(&digits, escape_loc)?
+            }
+
+            // line continuation: skips the line break and the indentation around it
+            ch if ch == '\n' || is_intraline_space(ch) => {
+                let mut ch = ch;
+                while is_intraline_space(ch) {
+                    match self.next_char_if(|&ch| is_intraline_space(ch) || ch == '\n') {
+                        Some(next_ch) => ch = next_ch,
+                        None => return Err(LexError::InvalidEscape(self.span_from(escape_loc))),
+                    }
+                }
+                self.read_while(is_intraline_space);
+                return Ok(());
+            }
+
+            _ => return Err(LexError::InvalidEscape(self.span_from(escape_loc))),
+        };
+
+        text.push(escaped);
+        Ok(())
+    }
+
+    fn char_from_hex(&self, digits: &str, escape_loc: Loc) -> Result<char, LexError> {
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| LexError::InvalidEscape(self.span_from(escape_loc)))
+    }
+
+    /// Reads a raw string whose `#r` has been consumed, e.g. `#r"\d+"`. The
+    /// text is taken as is, without any escape sequences. To include `"` in
+    /// the text, put the same number of `#`s around the quotes, e.g.
+    /// `#r#"say "hi""#`.
+    fn read_raw_string(&mut self, begin_loc: Loc) -> LexResult {
+        let hashes = self.read_while(|ch| ch == '#').len();
+        if self.next_char_if(|&ch| ch == '"').is_none() {
+            let prefix = format!("#r{}", "#".repeat(hashes));
+            return self.read_symbol_with(prefix, begin_loc);
+        }
+
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let mut text = String::new();
+        while let Some(ch) = self.next_char() {
+            text.push(ch);
+            if text.ends_with(&terminator) {
+                text.truncate(text.len() - terminator.len());
+                return Ok(Some(Token::Str(text, self.span_from(begin_loc))));
+            }
+        }
+        Err(LexError::IncompleteString(self.span_from(begin_loc)))
+    }
 
-        digits
-            .parse::<f64>()
-            .map(|value| Some(Token::Num(value * sign, span)))
-            .map_err(|_| LexError::InvalidNumber(span))
+    /// Reads a number or a symbol.
+    /// Reads `#n=` or `#n#` after the `#`. Anything else is read as an atom.
+    fn read_datum_label(&mut self, begin_loc: Loc) -> LexResult {
+        let digits = self.read_while(|ch| ch.is_ascii_digit());
+        let Ok(label) = digits.parse() else {
+            return self.read_atom(format!("#{}", digits), begin_loc);
+        };
+        match self.iter.peek() {
+            Some('=') => {
+                self.next_char();
+                Ok(Some(Token::DatumLabel(label, self.span_from(begin_loc))))
+            }
+            Some('#') => {
+                self.next_char();
+                Ok(Some(Token::DatumRef(label, self.span_from(begin_loc))))
+            }
+            _ => self.read_atom(format!("#{}", digits), begin_loc),
+        }
     }
 
-    fn read_symbol(&mut self, first_char: char, begin_loc: Loc) -> LexResult {
-        let mut name = String::with_capacity(16);
-        name.push(first_char);
+    fn read_atom(&mut self, mut text: String, begin_loc: Loc) -> LexResult {
+        text += &self.read_while(|ch| !TOKEN_DELIMITERS.contains(ch));
 
-        while let Some(ch) = self.next_char_if(|ch| !TOKEN_DELIMITERS.contains(*ch)) {
-            name.push(ch);
+        let span = self.span_from(begin_loc);
+        if let Some(value) = parse_number(&text) {
+            Ok(Some(Token::Num(value, span)))
+        } else if !looks_like_number(&text) {
+            Ok(Some(Token::Sym(text, span)))
+        } else if parse_number(&text.replace('_', "")).is_some() {
+            Err(LexError::DigitSeparator(span)) // e.g. 1_000
+        } else {
+            Err(LexError::InvalidNumber(span))
         }
+    }
+
+    fn read_symbol_with(&mut self, mut name: String, begin_loc: Loc) -> LexResult {
+        name += &self.read_while(|ch| !TOKEN_DELIMITERS.contains(ch));
 
-        Ok(Some(Token::Sym(name, Span::new(begin_loc, self.loc))))
+        Ok(Some(Token::Sym(name, self.span_from(begin_loc))))
     }
 }
 
@@ -159,8 +343,21 @@ where
         ch
     }
 
+    fn read_while(&mut self, func: impl Fn(char) -> bool) -> String {
+        let mut text = String::new();
+        while let Some(ch) = self.next_char_if(|&ch| func(ch)) {
+            text.push(ch);
+        }
+        text
+    }
+
+    fn span_from(&self, begin_loc: Loc) -> Span {
+        begin_loc.span_to(self.loc).with_source(self.source)
+    }
+
     fn advance_loc(&mut self, ch: &Option<char>) {
         if let Some(ch) = ch {
+            self.loc.offset += ch.len_utf8();
             if *ch == '\n' {
                 self.loc.line += 1;
                 self.loc.column = 1;
@@ -171,9 +368,32 @@ where
     }
 }
 
+fn is_intraline_space(ch: char) -> bool {
+    ch == ' ' || ch == '\t' || ch == '\r'
+}
+
 pub fn tokenize(text: &str) -> Result<Vec<Token>, LexError> {
+    collect_tokens(Lexer::new(text.chars()))
+}
+
+/// Tokenizes a source registered in the `SourceMap`.
+pub fn tokenize_source(source: SourceId) -> Result<Vec<Token>, LexError> {
+    let text = SourceMap::text(source).unwrap_or_default();
+    collect_tokens(Lexer::new(text.chars()).with_source(source))
+}
+
+/// Tokenizes a source registered in the `SourceMap`, collecting every lex error
+/// instead of stopping at the first one.
+pub fn tokenize_source_all(source: SourceId) -> (Vec<Token>, Vec<LexError>) {
+    let text = SourceMap::text(source).unwrap_or_default();
+    Lexer::new(text.chars()).with_source(source).read_all()
+}
+
+fn collect_tokens<Iter>(mut lexer: Lexer<Iter>) -> Result<Vec<Token>, LexError>
+where
+    Iter: Iterator<Item = char>,
+{
     let mut tokens = Vec::new();
-    let mut lexer = Lexer::new(text.chars());
 
     while let Some(token) = lexer.get_token()? {
         tokens.push(token);
@@ -206,8 +426,50 @@ mod tests {
         assert_parse_string!(r#""an escaped\" string""#, "an escaped\" string");
         assert_parse_string!(
             r#""incomplete string"#,
-            LexError::IncompleteString(Span::new(Loc::new(1, 1), Loc::new(1, 19)))
+            LexError::IncompleteString(Span::new(Loc::new(1, 1, 0), Loc::new(1, 19, 18)))
+        );
+        assert_parse_string!("\"multi\nline\"", "multi\nline");
+        assert_parse_string!(r#""\x41;\x3bb;\u{1F600}""#, "Aλ😀");
+        assert_parse_string!("\"con\\  \n    tinued\"", "continued");
+        assert_parse_string!(r#""\a\b\0\\""#, "\x07\x08\0\\");
+        assert_parse_string!(
+            r#""a \q b""#,
+            LexError::InvalidEscape(Span::new(Loc::new(1, 4, 3), Loc::new(1, 6, 5)))
+        );
+        assert_parse_string!(
+            r#""\x41""#,
+            LexError::InvalidEscape(Span::new(Loc::new(1, 2, 1), Loc::new(1, 6, 5)))
         );
+        assert_parse_string!(
+            r#""\u{110000}""#,
+            LexError::InvalidEscape(Span::new(Loc::new(1, 2, 1), Loc::new(1, 12, 11)))
+        );
+    }
+
+    #[test]
+    fn test_read_raw_string() {
+        let tokens = tokenize(r###"#r"\d+" #r#"say "hi""# #rest"###).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Str(r"\d+".to_string(), tokens[0].span()),
+                Token::Str(r#"say "hi""#.to_string(), tokens[1].span()),
+                Token::Sym("#rest".to_string(), tokens[2].span()),
+            ]
+        );
+        assert_eq!(tokens[1].span().len(), 14);
+
+        assert!(matches!(
+            tokenize(r#"#r"abc"#),
+            Err(LexError::IncompleteString(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_escape_does_not_end_string() {
+        let (tokens, errors) = Lexer::new(r#"("\q" a)"#.chars()).read_all();
+        assert_eq!(tokens.len(), 3); // ( a )
+        assert!(matches!(errors[..], [LexError::InvalidEscape(_)]));
     }
 
     #[test]
@@ -225,8 +487,35 @@ mod tests {
         assert_parsed_number!("1", 1);
         assert_parsed_number!("1.1", 1.1);
         assert_parsed_number!("-1", -1);
+        assert_parsed_number!(".5", 0.5);
+        assert_parsed_number!("1e3", 1000);
+        assert_parsed_number!("#xff", 255);
+        assert_parsed_number!("#b-101", -5);
+        assert_parsed_number!("1/4", 0.25);
+        assert_eq!(
+            tokenize("-inf.0"),
+            Ok(vec![Token::Num(
+                f64::NEG_INFINITY,
+                Span::new(Loc::new(1, 1, 0), Loc::new(1, 7, 6))
+            )])
+        );
 
-        assert!(Lexer::new("123xya".chars()).get_token().is_err());
+        assert!(matches!(
+            Lexer::new("123xya".chars()).get_token(),
+            Err(LexError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            Lexer::new("#b102".chars()).get_token(),
+            Err(LexError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            Lexer::new("1_000".chars()).get_token(),
+            Err(LexError::DigitSeparator(_))
+        ));
+
+        // symbols that start like numbers
+        let tokens = tokenize("+ - ... -> +inf #define").unwrap();
+        assert!(tokens.iter().all(|token| matches!(token, Token::Sym(_, _))));
     }
 
     #[test]
@@ -255,8 +544,8 @@ mod tests {
             };
             ($token_case:ident) => {
                 let token = lexer.get_token().unwrap().unwrap();
-                let loc = Loc::new(1, 1); // don't care about the location
-                assert_eq!(token, Token::$token_case(loc));
+                let span = token.span(); // don't care about the location
+                assert_eq!(token, Token::$token_case(span));
             };
         }
 
@@ -290,8 +579,8 @@ mod tests {
             };
             (Some($token_case:ident)) => {
                 let token = lexer.get_token().unwrap().unwrap();
-                let loc = Loc::new(1, 1); // don't care about the location
-                assert_eq!(token, Token::$token_case(loc));
+                let span = token.span(); // don't care about the location
+                assert_eq!(token, Token::$token_case(span));
             };
             (Some($token_case:ident($value:expr))) => {
                 let token = lexer.get_token().unwrap().unwrap();
@@ -336,20 +625,20 @@ mod tests {
             };
         }
 
-        match_next_span!(Span::new(Loc::new(2, 13), Loc::new(2, 14))); // (
-        match_next_span!(Span::new(Loc::new(2, 14), Loc::new(2, 20))); // define
-        match_next_span!(Span::new(Loc::new(2, 21), Loc::new(2, 22))); // (
-        match_next_span!(Span::new(Loc::new(2, 22), Loc::new(2, 31))); // factorial
-        match_next_span!(Span::new(Loc::new(2, 32), Loc::new(2, 33))); // n
-        match_next_span!(Span::new(Loc::new(2, 33), Loc::new(2, 34))); // )
-        match_next_span!(Span::new(Loc::new(3, 17), Loc::new(3, 18))); // (
-        match_next_span!(Span::new(Loc::new(3, 18), Loc::new(3, 20))); // if
-        match_next_span!(Span::new(Loc::new(3, 21), Loc::new(3, 22))); // (
-        match_next_span!(Span::new(Loc::new(3, 22), Loc::new(3, 23))); // =
-        match_next_span!(Span::new(Loc::new(3, 24), Loc::new(3, 25))); // n
-        match_next_span!(Span::new(Loc::new(3, 26), Loc::new(3, 27))); // 0
-        match_next_span!(Span::new(Loc::new(3, 27), Loc::new(3, 28))); // )
-        match_next_span!(Span::new(Loc::new(4, 21), Loc::new(4, 22))); // 1
+        match_next_span!(Span::new(Loc::new(2, 13, 13), Loc::new(2, 14, 14))); // (
+        match_next_span!(Span::new(Loc::new(2, 14, 14), Loc::new(2, 20, 20))); // define
+        match_next_span!(Span::new(Loc::new(2, 21, 21), Loc::new(2, 22, 22))); // (
+        match_next_span!(Span::new(Loc::new(2, 22, 22), Loc::new(2, 31, 31))); // factorial
+        match_next_span!(Span::new(Loc::new(2, 32, 32), Loc::new(2, 33, 33))); // n
+        match_next_span!(Span::new(Loc::new(2, 33, 33), Loc::new(2, 34, 34))); // )
+        match_next_span!(Span::new(Loc::new(3, 17, 51), Loc::new(3, 18, 52))); // (
+        match_next_span!(Span::new(Loc::new(3, 18, 52), Loc::new(3, 20, 54))); // if
+        match_next_span!(Span::new(Loc::new(3, 21, 55), Loc::new(3, 22, 56))); // (
+        match_next_span!(Span::new(Loc::new(3, 22, 56), Loc::new(3, 23, 57))); // =
+        match_next_span!(Span::new(Loc::new(3, 24, 58), Loc::new(3, 25, 59))); // n
+        match_next_span!(Span::new(Loc::new(3, 26, 60), Loc::new(3, 27, 61))); // 0
+        match_next_span!(Span::new(Loc::new(3, 27, 61), Loc::new(3, 28, 62))); // )
+        match_next_span!(Span::new(Loc::new(4, 21, 83), Loc::new(4, 22, 84))); // 1
 
         // ...
     }
@@ -368,11 +657,89 @@ mod tests {
             };
         }
 
-        match_next_span!(Span::new(Loc::new(1, 1), Loc::new(1, 2))); // (
-        match_next_span!(Span::new(Loc::new(1, 2), Loc::new(1, 8))); // define
-        match_next_span!(Span::new(Loc::new(1, 9), Loc::new(1, 13))); // test
-        match_next_span!(Span::new(Loc::new(1, 14), Loc::new(1, 20))); // "test"
-        match_next_span!(Span::new(Loc::new(1, 20), Loc::new(1, 21))); // )
+        match_next_span!(Span::new(Loc::new(1, 1, 0), Loc::new(1, 2, 1))); // (
+        match_next_span!(Span::new(Loc::new(1, 2, 1), Loc::new(1, 8, 7))); // define
+        match_next_span!(Span::new(Loc::new(1, 9, 8), Loc::new(1, 13, 12))); // test
+        match_next_span!(Span::new(Loc::new(1, 14, 13), Loc::new(1, 20, 19))); // "test"
+        match_next_span!(Span::new(Loc::new(1, 20, 19), Loc::new(1, 21, 20))); // )
         match_next_span!(None);
     }
+
+    #[test]
+    fn test_span_with_source() {
+        let source = SourceMap::add("test.rsc", "; λ comment\n(λ \"é\")");
+        let tokens = tokenize_source(source).unwrap();
+
+        let spans = tokens.iter().map(|token| token.span()).collect::<Vec<_>>();
+        assert!(spans.iter().all(|span| span.source == Some(source)));
+        assert_eq!(spans[1].begin, Loc::new(2, 2, 14)); // λ
+        assert_eq!(spans[2].begin, Loc::new(2, 4, 17)); // "é"
+        assert_eq!(spans[2].len(), 4);
+        assert_eq!(spans[3].end, Loc::new(2, 8, 22)); // )
+    }
+
+    #[test]
+    fn test_read_all_collects_errors() {
+        let (tokens, errors) = Lexer::new("(1x 2 \"a\\qc\" 3)\n\"abc".chars()).read_all();
+
+        assert_eq!(tokens.len(), 4); // ( 2 3 )
+        assert_eq!(
+            errors,
+            vec![
+                LexError::InvalidNumber(Span::new(Loc::new(1, 2, 1), Loc::new(1, 4, 3))),
+                LexError::InvalidEscape(Span::new(Loc::new(1, 9, 8), Loc::new(1, 11, 10))),
+                LexError::IncompleteString(Span::new(Loc::new(2, 1, 16), Loc::new(2, 5, 20))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let text = "#!/usr/bin/env rusche-cli\n#| block #| nested |# |# a #; #c ; line\n c";
+        let tokens = tokenize(text).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Sym("a".to_string(), tokens[0].span()),
+                Token::DatumComment(tokens[1].span()),
+                Token::Sym("#c".to_string(), tokens[2].span()),
+                Token::Sym("c".to_string(), tokens[3].span()),
+            ]
+        );
+        assert_eq!(tokens[0].span().begin, Loc::new(2, 26, 51));
+        assert_eq!(tokens[1].span().len(), 2);
+
+        // shebang is only allowed on the first line
+        assert!(tokenize("a\n#!b")
+            .is_ok_and(|tokens| tokens[1] == Token::Sym("#!b".to_string(), tokens[1].span())));
+
+        assert_eq!(
+            tokenize("a #| #| |#"),
+            Err(LexError::IncompleteBlockComment(Span::new(
+                Loc::new(1, 3, 2),
+                Loc::new(1, 11, 10)
+            )))
+        );
+    }
+
+    #[test]
+    fn test_datum_labels() {
+        let tokens = tokenize("#0=(a #0#) #12# #1x #1").unwrap();
+        let span = |index: usize| tokens[index].span();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::DatumLabel(0, span(0)),
+                Token::OpenParen(span(1)),
+                Token::Sym("a".to_string(), span(2)),
+                Token::DatumRef(0, span(3)),
+                Token::CloseParen(span(4)),
+                Token::DatumRef(12, span(5)),
+                Token::Sym("#1x".to_string(), span(6)),
+                Token::Sym("#1".to_string(), span(7)),
+            ]
+        );
+        assert_eq!(span(0).len(), 3);
+        assert_eq!(span(5).len(), 4);
+    }
 }
@@ -1,14 +1,30 @@
 mod builtin;
 mod prelude;
 
+pub mod backtrace;
+pub mod coverage;
+pub mod cst;
+pub mod debug;
+pub mod diagnostic;
 pub mod env;
 pub mod eval;
 pub mod expr;
+pub mod foreign;
+pub mod format;
+pub mod gc;
 pub mod lexer;
+pub mod limits;
 pub mod list;
 pub mod macros;
+pub mod number;
+pub mod observer;
 pub mod parser;
+pub mod pretty;
 pub mod proc;
+pub mod profile;
+pub mod source;
 pub mod span;
+pub mod testing;
 pub mod token;
+pub mod trace;
 pub mod utils;
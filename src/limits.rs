@@ -0,0 +1,143 @@
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    eval::{EvalError, EvalErrorKind},
+    span::Span,
+};
+
+/// Interrupts the evaluations of an evaluator from any thread, e.g. from a
+/// Ctrl-C handler.
+///
+/// ```
+/// use rusche::{eval::{EvalErrorKind, Evaluator}, lexer::tokenize, parser::Parser};
+///
+/// let evaluator = Evaluator::with_prelude();
+/// let handle = evaluator.interrupt_handle();
+/// std::thread::spawn(move || handle.interrupt());
+///
+/// let tokens = tokenize("(while #t 1)").unwrap();
+/// let expr = Parser::with_tokens(tokens).parse().unwrap().unwrap();
+/// let error = evaluator.eval(&expr).unwrap_err();
+/// assert_eq!(*error.kind, EvalErrorKind::Interrupted);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Stops the running evaluation with an `Interrupted` error, or the next
+    /// one if none is running.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Limits set by the host on the evaluations of an evaluator, shared by its
+/// contexts.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Limits(Rc<LimitsState>);
+
+#[derive(Debug, Default)]
+struct LimitsState {
+    interrupt: InterruptHandle,
+    max_call_depth: Cell<Option<usize>>,
+}
+
+impl Limits {
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.0.interrupt.clone()
+    }
+
+    pub fn set_max_call_depth(&self, max_call_depth: Option<usize>) {
+        self.0.max_call_depth.set(max_call_depth);
+    }
+
+    pub fn check_interrupt(&self, span: Option<Span>) -> Result<(), EvalError> {
+        if self.0.interrupt.0.load(Ordering::Relaxed) {
+            return Err(EvalError::new(EvalErrorKind::Interrupted, span));
+        }
+        Ok(())
+    }
+
+    /// Called once an evaluation stopped by an interrupt has returned, so that
+    /// the interrupt doesn't stop the next one.
+    pub fn clear_interrupt(&self) {
+        self.0.interrupt.0.store(false, Ordering::Relaxed);
+    }
+
+    pub fn check_call_depth(&self, depth: usize, span: Option<Span>) -> Result<(), EvalError> {
+        match self.0.max_call_depth.get() {
+            Some(limit) if depth > limit => Err(EvalError::new(
+                EvalErrorKind::ResourceLimit {
+                    resource: "call depth".to_string(),
+                    limit,
+                },
+                span,
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        eval::{EvalErrorKind, EvalResult, Evaluator},
+        lexer::tokenize,
+        parser::Parser,
+    };
+
+    fn eval_all(evaluator: &Evaluator, text: &str) -> EvalResult {
+        let mut parser = Parser::with_tokens(tokenize(text).unwrap());
+        let mut result = Ok(crate::expr::NIL);
+        while let Some(expr) = parser.parse().unwrap() {
+            result = Ok(evaluator.eval(&expr)?);
+        }
+        result
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let evaluator = Evaluator::with_prelude();
+        let handle = evaluator.interrupt_handle();
+
+        handle.interrupt();
+        let error = eval_all(&evaluator, "(+ 1 2)").unwrap_err();
+        assert_eq!(*error.kind, EvalErrorKind::Interrupted);
+        assert_eq!(error.message(), "Evaluation interrupted.");
+
+        // an interrupt stops one evaluation only
+        assert_eq!(eval_all(&evaluator, "(+ 1 2)"), Ok(3.into()));
+    }
+
+    #[test]
+    fn test_max_call_depth() {
+        let evaluator = Evaluator::with_prelude();
+        let text = "
+(define (depth n) (if (= n 0) 0 (+ 1 (depth (- n 1)))))
+(define (count n) (if (= n 0) 0 (count (- n 1))))";
+        eval_all(&evaluator, text).unwrap();
+
+        evaluator.set_max_call_depth(Some(50));
+        assert_eq!(eval_all(&evaluator, "(depth 10)"), Ok(10.into()));
+        let error = eval_all(&evaluator, "(depth 100)").unwrap_err();
+        assert_eq!(
+            *error.kind,
+            EvalErrorKind::ResourceLimit {
+                resource: "call depth".to_string(),
+                limit: 50,
+            }
+        );
+        // tail calls don't nest
+        assert_eq!(eval_all(&evaluator, "(count 100)"), Ok(0.into()));
+
+        evaluator.set_max_call_depth(None);
+        assert_eq!(eval_all(&evaluator, "(depth 100)"), Ok(100.into()));
+    }
+}
@@ -27,6 +27,31 @@ impl Cons {
             None
         }
     }
+
+    /// Moves the conses of `car` and `cdr` to `pending`, leaving empty lists
+    /// behind.
+    fn detach_lists(&mut self, pending: &mut Vec<Cons>) {
+        if let Expr::List(list @ List::Cons(_), _) = self.car.as_mut() {
+            if let List::Cons(cons) = std::mem::replace(list, List::Nil) {
+                pending.push(cons);
+            }
+        }
+        if let List::Cons(cons) = std::mem::replace(self.cdr.as_mut(), List::Nil) {
+            pending.push(cons);
+        }
+    }
+}
+
+impl Drop for Cons {
+    fn drop(&mut self) {
+        // dropped with an explicit stack, since long or deeply nested lists would
+        // overflow the call stack
+        let mut pending = Vec::new();
+        self.detach_lists(&mut pending);
+        while let Some(mut cons) = pending.pop() {
+            cons.detach_lists(&mut pending);
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -36,7 +61,7 @@ pub enum List {
 }
 
 impl List {
-    pub fn iter(&self) -> ListIter {
+    pub fn iter(&self) -> ListIter<'_> {
         ListIter::new(self)
     }
 
@@ -44,12 +69,12 @@ impl List {
         self.iter().count()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.is_nil()
+    }
+
     pub fn is_nil(&self) -> bool {
-        if let List::Nil = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, List::Nil)
     }
 
     pub fn span(&self) -> Option<Span> {
@@ -57,9 +82,7 @@ impl List {
 
         match (iter.next(), iter.last()) {
             (Some(first), Some(last)) => match (first.span(), last.span()) {
-                (Some(first_span), Some(last_span)) => {
-                    Some(Span::new(first_span.begin, last_span.end))
-                }
+                (Some(first_span), Some(last_span)) => Some(first_span.to(last_span)),
                 _ => None,
             },
             (Some(first), None) => first.span(),
@@ -169,35 +192,41 @@ mod tests {
     fn test_list_span() {
         // (1 2 3)
         let args = list!(
-            Expr::Num(1.0, Some(Span::new(Loc::new(1, 1), Loc::new(1, 2)))),
-            Expr::Num(2.0, Some(Span::new(Loc::new(1, 3), Loc::new(1, 4)))),
-            Expr::Num(3.0, Some(Span::new(Loc::new(1, 5), Loc::new(1, 6))))
+            Expr::Num(1.0, Some(Span::new(Loc::new(1, 1, 0), Loc::new(1, 2, 1)))),
+            Expr::Num(2.0, Some(Span::new(Loc::new(1, 3, 2), Loc::new(1, 4, 3)))),
+            Expr::Num(3.0, Some(Span::new(Loc::new(1, 5, 4), Loc::new(1, 6, 5))))
+        );
+        assert_eq!(
+            args.span(),
+            Some(Span::new(Loc::new(1, 1, 0), Loc::new(1, 6, 5)))
         );
-        assert_eq!(args.span(), Some(Span::new(Loc::new(1, 1), Loc::new(1, 6))));
 
         // (1 2 3)
         let args = list!(
             Expr::Num(1.0, None),
-            Expr::Num(2.0, Some(Span::new(Loc::new(1, 3), Loc::new(1, 4)))),
-            Expr::Num(3.0, Some(Span::new(Loc::new(1, 5), Loc::new(1, 6))))
+            Expr::Num(2.0, Some(Span::new(Loc::new(1, 3, 2), Loc::new(1, 4, 3)))),
+            Expr::Num(3.0, Some(Span::new(Loc::new(1, 5, 4), Loc::new(1, 6, 5))))
         );
         assert_eq!(args.span(), None);
 
         // (1 2 3)
         let args = list!(
-            Expr::Num(1.0, Some(Span::new(Loc::new(1, 1), Loc::new(1, 2)))),
-            Expr::Num(2.0, Some(Span::new(Loc::new(1, 3), Loc::new(1, 4)))),
+            Expr::Num(1.0, Some(Span::new(Loc::new(1, 1, 0), Loc::new(1, 2, 1)))),
+            Expr::Num(2.0, Some(Span::new(Loc::new(1, 3, 2), Loc::new(1, 4, 3)))),
             Expr::Num(3.0, None)
         );
         assert_eq!(args.span(), None);
 
         // (1 2 3)
         let args = list!(
-            Expr::Num(1.0, Some(Span::new(Loc::new(1, 1), Loc::new(1, 2)))),
+            Expr::Num(1.0, Some(Span::new(Loc::new(1, 1, 0), Loc::new(1, 2, 1)))),
             Expr::Num(2.0, None),
-            Expr::Num(3.0, Some(Span::new(Loc::new(1, 5), Loc::new(1, 6))))
+            Expr::Num(3.0, Some(Span::new(Loc::new(1, 5, 4), Loc::new(1, 6, 5))))
+        );
+        assert_eq!(
+            args.span(),
+            Some(Span::new(Loc::new(1, 1, 0), Loc::new(1, 6, 5)))
         );
-        assert_eq!(args.span(), Some(Span::new(Loc::new(1, 1), Loc::new(1, 6))));
     }
 
     #[test]
@@ -224,4 +253,16 @@ mod tests {
             list!(0, list!(1), 2)
         );
     }
+
+    #[test]
+    fn test_drop_long_and_deep_lists() {
+        let mut long = List::Nil;
+        let mut deep = List::Nil;
+        for index in 0..100_000 {
+            long = cons(index, long);
+            deep = cons(deep, List::Nil);
+        }
+        drop(long);
+        drop(deep);
+    }
 }
@@ -0,0 +1,209 @@
+//! Syntax of numeric literals.
+//!
+//! Supports the real number part of the Scheme grammar:
+//!
+//! - radix prefixes `#b`, `#o`, `#d` and `#x`, e.g. `#xff`;
+//! - exactness prefixes `#e` and `#i`, in either order with the radix prefix;
+//! - decimals with exponents, e.g. `1.5e-3` or `.5`;
+//! - ratios, e.g. `1/3` or `#x-a/b`;
+//! - `+inf.0`, `-inf.0`, `+nan.0` and `-nan.0`.
+//!
+//! All numbers are represented as `f64`, so exactness prefixes are accepted but
+//! have no effect.
+
+use std::fmt;
+
+/// Parses a numeric literal. Returns `None` if `text` isn't a valid number.
+pub fn parse_number(text: &str) -> Option<f64> {
+    let (radix, body) = parse_prefixes(text)?;
+
+    match body.to_ascii_lowercase().as_str() {
+        "+inf.0" => return Some(f64::INFINITY),
+        "-inf.0" => return Some(f64::NEG_INFINITY),
+        "+nan.0" | "-nan.0" => return Some(f64::NAN),
+        _ => {}
+    }
+
+    let (sign, unsigned) = match body.as_bytes().first()? {
+        b'+' => (1.0, &body[1..]),
+        b'-' => (-1.0, &body[1..]),
+        _ => (1.0, body),
+    };
+
+    let value = if let Some((numerator, denominator)) = unsigned.split_once('/') {
+        parse_uinteger(numerator, radix)? / parse_uinteger(denominator, radix)?
+    } else if radix == 10 {
+        parse_decimal(unsigned)?
+    } else {
+        parse_uinteger(unsigned, radix)?
+    };
+
+    Some(sign * value)
+}
+
+/// Whether `text` is meant to be a number rather than a symbol, i.e. whether
+/// an invalid numeric literal should be reported as an error.
+pub fn looks_like_number(text: &str) -> bool {
+    let Some((radix, body)) = parse_prefixes(text) else {
+        return false; // e.g. "#c" or "#x#x1"
+    };
+
+    let body = body.strip_prefix(['+', '-']).unwrap_or(body);
+    let body = body.strip_prefix('.').unwrap_or(body);
+    body.starts_with(|ch: char| ch.is_digit(radix))
+}
+
+/// Writes `value` so that `parse_number` reads it back as the same number.
+pub fn write_number(f: &mut fmt::Formatter<'_>, value: f64) -> fmt::Result {
+    if value.is_nan() {
+        write!(f, "+nan.0")
+    } else if value == f64::INFINITY {
+        write!(f, "+inf.0")
+    } else if value == f64::NEG_INFINITY {
+        write!(f, "-inf.0")
+    } else {
+        write!(f, "{}", value)
+    }
+}
+
+/// Strips the radix and exactness prefixes, and returns the radix along with
+/// the rest of the text.
+fn parse_prefixes(text: &str) -> Option<(u32, &str)> {
+    let mut radix = None;
+    let mut has_exactness = false;
+    let mut rest = text;
+
+    while let Some(after_hash) = rest.strip_prefix('#') {
+        let mut chars = after_hash.chars();
+        match chars.next()?.to_ascii_lowercase() {
+            'b' if radix.is_none() => radix = Some(2),
+            'o' if radix.is_none() => radix = Some(8),
+            'd' if radix.is_none() => radix = Some(10),
+            'x' if radix.is_none() => radix = Some(16),
+            'e' | 'i' if !has_exactness => has_exactness = true,
+            _ => return None,
+        }
+        rest = chars.as_str();
+    }
+
+    Some((radix.unwrap_or(10), rest))
+}
+
+fn parse_uinteger(digits: &str, radix: u32) -> Option<f64> {
+    if digits.is_empty() {
+        return None;
+    }
+    digits.chars().try_fold(0.0, |value, ch| {
+        ch.to_digit(radix)
+            .map(|digit| value * radix as f64 + digit as f64)
+    })
+}
+
+fn parse_decimal(text: &str) -> Option<f64> {
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(index) => (&text[..index], Some(&text[index + 1..])),
+        None => (text, None),
+    };
+
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let is_digits = |text: &str| text.chars().all(|ch| ch.is_ascii_digit());
+    if integer.is_empty() && fraction.is_empty() || !is_digits(integer) || !is_digits(fraction) {
+        return None;
+    }
+
+    if let Some(exponent) = exponent {
+        let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        if digits.is_empty() || !is_digits(digits) {
+            return None;
+        }
+    }
+
+    // the text is known to be well-formed at this point
+    text.parse::<f64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(parse_number("0"), Some(0.0));
+        assert_eq!(parse_number("-12.5"), Some(-12.5));
+        assert_eq!(parse_number("+.5"), Some(0.5));
+        assert_eq!(parse_number("1."), Some(1.0));
+        assert_eq!(parse_number("1e3"), Some(1000.0));
+        assert_eq!(parse_number("1.5E-3"), Some(0.0015));
+        assert_eq!(parse_number("#d10"), Some(10.0));
+        assert_eq!(parse_number("#e1.5"), Some(1.5));
+
+        assert_eq!(parse_number("."), None);
+        assert_eq!(parse_number("1e"), None);
+        assert_eq!(parse_number("1.2.3"), None);
+        assert_eq!(parse_number("inf"), None);
+        assert_eq!(parse_number("1_000"), None);
+    }
+
+    #[test]
+    fn test_parse_radix() {
+        assert_eq!(parse_number("#xff"), Some(255.0));
+        assert_eq!(parse_number("#X-1A"), Some(-26.0));
+        assert_eq!(parse_number("#b101"), Some(5.0));
+        assert_eq!(parse_number("#o17"), Some(15.0));
+        assert_eq!(parse_number("#i#x10"), Some(16.0));
+        assert_eq!(parse_number("#x#e10"), Some(16.0));
+
+        assert_eq!(parse_number("#b102"), None);
+        assert_eq!(parse_number("#x1.5"), None);
+        assert_eq!(parse_number("#x#x1"), None);
+        assert_eq!(parse_number("#x"), None);
+    }
+
+    #[test]
+    fn test_parse_ratio() {
+        assert_eq!(parse_number("1/4"), Some(0.25));
+        assert_eq!(parse_number("-3/2"), Some(-1.5));
+        assert_eq!(parse_number("#xa/2"), Some(5.0));
+
+        assert_eq!(parse_number("1/"), None);
+        assert_eq!(parse_number("1.5/2"), None);
+    }
+
+    #[test]
+    fn test_parse_special_values() {
+        assert_eq!(parse_number("+inf.0"), Some(f64::INFINITY));
+        assert_eq!(parse_number("-inf.0"), Some(f64::NEG_INFINITY));
+        assert!(parse_number("+nan.0").is_some_and(f64::is_nan));
+        assert!(parse_number("-nan.0").is_some_and(f64::is_nan));
+
+        assert_eq!(parse_number("inf.0"), None);
+    }
+
+    #[test]
+    fn test_looks_like_number() {
+        assert!(looks_like_number("1x"));
+        assert!(looks_like_number("-.5.5"));
+        assert!(looks_like_number("#xfg"));
+        assert!(!looks_like_number("#xzz"));
+        assert!(!looks_like_number("+"));
+        assert!(!looks_like_number("..."));
+        assert!(!looks_like_number("-x"));
+        assert!(!looks_like_number("#define"));
+        assert!(!looks_like_number("#c1"));
+    }
+
+    #[test]
+    fn test_write_number_round_trip() {
+        struct Num(f64);
+        impl fmt::Display for Num {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write_number(f, self.0)
+            }
+        }
+
+        for value in [0.0, -1.5, 1e300, 1e-300, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(parse_number(&Num(value).to_string()), Some(value));
+        }
+        assert_eq!(Num(f64::NAN).to_string(), "+nan.0");
+    }
+}
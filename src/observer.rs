@@ -0,0 +1,317 @@
+//! Evaluation events for embedders.
+//!
+//! An `EvalObserver` installed with `Evaluator::set_observer` is told about
+//! procedure calls and returns, definitions and updates of global variables,
+//! and errors. Nothing is done for these events while no observer is installed.
+//!
+//! # Example
+//!
+//! ```
+//! use std::{cell::Cell, rc::Rc};
+//!
+//! use rusche::{eval::Evaluator, expr::Expr, lexer::tokenize, observer::EvalObserver, parser::Parser};
+//!
+//! #[derive(Default)]
+//! struct DefineCounter(Rc<Cell<usize>>);
+//!
+//! impl EvalObserver for DefineCounter {
+//!     fn on_define(&self, _name: &str, _value: &Expr) {
+//!         self.0.set(self.0.get() + 1);
+//!     }
+//! }
+//!
+//! let evaluator = Evaluator::with_prelude();
+//! let count = Rc::new(Cell::new(0));
+//! evaluator.set_observer(Some(Box::new(DefineCounter(count.clone()))));
+//!
+//! let mut parser = Parser::with_tokens(tokenize("(define x 1) (define y 2)").unwrap());
+//! while let Some(expr) = parser.parse().unwrap() {
+//!     evaluator.eval(&expr).unwrap();
+//! }
+//! assert_eq!(count.get(), 2);
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::{
+    eval::{EvalError, EvalResult},
+    expr::Expr,
+    list::List,
+    proc::Proc,
+    span::Span,
+};
+
+/// Receives evaluation events. Every method does nothing by default.
+pub trait EvalObserver {
+    /// Called when `proc` is called from `span` with `args`, which are not
+    /// evaluated yet.
+    fn on_call(&self, _proc: &Proc, _args: &List, _span: Option<Span>) {}
+
+    /// Called when the innermost call that hasn't returned yet returns, so that
+    /// calls and returns nest like parentheses. A procedure whose last
+    /// expression is a tail call returns along with the tail call.
+    fn on_return(&self, _result: &EvalResult) {}
+
+    /// Called before a global variable is defined, including redefinitions.
+    fn on_define(&self, _name: &str, _value: &Expr) {}
+
+    /// Called before a global variable is assigned with `set!`.
+    fn on_update(&self, _name: &str, _value: &Expr) {}
+
+    /// Called once for each error raised, before it propagates.
+    fn on_error(&self, _error: &EvalError) {}
+}
+
+#[derive(Default)]
+struct ObserverState {
+    /// Checked before any other work, so that nothing is done without an
+    /// observer.
+    is_installed: Cell<bool>,
+    observer: RefCell<Option<Rc<dyn EvalObserver>>>,
+    /// Number of calls reported that haven't returned yet.
+    active_calls: Cell<usize>,
+}
+
+/// Where the observer of an evaluator is installed. Shared by its contexts
+/// and its root env.
+#[derive(Clone, Default)]
+pub(crate) struct ObserverSlot(Rc<ObserverState>);
+
+impl std::fmt::Debug for ObserverSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObserverSlot")
+            .field("is_installed", &self.is_installed())
+            .finish()
+    }
+}
+
+impl ObserverSlot {
+    pub(crate) fn set(&self, observer: Option<Box<dyn EvalObserver>>) {
+        self.0.is_installed.set(observer.is_some());
+        *self.0.observer.borrow_mut() = observer.map(Rc::from);
+    }
+
+    pub(crate) fn is_installed(&self) -> bool {
+        self.0.is_installed.get()
+    }
+
+    /// The observer is cloned out, so that it may install another one.
+    fn get(&self) -> Option<Rc<dyn EvalObserver>> {
+        if !self.is_installed() {
+            return None;
+        }
+        self.0.observer.borrow().clone()
+    }
+
+    pub(crate) fn active_calls(&self) -> usize {
+        self.0.active_calls.get()
+    }
+
+    /// Must be followed by `notify_returns`.
+    pub(crate) fn notify_call(&self, proc: &Proc, args: &List, span: Option<Span>) {
+        if let Some(observer) = self.get() {
+            self.0.active_calls.set(self.0.active_calls.get() + 1);
+            observer.on_call(proc, args, span);
+        }
+    }
+
+    /// Reports `result` as returned by every call reported after
+    /// `active_calls` of them were active.
+    pub(crate) fn notify_returns(&self, active_calls: usize, result: &EvalResult) {
+        while self.0.active_calls.get() > active_calls {
+            self.0.active_calls.set(self.0.active_calls.get() - 1);
+            if let Some(observer) = self.get() {
+                observer.on_return(result);
+            }
+        }
+    }
+
+    pub(crate) fn notify_define(&self, name: &str, value: &Expr) {
+        if let Some(observer) = self.get() {
+            observer.on_define(name, value);
+        }
+    }
+
+    pub(crate) fn notify_update(&self, name: &str, value: &Expr) {
+        if let Some(observer) = self.get() {
+            observer.on_update(name, value);
+        }
+    }
+
+    /// Reports `error` unless it already was, e.g. by an inner expression it
+    /// propagated from.
+    pub(crate) fn report_error(&self, error: &mut EvalError) {
+        if error.is_reported {
+            return;
+        }
+        if let Some(observer) = self.get() {
+            error.is_reported = true;
+            observer.on_error(error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, lexer::tokenize, parser::Parser};
+
+    /// Procedures print with a hash, which isn't stable.
+    fn show(value: &Expr) -> String {
+        match value {
+            Expr::Proc(proc, _) => format!("<{}>", proc.name()),
+            value => value.to_string(),
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl EvalObserver for Recorder {
+        fn on_call(&self, proc: &Proc, args: &List, _span: Option<Span>) {
+            self.0
+                .borrow_mut()
+                .push(format!("call {} {}", proc.name(), args));
+        }
+
+        fn on_return(&self, result: &EvalResult) {
+            let event = match result {
+                Ok(value) => format!("return {}", show(value)),
+                Err(_) => "return error".to_string(),
+            };
+            self.0.borrow_mut().push(event);
+        }
+
+        fn on_define(&self, name: &str, value: &Expr) {
+            self.0
+                .borrow_mut()
+                .push(format!("define {} {}", name, show(value)));
+        }
+
+        fn on_update(&self, name: &str, value: &Expr) {
+            self.0
+                .borrow_mut()
+                .push(format!("update {} {}", name, show(value)));
+        }
+
+        fn on_error(&self, error: &EvalError) {
+            self.0
+                .borrow_mut()
+                .push(format!("error {}", error.message()));
+        }
+    }
+
+    fn observe(evaluator: &Evaluator, text: &str) -> Vec<String> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        evaluator.set_observer(Some(Box::new(Recorder(events.clone()))));
+
+        let mut parser = Parser::with_tokens(tokenize(text).unwrap());
+        while let Some(expr) = parser.parse().unwrap() {
+            let _ = evaluator.eval(&expr);
+        }
+        evaluator.set_observer(None);
+
+        let events = events.borrow().clone();
+        events
+    }
+
+    #[test]
+    fn test_calls_and_returns() {
+        let evaluator = Evaluator::with_builtin();
+        let events = observe(&evaluator, "(if (eq? 1 1) 'yes 'no)");
+        assert_eq!(
+            events,
+            vec![
+                "call if ((eq? 1 1) (quote yes) (quote no))",
+                "call eq? (1 1)",
+                "return 1",
+                "return yes",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let evaluator = Evaluator::with_builtin();
+        let events = observe(&evaluator, "(define (f) (g)) (define (g) 1) (f)");
+        assert_eq!(
+            events,
+            vec![
+                "call define ((f) (g))",
+                "define f <f>",
+                "return ()",
+                "call define ((g) 1)",
+                "define g <g>",
+                "return ()",
+                "call f ()",
+                "call g ()",
+                "return 1",
+                "return 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_globals() {
+        let evaluator = Evaluator::with_prelude();
+        let events = observe(
+            &evaluator,
+            "(define x 1) (set! x 2) (define (f y) (set! y 3) (set! x y)) (f 0)",
+        )
+        .into_iter()
+        .filter(|event| event.starts_with("define") || event.starts_with("update"))
+        .collect::<Vec<_>>();
+        // local variables aren't reported
+        assert_eq!(
+            events,
+            vec!["define x 1", "update x 2", "define f <f>", "update x 3"]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let evaluator = Evaluator::with_prelude();
+        let events = observe(&evaluator, "(define (f x) (car x)) (f 1) undefined")
+            .into_iter()
+            .filter(|event| event.starts_with("error"))
+            .collect::<Vec<_>>();
+        // each error once, although it propagates through `f`
+        assert_eq!(
+            events,
+            vec![
+                "error car: expected a non-empty list, but got a number.",
+                "error Undefined symbol: `undefined`",
+            ]
+        );
+
+        // nothing is reported once the observer is removed
+        let events = Rc::new(RefCell::new(Vec::new()));
+        evaluator.set_observer(Some(Box::new(Recorder(events.clone()))));
+        evaluator.set_observer(None);
+        let _ = evaluator.eval(&Expr::from(vec![crate::expr::intern("f"), Expr::from(1)]));
+        assert!(events.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_errors_outside_evaluator_eval() {
+        let evaluator = Evaluator::with_prelude();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        evaluator.set_observer(Some(Box::new(Recorder(events.clone()))));
+
+        // evaluated in a derived context, outside of any call
+        let context = crate::eval::EvalContext::derive_from(evaluator.context());
+        let expr = Parser::with_tokens(tokenize("(list 1 undefined)").unwrap())
+            .parse()
+            .unwrap()
+            .unwrap();
+        let _ = crate::eval::eval(&expr, &context);
+
+        let events = events.borrow();
+        let errors = events
+            .iter()
+            .filter(|event| event.starts_with("error"))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec!["error Undefined symbol: `undefined`"]);
+    }
+}
@@ -3,12 +3,60 @@ use crate::list::{cons, List};
 use crate::macros::list;
 use crate::span::Span;
 use crate::token::Token;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 
 #[derive(Debug, PartialEq)]
 pub enum ParseError {
+    /// The expression is not complete yet. More tokens may complete it.
     NeedMoreToken,
-    UnexpectedToken(Token),
+    /// A list that is never closed. The span is the one of its opening `(`.
+    UnclosedList(Span),
+    /// A quote, a datum comment or a datum label that is not followed by an
+    /// expression.
+    LoneQuote(Token),
+    /// A `)` that doesn't close any list.
+    DanglingCloseParen(Span),
+    /// A `#n#` without a preceding `#n=` in the same top-level expression.
+    UndefinedLabel(usize, Span),
+    /// A `#n#` inside the expression labeled `n`. Lists are immutable, so
+    /// cyclic lists can't be built.
+    CyclicLabel(usize, Span),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParseError::NeedMoreToken => None,
+            ParseError::UnclosedList(span) => Some(*span),
+            ParseError::LoneQuote(token) => Some(token.span()),
+            ParseError::DanglingCloseParen(span) => Some(*span),
+            ParseError::UndefinedLabel(_, span) | ParseError::CyclicLabel(_, span) => Some(*span),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::NeedMoreToken => write!(f, "incomplete expression"),
+            ParseError::UnclosedList(_) => write!(f, "unclosed list, expected `)`"),
+            ParseError::LoneQuote(token) => {
+                write!(f, "expected an expression after `{}`", token)
+            }
+            ParseError::DanglingCloseParen(_) => write!(f, "unexpected `)` without matching `(`"),
+            ParseError::UndefinedLabel(label, _) => {
+                write!(f, "undefined datum label `#{}#`", label)
+            }
+            ParseError::CyclicLabel(label, _) => {
+                write!(
+                    f,
+                    "datum label `#{}#` refers to a list that contains it",
+                    label
+                )
+            }
+        }
+    }
 }
 
 type ParseResult = Result<Option<Expr>, ParseError>;
@@ -21,6 +69,15 @@ struct ParseContext {
 pub struct Parser {
     tokens: VecDeque<Token>,
     contexts: Vec<ParseContext>,
+    /// Expressions labeled with `#n=` in the current top-level expression.
+    labels: HashMap<usize, Expr>,
+    is_recovering: bool,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Parser {
@@ -28,6 +85,8 @@ impl Parser {
         Self {
             tokens: VecDeque::new(),
             contexts: Vec::new(),
+            labels: HashMap::new(),
+            is_recovering: false,
         }
     }
 
@@ -41,9 +100,16 @@ impl Parser {
         !self.contexts.is_empty()
     }
 
+    fn is_in_list(&self) -> bool {
+        self.contexts
+            .iter()
+            .any(|context| matches!(context.token, Some(Token::OpenParen(_))))
+    }
+
     pub fn reset(&mut self) {
         self.tokens.clear();
         self.contexts.clear();
+        self.labels.clear();
     }
 
     pub fn add_tokens<Iter>(&mut self, tokens: Iter)
@@ -54,7 +120,7 @@ impl Parser {
     }
 
     pub fn parse(&mut self) -> ParseResult {
-        loop {
+        'parse: loop {
             let Some(token) = self.get_token() else {
                 return if self.is_parsing() {
                     Err(ParseError::NeedMoreToken)
@@ -63,16 +129,24 @@ impl Parser {
                 };
             };
 
+            if self.is_recovering && self.is_in_list() && is_top_level_form_start(&token) {
+                self.tokens.push_front(token);
+                return Err(ParseError::NeedMoreToken); // the current form is never closed
+            }
+
             let mut expr = match token {
                 Token::OpenParen(_)
                 | Token::Quote(_)
                 | Token::Quasiquote(_)
                 | Token::Unquote(_)
-                | Token::UnquoteSplicing(_) => {
+                | Token::UnquoteSplicing(_)
+                | Token::DatumComment(_)
+                | Token::DatumLabel(..) => {
                     self.begin_list(token);
                     continue;
                 }
                 Token::CloseParen(_) => self.end_list(token)?,
+                Token::DatumRef(label, span) => self.resolve_label(label, span)?,
                 Token::Sym(name, span) => Expr::Sym(name, Some(span)),
                 Token::Str(text, span) => Expr::Str(text, Some(span)),
                 Token::Num(value, span) => Expr::Num(value, Some(span)),
@@ -81,8 +155,21 @@ impl Parser {
             loop {
                 if let Some(context) = self.contexts.last_mut() {
                     if let Some(quote_name) = get_quote_name(context.token.as_ref()) {
+                        let span = match (&context.token, expr.span()) {
+                            (Some(token), Some(end)) => Some(token.span().to(end)),
+                            _ => None,
+                        };
                         self.contexts.pop();
-                        expr = list!(intern(quote_name), expr).into();
+                        expr = Expr::List(list!(intern(quote_name), expr), span);
+                        continue;
+                    }
+                    if let Some(Token::DatumComment(_)) = context.token {
+                        self.contexts.pop();
+                        continue 'parse; // discard the commented out expression
+                    }
+                    if let Some(Token::DatumLabel(label, _)) = context.token {
+                        self.contexts.pop();
+                        self.labels.insert(label, expr.clone());
                         continue;
                     }
                     if context.car.is_none() {
@@ -95,12 +182,96 @@ impl Parser {
                     }
                     break;
                 } else {
+                    self.labels.clear(); // labels are local to a top-level expression
                     return Ok(Some(expr));
                 }
             }
         }
     }
 
+    /// Parses all the remaining tokens in recovery mode, collecting every error
+    /// instead of stopping at the first one.
+    ///
+    /// After an error, the partially parsed expression is discarded and parsing
+    /// resumes at the next top-level form, i.e. the next `(` at the first column.
+    /// Such a `(` also ends an unclosed list, so that a missing `)` doesn't
+    /// swallow the rest of the file.
+    pub fn parse_all(&mut self) -> (Vec<Expr>, Vec<ParseError>) {
+        let mut exprs = Vec::new();
+        let mut errors = Vec::new();
+
+        self.is_recovering = true;
+        loop {
+            match self.parse() {
+                Ok(Some(expr)) => exprs.push(expr),
+                Ok(None) => break,
+                Err(ParseError::NeedMoreToken) => {
+                    if let Err(error) = self.finish() {
+                        errors.push(error);
+                    }
+                }
+                Err(error) => {
+                    errors.push(error);
+                    self.synchronize();
+                }
+            }
+        }
+        self.is_recovering = false;
+
+        (exprs, errors)
+    }
+
+    /// Signals the end of the input. Returns an error pointing at the innermost
+    /// `(` or quote if an expression was left incomplete, and discards it.
+    pub fn finish(&mut self) -> Result<(), ParseError> {
+        let open_token = self
+            .contexts
+            .iter()
+            .rev()
+            .find_map(|context| context.token.as_ref());
+
+        let result = match open_token {
+            Some(token) if is_prefix(Some(token)) => Err(ParseError::LoneQuote(token.clone())),
+            Some(token) => Err(ParseError::UnclosedList(token.span())),
+            None => Ok(()),
+        };
+
+        self.contexts.clear();
+        self.labels.clear();
+        result
+    }
+
+    fn synchronize(&mut self) {
+        if self.contexts.is_empty() {
+            return; // nothing to discard, e.g. a dangling ')' at the top level
+        }
+        self.contexts.clear();
+        self.labels.clear();
+        while self
+            .tokens
+            .front()
+            .is_some_and(|token| !is_top_level_form_start(token))
+        {
+            self.tokens.pop_front();
+        }
+    }
+
+    /// Returns a copy of the expression labeled `label`. Lists are values, so
+    /// the shared structure is not preserved.
+    fn resolve_label(&self, label: usize, span: Span) -> Result<Expr, ParseError> {
+        if let Some(expr) = self.labels.get(&label) {
+            return Ok(expr.clone());
+        }
+        let is_open = self.contexts.iter().any(
+            |context| matches!(context.token, Some(Token::DatumLabel(open, _)) if open == label),
+        );
+        if is_open {
+            Err(ParseError::CyclicLabel(label, span))
+        } else {
+            Err(ParseError::UndefinedLabel(label, span))
+        }
+    }
+
     fn get_token(&mut self) -> Option<Token> {
         self.tokens.pop_front()
     }
@@ -115,24 +286,33 @@ impl Parser {
     fn end_list(&mut self, token: Token) -> Result<Expr, ParseError> {
         let mut list = List::Nil;
         while let Some(context) = self.contexts.pop() {
-            if get_quote_name(context.token.as_ref()).is_some() {
-                break;
+            if is_prefix(context.token.as_ref()) {
+                let prefix_token = context.token.unwrap();
+                return Err(ParseError::LoneQuote(prefix_token)); // e.g. "(a ')"
             }
             if let Some(car) = context.car {
                 list = cons(car, list);
             }
             if let Some(begin_token) = context.token {
-                let expr_span = Span {
-                    begin: begin_token.span().begin,
-                    end: token.span().end,
-                };
+                let expr_span = begin_token.span().to(token.span());
                 return Ok(Expr::List(list, Some(expr_span)));
             }
         }
-        Err(ParseError::UnexpectedToken(token)) // dangling ')'
+        Err(ParseError::DanglingCloseParen(token.span()))
     }
 }
 
+fn is_top_level_form_start(token: &Token) -> bool {
+    matches!(token, Token::OpenParen(span) if span.begin.column == 1)
+}
+
+/// Whether the token applies to the expression following it, i.e. a quote, a
+/// datum comment or a datum label.
+fn is_prefix(token: Option<&Token>) -> bool {
+    get_quote_name(token).is_some()
+        || matches!(token, Some(Token::DatumComment(_) | Token::DatumLabel(..)))
+}
+
 fn get_quote_name(token: Option<&Token>) -> Option<&'static str> {
     use crate::builtin::quote::{QUASIQUOTE, QUOTE, UNQUOTE, UNQUOTE_SPLICING};
     match token {
@@ -151,10 +331,10 @@ mod tests {
 
     macro_rules! tok {
         ($token_case:ident) => {
-            Token::$token_case(Loc::new(1, 1))
+            Token::$token_case(Span::new(Loc::new(1, 1, 0), Loc::new(1, 2, 1)))
         };
         ($token_case:ident($value:expr)) => {
-            Token::$token_case($value, Span::new(Loc::new(1, 1), Loc::new(1, 2)))
+            Token::$token_case($value, Span::new(Loc::new(1, 1, 0), Loc::new(1, 2, 1)))
         };
     }
 
@@ -250,4 +430,158 @@ mod tests {
         let expected_expr = list!(intern("unquote-splicing"), 1).into();
         assert_eq!(parsed_expr, expected_expr);
     }
+
+    #[test]
+    fn test_quote_spans() {
+        use crate::lexer::tokenize;
+
+        let text = "'(1 2) `(a ,b) ''c";
+        let (exprs, errors) = Parser::with_tokens(tokenize(text).unwrap()).parse_all();
+        assert!(errors.is_empty());
+
+        let text_of = |expr: &Expr| {
+            let span = expr.span().unwrap();
+            &text[span.begin.offset..span.end.offset]
+        };
+        // from the quote to the end of the quoted expression
+        assert_eq!(
+            exprs.iter().map(text_of).collect::<Vec<_>>(),
+            vec!["'(1 2)", "`(a ,b)", "''c"]
+        );
+
+        let Expr::List(quasiquoted, _) = &exprs[1] else {
+            panic!("expected a list");
+        };
+        let Some(Expr::List(items, _)) = quasiquoted.iter().nth(1) else {
+            panic!("expected a list");
+        };
+        assert_eq!(text_of(items.iter().nth(1).unwrap()), ",b");
+
+        let Expr::List(quoted, _) = &exprs[2] else {
+            panic!("expected a list");
+        };
+        assert_eq!(text_of(quoted.iter().nth(1).unwrap()), "'c");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        use crate::lexer::tokenize;
+
+        let parse = |text| Parser::with_tokens(tokenize(text).unwrap()).parse();
+        let span = |column, offset| {
+            Span::new(
+                Loc::new(1, column, offset),
+                Loc::new(1, column + 1, offset + 1),
+            )
+        };
+
+        assert_eq!(parse("(a))"), Ok(Some(list!(a).into())));
+        assert_eq!(parse(")"), Err(ParseError::DanglingCloseParen(span(1, 0))));
+        assert_eq!(
+            parse("(a ')"),
+            Err(ParseError::LoneQuote(Token::Quote(span(4, 3))))
+        );
+        assert_eq!(parse("(a (b)"), Err(ParseError::NeedMoreToken));
+    }
+
+    #[test]
+    fn test_parser_finish() {
+        use crate::lexer::tokenize;
+
+        let mut parser = Parser::with_tokens(tokenize("(a\n  (b c").unwrap());
+        assert_eq!(parser.parse(), Err(ParseError::NeedMoreToken));
+        assert_eq!(
+            parser.finish(),
+            Err(ParseError::UnclosedList(Span::new(
+                Loc::new(2, 3, 5),
+                Loc::new(2, 4, 6)
+            )))
+        );
+        assert!(!parser.is_parsing());
+        assert_eq!(parser.finish(), Ok(()));
+
+        parser.add_tokens(tokenize("(a ,").unwrap());
+        assert_eq!(parser.parse(), Err(ParseError::NeedMoreToken));
+        assert!(matches!(
+            parser.finish(),
+            Err(ParseError::LoneQuote(Token::Unquote(_)))
+        ));
+    }
+
+    #[test]
+    fn test_parse_all_recovers() {
+        use crate::lexer::tokenize;
+
+        let tokens = tokenize("(a))\n(b '\n(c (d)\n(e)\n'").unwrap();
+        let (exprs, errors) = Parser::with_tokens(tokens).parse_all();
+
+        assert_eq!(exprs, vec![list!(a).into(), list!(e).into()]);
+        assert_eq!(errors.len(), 4);
+        assert!(
+            matches!(errors[0], ParseError::DanglingCloseParen(span) if span.begin.column == 4)
+        );
+        assert!(matches!(&errors[1], ParseError::LoneQuote(token) if token.span().begin.line == 2));
+        assert!(matches!(errors[2], ParseError::UnclosedList(span) if span.begin.line == 3));
+        assert!(matches!(&errors[3], ParseError::LoneQuote(token) if token.span().begin.line == 5));
+    }
+
+    #[test]
+    fn test_datum_comment() {
+        use crate::lexer::tokenize;
+
+        let parse_all = |text| Parser::with_tokens(tokenize(text).unwrap()).parse_all();
+
+        let (exprs, errors) = parse_all("#;(a b) (c #;d x #;'(f) #;#;g h) #;\n(i)");
+        assert_eq!(exprs, vec![list!(c, intern("x")).into()]);
+        assert!(errors.is_empty());
+
+        let (exprs, errors) = parse_all("(a #;)");
+        assert!(exprs.is_empty());
+        assert!(matches!(
+            &errors[..],
+            [ParseError::LoneQuote(Token::DatumComment(_))]
+        ));
+    }
+
+    #[test]
+    fn test_datum_labels() {
+        use crate::lexer::tokenize;
+
+        let parse_all = |text| Parser::with_tokens(tokenize(text).unwrap()).parse_all();
+
+        let (exprs, errors) = parse_all("(#0=(a b) #0# '#1=c #1#) (#0=d #0#)");
+        assert!(errors.is_empty());
+        assert_eq!(
+            exprs,
+            vec![
+                list!(
+                    list!(a, intern("b")),
+                    list!(a, intern("b")),
+                    list!(quote, intern("c")),
+                    intern("c")
+                )
+                .into(),
+                list!(d, intern("d")).into(),
+            ]
+        );
+
+        let (exprs, errors) = parse_all(
+            "(#0=(a #0#))
+(b #1#)
+(c #2=)",
+        );
+        assert!(exprs.is_empty());
+        assert!(matches!(
+            &errors[..],
+            [
+                ParseError::CyclicLabel(0, _),
+                ParseError::UndefinedLabel(1, _),
+                ParseError::LoneQuote(Token::DatumLabel(2, _)),
+            ]
+        ));
+        assert_eq!(
+            errors[0].to_string(),
+            "datum label `#0#` refers to a list that contains it"
+        );
+    }
 }
@@ -1,7 +1,8 @@
 use crate::{
     eval::{eval, EvalContext},
-    lexer::tokenize,
-    parser::{ParseError, Parser},
+    lexer::tokenize_source,
+    parser::Parser,
+    source::SourceMap,
 };
 
 const PRELUDE_SYMBOLS: [&str; 4] = [
@@ -152,7 +153,9 @@ pub fn load_prelude(context: &EvalContext) {
 }
 
 fn eval_src(src: &str, context: &EvalContext) {
-    let tokens = tokenize(src).expect(&format!("Prelude tokniization failed: {}", src));
+    let source = SourceMap::add("<prelude>", src);
+    let tokens =
+        tokenize_source(source).unwrap_or_else(|_| panic!("Prelude tokniization failed: {}", src));
 
     let mut parser = Parser::with_tokens(tokens);
 
@@ -162,16 +165,11 @@ fn eval_src(src: &str, context: &EvalContext) {
                 break; // we're done!
             }
             Ok(Some(expr)) => {
-                let _ = eval(&expr, context).expect(&format!("Prelude evaluation failed: {}", src));
+                let _ = eval(&expr, context)
+                    .unwrap_or_else(|_| panic!("Prelude evaluation failed: {}", src));
             }
-            Err(ParseError::NeedMoreToken) => {
-                panic!("Prelude parse failure - incomplete expression: {}", src);
-            }
-            Err(ParseError::UnexpectedToken(token)) => {
-                panic!(
-                    "Prelude parse failure - unexpected token ({}): {}",
-                    token, src
-                );
+            Err(error) => {
+                panic!("Prelude parse failure - {}: {}", error, src);
             }
         }
     }
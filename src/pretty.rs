@@ -0,0 +1,343 @@
+//! Pretty printing of expressions.
+//!
+//! Lists that fit in the remaining width are printed on a single line, like
+//! `Display` does. Otherwise they are broken into lines and indented the same
+//! way the formatter indents code, see `format`. Lists of atoms fill each line
+//! instead, so that long data lists don't take one line per item.
+//!
+//! # Example
+//!
+//! ```
+//! use rusche::expr::Expr;
+//! use rusche::pretty::{pretty_print, PrettyOptions};
+//!
+//! let expr = Expr::from((1..=6).map(Expr::from).collect::<Vec<_>>());
+//!
+//! let options = PrettyOptions { width: 8, ..Default::default() };
+//! assert_eq!(pretty_print(&expr, &options), "(1 2 3 4\n 5 6)");
+//!
+//! let options = PrettyOptions { max_length: Some(3), ..Default::default() };
+//! assert_eq!(pretty_print(&expr, &options), "(1 2 3 ...)");
+//! ```
+
+use crate::expr::Expr;
+use crate::format::distinguished_args;
+
+const ELLIPSIS: &str = "...";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrettyOptions {
+    /// Target line width. Lines may still exceed it, e.g. for long atoms.
+    pub width: usize,
+    /// Number of columns the bodies of forms like `define` are indented by.
+    pub indent: usize,
+    /// Lists nested deeper than this are printed as `...`.
+    pub max_depth: Option<usize>,
+    /// Items of a list after this many are printed as a single `...`.
+    pub max_length: Option<usize>,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            indent: 2,
+            max_depth: None,
+            max_length: None,
+        }
+    }
+}
+
+/// Pretty prints `expr`. The result doesn't end with a newline.
+pub fn pretty_print(expr: &Expr, options: &PrettyOptions) -> String {
+    let doc = Doc::new(expr, 0, options);
+    let mut printer = Printer {
+        options,
+        text: String::new(),
+        column: 0,
+    };
+    printer.write_doc(&doc);
+    printer.text
+}
+
+/// An expression prepared for printing, after elision.
+enum Doc {
+    Atom {
+        text: String,
+        is_symbol: bool,
+    },
+    List {
+        items: Vec<Doc>,
+        /// Length of the list when printed on a single line.
+        flat_len: usize,
+    },
+}
+
+impl Doc {
+    fn new(expr: &Expr, depth: usize, options: &PrettyOptions) -> Self {
+        let list = match expr {
+            Expr::List(list, _) if !list.is_nil() => list,
+            _ => {
+                return Doc::Atom {
+                    text: expr.to_string(),
+                    is_symbol: matches!(expr, Expr::Sym(..)),
+                }
+            }
+        };
+
+        if options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            return Doc::ellipsis();
+        }
+
+        let max_length = options.max_length.unwrap_or(usize::MAX);
+        let mut items = list
+            .iter()
+            .take(max_length)
+            .map(|expr| Doc::new(expr, depth + 1, options))
+            .collect::<Vec<_>>();
+        if list.len() > max_length {
+            items.push(Doc::ellipsis());
+        }
+
+        let flat_len = items.iter().map(Doc::flat_len).sum::<usize>() + items.len() + 1;
+        Doc::List { items, flat_len }
+    }
+
+    fn ellipsis() -> Self {
+        Doc::Atom {
+            text: ELLIPSIS.to_string(),
+            is_symbol: false,
+        }
+    }
+
+    fn flat_len(&self) -> usize {
+        match self {
+            Doc::Atom { text, .. } => text.chars().count(),
+            Doc::List { flat_len, .. } => *flat_len,
+        }
+    }
+
+    fn symbol(&self) -> Option<&str> {
+        match self {
+            Doc::Atom {
+                text,
+                is_symbol: true,
+            } => Some(text),
+            _ => None,
+        }
+    }
+}
+
+struct Printer<'a> {
+    options: &'a PrettyOptions,
+    text: String,
+    column: usize,
+}
+
+impl Printer<'_> {
+    fn write(&mut self, text: &str) {
+        self.text.push_str(text);
+        match text.rfind('\n') {
+            Some(index) => self.column = text[index + 1..].chars().count(),
+            None => self.column += text.chars().count(),
+        }
+    }
+
+    fn break_line(&mut self, indent: usize) {
+        self.text.push('\n');
+        self.text.push_str(&" ".repeat(indent));
+        self.column = indent;
+    }
+
+    fn fits(&self, doc: &Doc) -> bool {
+        self.column + doc.flat_len() <= self.options.width
+    }
+
+    fn write_flat(&mut self, doc: &Doc) {
+        match doc {
+            Doc::Atom { text, .. } => self.write(text),
+            Doc::List { items, .. } => {
+                self.write("(");
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        self.write(" ");
+                    }
+                    self.write_flat(item);
+                }
+                self.write(")");
+            }
+        }
+    }
+
+    fn write_doc(&mut self, doc: &Doc) {
+        let items = match doc {
+            Doc::List { items, .. } if !self.fits(doc) => items,
+            _ => return self.write_flat(doc),
+        };
+
+        let base = self.column;
+        self.write("(");
+
+        if items.iter().all(|item| matches!(item, Doc::Atom { .. })) {
+            return self.write_filled(items, base + 1);
+        }
+
+        // number of items on the first line, and the indentation of the others
+        let is_named_let = items.get(1).and_then(Doc::symbol).is_some();
+        let (inline_count, indent) = match items[0].symbol() {
+            Some(name) => match distinguished_args(name, is_named_let) {
+                Some(count) => (count + 1, base + self.options.indent),
+                None => {
+                    let align = base + name.chars().count() + 2;
+                    match items.get(1) {
+                        // the first argument doesn't fit next to the head
+                        Some(arg @ Doc::List { .. })
+                            if align + arg.flat_len() > self.options.width =>
+                        {
+                            (1, base + self.options.indent)
+                        }
+                        _ => (2, align),
+                    }
+                }
+            },
+            None => (1, base + 1),
+        };
+
+        for (index, item) in items.iter().enumerate() {
+            if index == 0 {
+                // nothing to separate
+            } else if index < inline_count {
+                self.write(" ");
+            } else {
+                self.break_line(indent);
+            }
+            self.write_doc(item);
+        }
+        self.write(")");
+    }
+
+    /// Writes atoms, filling each line up to the width.
+    fn write_filled(&mut self, items: &[Doc], indent: usize) {
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 {
+                // leave room for the closing paren after the last item
+                let reserved = if index + 1 == items.len() { 2 } else { 1 };
+                if self.column + item.flat_len() + reserved > self.options.width {
+                    self.break_line(indent);
+                } else {
+                    self.write(" ");
+                }
+            }
+            self.write_flat(item);
+        }
+        self.write(")");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cst::Cst;
+
+    fn pretty(text: &str, width: usize) -> String {
+        let expr = &Cst::parse(text).unwrap().to_exprs()[0];
+        let options = PrettyOptions {
+            width,
+            ..Default::default()
+        };
+        pretty_print(expr, &options)
+    }
+
+    #[test]
+    fn test_flat() {
+        assert_eq!(pretty("(a (b c) \"d\" ())", 80), "(a (b c) \"d\" ())");
+        assert_eq!(pretty("x", 0), "x");
+        assert_eq!(pretty("()", 0), "()");
+    }
+
+    #[test]
+    fn test_special_forms() {
+        let text = "(define (fact n) (if (= n 0) 1 (* n (fact (- n 1)))))";
+        assert_eq!(
+            pretty(text, 30),
+            "\
+(define (fact n)
+  (if (= n 0)
+      1
+      (* n (fact (- n 1)))))"
+        );
+
+        let text = "(cond ((< n 0) \"negative\") (else \"positive\"))";
+        assert_eq!(
+            pretty(text, 30),
+            "\
+(cond
+  ((< n 0) \"negative\")
+  (else \"positive\"))"
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        let text = "((alice 30 \"engineer\") (bob 25 \"designer\") (carol 35 \"manager\"))";
+        assert_eq!(
+            pretty(text, 30),
+            "\
+((alice 30 \"engineer\")
+ (bob 25 \"designer\")
+ (carol 35 \"manager\"))"
+        );
+
+        assert_eq!(
+            pretty("(10 20 30 40 50 60 70 80 90)", 14),
+            "\
+(10 20 30 40
+ 50 60 70 80
+ 90)"
+        );
+    }
+
+    #[test]
+    fn test_elision() {
+        let expr = &Cst::parse("(a (b (c (d))) 1 2 3 4)").unwrap().to_exprs()[0];
+
+        let options = PrettyOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(pretty_print(expr, &options), "(a (b ...) 1 2 3 4)");
+
+        let options = PrettyOptions {
+            max_length: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(pretty_print(expr, &options), "(a (b (c (d))) 1 ...)");
+
+        let options = PrettyOptions {
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(pretty_print(expr, &options), "...");
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let text = format!("{}x{}", "(a ".repeat(200), ")".repeat(200));
+        let printed = pretty(&text, 40);
+        assert!(printed.lines().count() >= 200);
+        assert_eq!(
+            printed.split_whitespace().collect::<String>(),
+            text.replace(' ', "")
+        );
+
+        let expr = &Cst::parse(&text).unwrap().to_exprs()[0];
+        let options = PrettyOptions {
+            max_depth: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(pretty_print(expr, &options), "(a (a (a ...)))");
+    }
+}
@@ -1,35 +1,63 @@
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::rc::Rc;
 
-use crate::eval::{eval, eval_tail, EvalContext, EvalError, EvalResult};
-use crate::expr::NIL;
-use crate::list::List;
+use crate::builtin::quote::QUOTE;
+use crate::eval::{eval, eval_tail, Arity, EvalContext, EvalError, EvalErrorKind, EvalResult};
+use crate::expr::{intern, Expr, NIL};
+use crate::list::{cons, List};
+use crate::macros::list;
+use crate::span::Span;
 
 pub type NativeFunc = fn(proc_name: &str, args: &List, context: &EvalContext) -> EvalResult;
 
 #[derive(Clone, Debug)]
 pub enum Proc {
     Closure {
-        name: Option<String>,
+        name: Option<Rc<str>>,
         formal_args: Vec<String>,
         body: Box<List>,
         outer_context: EvalContext,
     },
     Macro {
-        name: Option<String>,
+        name: Option<Rc<str>>,
         formal_args: Vec<String>,
         body: Box<List>,
     },
     Native {
-        name: String,
+        name: Rc<str>,
         func: NativeFunc,
+        /// Whether the procedure gets its arguments as written, like `if` or
+        /// `define`, rather than evaluating each of them once.
+        is_special_form: bool,
     },
 }
 
 impl Proc {
     pub fn invoke(&self, args: &List, context: &EvalContext) -> EvalResult {
-        context.push_call(self);
-        let result = match self {
+        self.invoke_at(args, context, None)
+    }
+
+    /// Invokes the procedure, recording the call made from `span` in the call stack.
+    pub(crate) fn invoke_at(
+        &self,
+        args: &List,
+        context: &EvalContext,
+        span: Option<Span>,
+    ) -> EvalResult {
+        context.push_call(self, span);
+        context.profiler().enter(self, span);
+        let traced_calls = context.tracer().active_calls();
+        let is_traced = context.tracer().is_traced(self);
+        // all observer work is skipped unless one is installed
+        let observed_calls = context.observer().is_installed().then(|| {
+            let observed_calls = context.observer().active_calls();
+            context.observer().notify_call(self, args, span);
+            observed_calls
+        });
+        let limit = context
+            .limits()
+            .check_call_depth(context.call_depth(), span);
+        let mut result = limit.and_then(|()| match self {
             Proc::Closure {
                 name,
                 formal_args,
@@ -42,18 +70,96 @@ impl Proc {
                 outer_context,
                 args,
                 context,
+                is_traced,
             ),
             Proc::Macro {
                 name,
                 formal_args,
                 body,
-            } => eval_macro(name.as_deref(), formal_args, body, args, context),
-            Proc::Native { name, func } => func(name, args, context),
-        };
+            } => {
+                context.debugger().enter_proc(self.name(), context);
+                if is_traced {
+                    context.tracer().enter(self.name(), args.iter());
+                }
+                eval_macro(name.as_deref(), formal_args, body, args, context)
+            }
+            Proc::Native {
+                name,
+                func,
+                is_special_form,
+            } => {
+                context.debugger().enter_proc(name, context);
+                if is_traced && !is_special_form {
+                    invoke_traced_native(name, *func, args, context)
+                } else {
+                    func(name, args, context)
+                }
+            }
+        });
+        if let Err(error) = &mut result {
+            if error.backtrace.is_none() {
+                error.backtrace = Some(Box::new(context.capture_backtrace()));
+            }
+            context.observer().report_error(error);
+        }
+        context.profiler().leave();
         context.pop_call();
+        // calls ending with a tail call return when the tail call does
+        if !matches!(result, Ok(Expr::TailCall { .. })) {
+            context.tracer().leave_calls(traced_calls, &result);
+            if let Some(observed_calls) = observed_calls {
+                context.observer().notify_returns(observed_calls, &result);
+            }
+        }
         result
     }
 
+    /// Number of arguments the procedure accepts. Native procedures check their
+    /// arguments themselves, so their arity is unknown.
+    pub fn arity(&self) -> Option<Arity> {
+        match self {
+            Proc::Closure { formal_args, .. } | Proc::Macro { formal_args, .. } => {
+                Some(arity_of(formal_args))
+            }
+            Proc::Native { .. } => None,
+        }
+    }
+
+    /// Span of the body of a closure or a macro, which locates its definition
+    /// in the source code. Native procedures have none.
+    pub fn definition_span(&self) -> Option<Span> {
+        match self {
+            Proc::Closure { body, .. } | Proc::Macro { body, .. } => body.span(),
+            Proc::Native { .. } => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Proc::Closure { name, .. } | Proc::Macro { name, .. } => {
+                name.as_deref().unwrap_or(self.anonymous_name())
+            }
+            Proc::Native { name, .. } => name,
+        }
+    }
+
+    /// Name of the procedure without copying it, or `None` if it is anonymous.
+    pub(crate) fn shared_name(&self) -> Option<Rc<str>> {
+        match self {
+            Proc::Closure { name, .. } | Proc::Macro { name, .. } => name.clone(),
+            Proc::Native { name, .. } => Some(name.clone()),
+        }
+    }
+
+    /// Name shown for the procedure if it is anonymous.
+    pub(crate) fn anonymous_name(&self) -> &'static str {
+        match self {
+            Proc::Closure { .. } => "<lambda>",
+            Proc::Macro { .. } => "<macro>",
+            Proc::Native { .. } => "<native>",
+        }
+    }
+
     pub fn badge(&self) -> String {
         match self {
             Proc::Closure { name, .. } => {
@@ -123,39 +229,77 @@ impl PartialEq for Proc {
     }
 }
 
+/// Evaluates the arguments of a traced native first, so that the trace shows
+/// their values, and passes them on quoted so that they aren't evaluated twice.
+fn invoke_traced_native(
+    name: &str,
+    func: NativeFunc,
+    args: &List,
+    context: &EvalContext,
+) -> EvalResult {
+    let values = args
+        .iter()
+        .map(|arg| eval(arg, context))
+        .collect::<Result<Vec<_>, _>>()?;
+    context.tracer().enter(name, values.iter());
+
+    // the quoted values keep the spans of the arguments for error locations
+    let mut quoted_args = List::Nil;
+    for (arg, value) in args
+        .iter()
+        .collect::<Vec<_>>()
+        .into_iter()
+        .zip(values)
+        .rev()
+    {
+        let quoted = Expr::List(list!(intern(QUOTE), value), arg.span());
+        quoted_args = cons(quoted, quoted_args);
+    }
+    func(name, &quoted_args, context)
+}
+
 fn eval_closure(
     closure_name: Option<&str>,
-    formal_args: &Vec<String>,
+    formal_args: &[String],
     body: &List,
     outer_context: &EvalContext,
     actual_args: &List,
     context: &EvalContext,
+    is_traced: bool,
 ) -> EvalResult {
     let closure_name = closure_name.unwrap_or("unnamed-closure");
-    let closure_context = EvalContext::derive_from(&outer_context);
-    let mut formal_args = formal_args.iter();
-    let mut actual_args = actual_args.iter();
+    let closure_context = EvalContext::derive_from(outer_context);
+    let mut formal_iter = formal_args.iter();
+    let mut actual_iter = actual_args.iter();
 
     loop {
-        if let Some(formal_arg) = formal_args.next() {
+        if let Some(formal_arg) = formal_iter.next() {
             if let Some(name) = get_variadic_args_name(formal_arg) {
-                closure_context.env.define(name, actual_args);
+                closure_context.env.define(name, actual_iter);
                 break;
             }
 
-            let expr = actual_args
+            let expr = actual_iter
                 .next()
-                .ok_or(EvalError::from(format!("{}: too few args", closure_name)))?;
+                .ok_or_else(|| arity_mismatch(closure_name, formal_args, actual_args))?;
 
             closure_context.env.define(formal_arg, eval(expr, context)?);
         } else {
-            if actual_args.next().is_none() {
+            if actual_iter.next().is_none() {
                 break;
             }
-            return Err(EvalError::from(format!("{}: too many args", closure_name)));
+            return Err(arity_mismatch(closure_name, formal_args, actual_args));
         }
     }
 
+    closure_context
+        .debugger()
+        .enter_proc(closure_name, &closure_context);
+    if is_traced {
+        let args = bound_args(formal_args, &closure_context);
+        context.tracer().enter(closure_name, args.iter());
+    }
+
     let mut iter = body.iter().peekable();
     while let Some(expr) = iter.next() {
         if iter.peek().is_none() {
@@ -167,35 +311,51 @@ fn eval_closure(
     Ok(NIL)
 }
 
+/// Values bound to `formal_args`, with the variadic ones spliced.
+fn bound_args(formal_args: &[String], context: &EvalContext) -> Vec<Expr> {
+    let mut args = Vec::new();
+    for formal_arg in formal_args {
+        match get_variadic_args_name(formal_arg) {
+            Some(name) => {
+                if let Some(Expr::List(list, _)) = context.env.lookup(name) {
+                    args.extend(list.iter().cloned());
+                }
+            }
+            None => args.extend(context.env.lookup(formal_arg)),
+        }
+    }
+    args
+}
+
 fn eval_macro(
     macro_name: Option<&str>,
-    formal_args: &Vec<String>,
+    formal_args: &[String],
     body: &List,
     actual_args: &List,
     context: &EvalContext,
 ) -> EvalResult {
     let macro_name = macro_name.unwrap_or("unnamed-macro");
     let macro_context = EvalContext::derive_from(context);
-    let mut formal_args = formal_args.iter();
-    let mut actual_args = actual_args.iter();
+    let mut formal_iter = formal_args.iter();
+    let mut actual_iter = actual_args.iter();
 
     loop {
-        if let Some(formal_arg) = formal_args.next() {
+        if let Some(formal_arg) = formal_iter.next() {
             if let Some(name) = get_variadic_args_name(formal_arg) {
-                macro_context.env.define(name, actual_args);
+                macro_context.env.define(name, actual_iter);
                 break;
             }
 
-            let expr = actual_args
+            let expr = actual_iter
                 .next()
-                .ok_or(EvalError::from(format!("{}: too few args", macro_name)))?;
+                .ok_or_else(|| arity_mismatch(macro_name, formal_args, actual_args))?;
 
             macro_context.env.define(formal_arg, expr.clone());
         } else {
-            if actual_args.next().is_none() {
+            if actual_iter.next().is_none() {
                 break;
             }
-            return Err(EvalError::from(format!("{}: too many args", macro_name)));
+            return Err(arity_mismatch(macro_name, formal_args, actual_args));
         }
     }
 
@@ -211,6 +371,24 @@ fn eval_macro(
     Ok(NIL)
 }
 
+fn arity_mismatch(proc_name: &str, formal_args: &[String], actual_args: &List) -> EvalError {
+    EvalError::from(EvalErrorKind::ArityMismatch {
+        proc_name: proc_name.to_string(),
+        expected: arity_of(formal_args),
+        actual: actual_args.len(),
+    })
+}
+
+fn arity_of(formal_args: &[String]) -> Arity {
+    match formal_args
+        .iter()
+        .position(|arg| get_variadic_args_name(arg).is_some())
+    {
+        Some(count) => Arity::AtLeast(count),
+        None => Arity::Exactly(formal_args.len()),
+    }
+}
+
 /// Extracts the name of variadic arguments from the given name.
 ///
 /// If the name starts with `*` and has more than one character,
@@ -285,7 +463,7 @@ mod tests {
             name: None,
             formal_args: vec!["a".into(), "b".into(), "c".into()],
             body: Box::new(list!(1, 2, 3, 4)),
-            outer_context: EvalContext::derive_from(&context),
+            outer_context: EvalContext::derive_from(context),
         };
         assert_ne!(closure, closure_context_diff);
     }
@@ -326,20 +504,23 @@ mod tests {
         let native1 = Proc::Native {
             name: "native".into(),
             func: native_fn_1,
+            is_special_form: false,
         };
         let native1_1 = Proc::Native {
             name: "native".into(),
             func: native_fn_1,
+            is_special_form: false,
         };
         let native2 = Proc::Native {
             name: "native".into(),
             func: native_fn_2,
+            is_special_form: false,
         };
         assert_eq!(native1.fingerprint(), native1_1.fingerprint());
         assert_ne!(native1.fingerprint(), native2.fingerprint());
 
         // code coverage workaround (#[coverage(off)] is unstable)
-        native_fn_1("", &list!(), &context).unwrap();
-        native_fn_2("", &list!(), &context).unwrap();
+        native_fn_1("", &list!(), context).unwrap();
+        native_fn_2("", &list!(), context).unwrap();
     }
 }
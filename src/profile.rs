@@ -0,0 +1,521 @@
+//! Procedure-level profiling.
+//!
+//! Every `Evaluator` has a `Profiler`. While it runs, it records each procedure
+//! call: how often each procedure is called and from where, how long the calls
+//! take, and how many envs they allocate. The resulting `Profile` prints as a
+//! table, or as folded stacks for flame graph tools.
+//!
+//! # Example
+//!
+//! ```
+//! use rusche::{eval::Evaluator, lexer::tokenize, parser::Parser};
+//!
+//! let evaluator = Evaluator::with_prelude();
+//! evaluator.profiler().start();
+//!
+//! let mut parser = Parser::with_tokens(
+//!     tokenize("(define (fact n) (if (= n 0) 1 (* n (fact (- n 1))))) (fact 5)").unwrap(),
+//! );
+//! while let Some(expr) = parser.parse().unwrap() {
+//!     evaluator.eval(&expr).unwrap();
+//! }
+//!
+//! let profile = evaluator.profiler().stop();
+//! let fact = profile
+//!     .procs
+//!     .iter()
+//!     .find(|proc| proc.badge == "proc/closure:fact")
+//!     .unwrap();
+//! assert_eq!(fact.calls, 6);
+//! assert_eq!(fact.call_sites.len(), 2);
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::{gc::Heap, proc::Proc, source::SourceId, span::Span};
+
+/// What was recorded for the calls to a procedure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcProfile {
+    /// `Proc::badge` of the procedure.
+    pub badge: String,
+    /// `Proc::definition_span` of the procedure, which tells apart procedures
+    /// with the same badge.
+    pub span: Option<Span>,
+    pub calls: usize,
+    /// Time from entry to return. Recursive calls aren't counted twice.
+    pub total_time: Duration,
+    /// Time spent in the procedure itself, excluding the procedures it called.
+    pub self_time: Duration,
+    /// Number of envs allocated by the procedure itself, e.g. to bind arguments.
+    pub allocations: usize,
+    /// Where the calls were made from, most frequent first.
+    pub call_sites: Vec<CallSite>,
+}
+
+/// Number of calls to a procedure made from one place.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallSite {
+    /// Span of the calling expression, if the call was made from source code.
+    pub span: Option<Span>,
+    pub calls: usize,
+}
+
+impl ProcProfile {
+    /// The badge, followed by the location of the definition if known.
+    pub fn label(&self) -> String {
+        match (self.span, self.span.and_then(|span| span.path())) {
+            (Some(span), Some(path)) => format!("{} ({}:{})", self.badge, path, span.begin),
+            (Some(span), None) => format!("{} ({})", self.badge, span.begin),
+            (None, _) => self.badge.clone(),
+        }
+    }
+}
+
+/// Everything recorded between `Profiler::start` and `Profiler::stop`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    /// Sorted by self time, longest first.
+    pub procs: Vec<ProcProfile>,
+    /// Self time of each distinct call stack, given as badges from the outermost
+    /// call to the innermost one.
+    pub stacks: Vec<(Vec<String>, Duration)>,
+}
+
+impl Profile {
+    /// Formats `procs` as a table with a header line.
+    pub fn table(&self) -> String {
+        let mut table = format!(
+            "{:>8} {:>12} {:>12} {:>8}  procedure\n",
+            "calls", "total ms", "self ms", "allocs"
+        );
+        for proc in &self.procs {
+            let _ = writeln!(
+                table,
+                "{:>8} {:>12.3} {:>12.3} {:>8}  {}",
+                proc.calls,
+                proc.total_time.as_secs_f64() * 1000.0,
+                proc.self_time.as_secs_f64() * 1000.0,
+                proc.allocations,
+                proc.label()
+            );
+        }
+        table
+    }
+
+    /// Formats `stacks` in the folded format read by flame graph tools: one line
+    /// per stack, with frames separated by ';' and followed by the self time in
+    /// microseconds. Stacks that took less than a microsecond are left out.
+    pub fn folded_stacks(&self) -> String {
+        let mut folded = String::new();
+        for (stack, time) in &self.stacks {
+            let micros = time.as_micros();
+            if micros > 0 {
+                let _ = writeln!(folded, "{} {}", stack.join(";"), micros);
+            }
+        }
+        folded
+    }
+}
+
+/// Identifies a place in the source code.
+type SpanKey = Option<(Option<SourceId>, usize)>;
+
+fn span_key(span: Option<Span>) -> SpanKey {
+    span.map(|span| (span.source, span.begin.offset))
+}
+
+/// A node of the call tree, which is what folded stacks are made of.
+struct Node {
+    badge: String,
+    children: HashMap<String, usize>,
+    self_time: Duration,
+}
+
+/// A call that hasn't returned yet.
+struct ActiveCall {
+    proc: usize,
+    node: usize,
+    /// Index in the call sites of `proc`. None when resumed to run tail calls,
+    /// so as not to count the call twice.
+    call_site: Option<usize>,
+    begin: Instant,
+    envs_allocated_at_begin: usize,
+    /// Whether an outer call to the same procedure is active.
+    is_recursive: bool,
+    child_time: Duration,
+    child_allocations: usize,
+}
+
+#[derive(Default)]
+struct ProfilerState {
+    is_running: bool,
+    procs: Vec<ProcProfile>,
+    /// Procedures by badge and definition.
+    proc_indices: HashMap<(String, SpanKey), usize>,
+    /// Call sites by procedure and calling expression.
+    call_site_indices: HashMap<(usize, SpanKey), usize>,
+    /// The call tree, rooted at the node 0 once anything was called.
+    nodes: Vec<Node>,
+    active_calls: Vec<ActiveCall>,
+}
+
+/// Records procedure calls while running. Clones share the same records.
+#[derive(Clone)]
+pub struct Profiler {
+    state: Rc<RefCell<ProfilerState>>,
+    heap: Weak<Heap>,
+}
+
+impl std::fmt::Debug for Profiler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Profiler")
+            .field("is_running", &self.is_running())
+            .finish()
+    }
+}
+
+impl Profiler {
+    pub(crate) fn new(heap: Weak<Heap>) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(ProfilerState::default())),
+            heap,
+        }
+    }
+
+    /// Starts recording, discarding what was recorded before.
+    pub fn start(&self) {
+        *self.state.borrow_mut() = ProfilerState {
+            is_running: true,
+            ..ProfilerState::default()
+        };
+    }
+
+    /// Stops recording and returns what was recorded. Calls that haven't
+    /// returned yet are left out.
+    pub fn stop(&self) -> Profile {
+        let profile = self.profile();
+        *self.state.borrow_mut() = ProfilerState::default();
+        profile
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.borrow().is_running
+    }
+
+    /// Returns what was recorded so far, without stopping.
+    pub fn profile(&self) -> Profile {
+        let state = self.state.borrow();
+
+        let mut procs = state.procs.clone();
+        for proc in &mut procs {
+            proc.call_sites.sort_by_key(|call_site| {
+                let offset = call_site.span.map(|span| span.begin.offset);
+                (std::cmp::Reverse(call_site.calls), offset)
+            });
+        }
+        procs.sort_by(|a, b| {
+            b.self_time
+                .cmp(&a.self_time)
+                .then(b.calls.cmp(&a.calls))
+                .then_with(|| a.label().cmp(&b.label()))
+        });
+
+        let mut stacks = Vec::new();
+        if !state.nodes.is_empty() {
+            collect_stacks(&state.nodes, 0, &mut Vec::new(), &mut stacks);
+        }
+
+        Profile { procs, stacks }
+    }
+
+    /// Called on entry to `proc`, called from `span`. Must be paired with `leave`.
+    pub(crate) fn enter(&self, proc: &Proc, span: Option<Span>) {
+        self.begin(proc, span, /*is_call*/ true);
+    }
+
+    /// Called before running the tail calls made by `proc`, called from `span`,
+    /// so that they're profiled as part of it. Must be paired with `leave`.
+    pub(crate) fn resume(&self, proc: &Proc, span: Option<Span>) {
+        self.begin(proc, span, /*is_call*/ false);
+    }
+
+    fn begin(&self, proc: &Proc, span: Option<Span>, is_call: bool) {
+        let mut state = self.state.borrow_mut();
+        if !state.is_running {
+            return;
+        }
+
+        let badge = proc.badge();
+        let definition_span = proc.definition_span();
+        let key = (badge.clone(), span_key(definition_span));
+        let proc_index = match state.proc_indices.get(&key) {
+            Some(index) => *index,
+            None => {
+                let index = state.procs.len();
+                state.procs.push(ProcProfile {
+                    badge: badge.clone(),
+                    span: definition_span,
+                    calls: 0,
+                    total_time: Duration::ZERO,
+                    self_time: Duration::ZERO,
+                    allocations: 0,
+                    call_sites: Vec::new(),
+                });
+                state.proc_indices.insert(key, index);
+                index
+            }
+        };
+
+        let call_site = is_call.then(|| {
+            let key = (proc_index, span_key(span));
+            match state.call_site_indices.get(&key) {
+                Some(index) => *index,
+                None => {
+                    let call_sites = &mut state.procs[proc_index].call_sites;
+                    call_sites.push(CallSite { span, calls: 0 });
+                    let index = call_sites.len() - 1;
+                    state.call_site_indices.insert(key, index);
+                    index
+                }
+            }
+        });
+
+        if state.nodes.is_empty() {
+            state.nodes.push(Node {
+                badge: String::new(),
+                children: HashMap::new(),
+                self_time: Duration::ZERO,
+            });
+        }
+        let parent = state.active_calls.last().map_or(0, |call| call.node);
+        let node = match state.nodes[parent].children.get(&badge) {
+            Some(node) => *node,
+            None => {
+                let node = state.nodes.len();
+                state.nodes[parent].children.insert(badge.clone(), node);
+                state.nodes.push(Node {
+                    badge,
+                    children: HashMap::new(),
+                    self_time: Duration::ZERO,
+                });
+                node
+            }
+        };
+
+        let is_recursive = state
+            .active_calls
+            .iter()
+            .any(|call| call.proc == proc_index);
+        state.active_calls.push(ActiveCall {
+            proc: proc_index,
+            node,
+            call_site,
+            begin: Instant::now(),
+            envs_allocated_at_begin: self.envs_allocated(),
+            is_recursive,
+            child_time: Duration::ZERO,
+            child_allocations: 0,
+        });
+    }
+
+    pub(crate) fn leave(&self) {
+        let mut state = self.state.borrow_mut();
+        // nothing to do for a call made before the profiler started
+        let Some(call) = state.active_calls.pop() else {
+            return;
+        };
+
+        let time = call.begin.elapsed();
+        let self_time = time.saturating_sub(call.child_time);
+        let allocations = self.envs_allocated() - call.envs_allocated_at_begin;
+
+        let proc = &mut state.procs[call.proc];
+        if let Some(call_site) = call.call_site {
+            proc.calls += 1;
+            proc.call_sites[call_site].calls += 1;
+        }
+        if !call.is_recursive {
+            proc.total_time += time;
+        }
+        proc.self_time += self_time;
+        proc.allocations += allocations.saturating_sub(call.child_allocations);
+        state.nodes[call.node].self_time += self_time;
+
+        if let Some(parent) = state.active_calls.last_mut() {
+            parent.child_time += time;
+            parent.child_allocations += allocations;
+        }
+    }
+
+    fn envs_allocated(&self) -> usize {
+        self.heap.upgrade().map_or(0, |heap| heap.envs_allocated())
+    }
+}
+
+fn collect_stacks(
+    nodes: &[Node],
+    index: usize,
+    stack: &mut Vec<String>,
+    stacks: &mut Vec<(Vec<String>, Duration)>,
+) {
+    let node = &nodes[index];
+    if index > 0 {
+        stack.push(node.badge.clone());
+        stacks.push((stack.clone(), node.self_time));
+    }
+
+    let mut children = node.children.values().copied().collect::<Vec<_>>();
+    children.sort_by(|a, b| nodes[*a].badge.cmp(&nodes[*b].badge));
+    for child in children {
+        collect_stacks(nodes, child, stack, stacks);
+    }
+
+    if index > 0 {
+        stack.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, lexer::tokenize_source, parser::Parser, source::SourceMap};
+
+    fn profile(text: &str) -> Profile {
+        let evaluator = Evaluator::with_prelude();
+        let source = SourceMap::add("test.rsc", text);
+        let exprs = Parser::with_tokens(tokenize_source(source).unwrap())
+            .parse_all()
+            .0;
+
+        evaluator.profiler().start();
+        for expr in exprs {
+            evaluator.eval(&expr).unwrap();
+        }
+        assert!(evaluator.profiler().is_running());
+        let profile = evaluator.profiler().stop();
+        assert!(!evaluator.profiler().is_running());
+        profile
+    }
+
+    fn find<'a>(profile: &'a Profile, label: &str) -> &'a ProcProfile {
+        profile
+            .procs
+            .iter()
+            .find(|proc| proc.label() == label)
+            .unwrap_or_else(|| panic!("no {} in\n{}", label, profile.table()))
+    }
+
+    #[test]
+    fn test_calls_and_allocations() {
+        let profile = profile(
+            "\
+(define (square n) (* n n))
+(define (sum-squares a b) (+ (square a) (square b)))
+(sum-squares 1 2)
+(sum-squares 3 4)
+",
+        );
+
+        // procedures are identified by their definitions
+        let sum_squares = find(&profile, "proc/closure:sum-squares (test.rsc:2:27)");
+        assert_eq!(sum_squares.calls, 2);
+        assert_eq!(sum_squares.allocations, 2);
+        assert!(sum_squares.self_time <= sum_squares.total_time);
+
+        let square = find(&profile, "proc/closure:square (test.rsc:1:20)");
+        assert_eq!(square.calls, 4);
+        assert_eq!(square.allocations, 4);
+
+        // and their calls are broken down by call site
+        let call_sites = square
+            .call_sites
+            .iter()
+            .map(|call_site| (call_site.span.unwrap().begin.to_string(), call_site.calls))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            call_sites,
+            vec![("2:30".to_string(), 2), ("2:41".to_string(), 2)]
+        );
+
+        // tail calls are nested in the procedure that made them
+        let stacks = profile
+            .stacks
+            .iter()
+            .map(|(stack, _)| stack.join(";"))
+            .collect::<Vec<_>>();
+        assert!(stacks.contains(
+            &"proc/closure:sum-squares;proc/native:num-add;proc/closure:square;proc/native:num-multiply"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn test_recursion() {
+        let profile = profile(
+            "\
+(define (fib n)
+  (if (< n 2)
+      n
+      (+ (fib (- n 1)) (fib (- n 2)))))
+(fib 10)
+",
+        );
+
+        let fib = find(&profile, "proc/closure:fib (test.rsc:2:3)");
+        assert_eq!(fib.calls, 177);
+        let calls = fib
+            .call_sites
+            .iter()
+            .map(|call_site| call_site.calls)
+            .collect::<Vec<_>>();
+        assert_eq!(calls, vec![88, 88, 1]);
+
+        // recursive calls don't add up to more than the outermost one
+        let self_time = profile.procs.iter().map(|proc| proc.self_time).sum();
+        assert!(fib.total_time <= self_time);
+    }
+
+    #[test]
+    fn test_output() {
+        let profile = Profile {
+            procs: vec![ProcProfile {
+                badge: "proc/closure:f".to_string(),
+                span: None,
+                calls: 3,
+                total_time: Duration::from_micros(2500),
+                self_time: Duration::from_micros(1500),
+                allocations: 3,
+                call_sites: vec![],
+            }],
+            stacks: vec![
+                (
+                    vec!["proc/closure:f".to_string()],
+                    Duration::from_micros(1500),
+                ),
+                (
+                    vec!["proc/closure:f".to_string(), "proc/native:car".to_string()],
+                    Duration::from_micros(1000),
+                ),
+                (
+                    vec!["proc/closure:f".to_string(), "proc/native:cdr".to_string()],
+                    Duration::from_nanos(10),
+                ),
+            ],
+        };
+
+        assert_eq!(
+            profile.table(),
+            "   calls     total ms      self ms   allocs  procedure\n\
+            \x20      3        2.500        1.500        3  proc/closure:f\n"
+        );
+        assert_eq!(
+            profile.folded_stacks(),
+            "proc/closure:f 1500\nproc/closure:f;proc/native:car 1000\n"
+        );
+    }
+}
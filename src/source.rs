@@ -0,0 +1,137 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Identifies a source registered in the `SourceMap`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SourceId(u32);
+
+impl fmt::Display for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// A piece of source code loaded by the interpreter, e.g. a script file.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Source {
+    pub path: String,
+    pub text: Rc<str>,
+}
+
+/// Registry of the sources loaded on the current thread.
+///
+/// A `SourceId` stays valid until its source is removed. Registering the same
+/// path and text twice returns the same ID, and the text is stored only once.
+///
+/// # Example
+///
+/// ```
+/// use rusche::source::SourceMap;
+///
+/// let id = SourceMap::add("main.rsc", "(display 1)");
+/// let source = SourceMap::get(id).unwrap();
+/// assert_eq!(source.path, "main.rsc");
+/// assert_eq!(&*source.text, "(display 1)");
+///
+/// assert_eq!(SourceMap::add("main.rsc", "(display 1)"), id);
+///
+/// SourceMap::remove(id);
+/// assert!(SourceMap::get(id).is_none());
+/// ```
+#[derive(Default)]
+pub struct SourceMap {
+    sources: HashMap<SourceId, Rc<Source>>,
+    ids: HashMap<Rc<Source>, SourceId>,
+    next_id: u32,
+}
+
+thread_local! {
+    static SOURCE_MAP: RefCell<SourceMap> = RefCell::new(SourceMap::default());
+}
+
+impl SourceMap {
+    /// Registers a source and returns its ID.
+    pub fn add<P: Into<String>, T: Into<Rc<str>>>(path: P, text: T) -> SourceId {
+        let source = Rc::new(Source {
+            path: path.into(),
+            text: text.into(),
+        });
+
+        SOURCE_MAP.with_borrow_mut(|map| {
+            if let Some(id) = map.ids.get(&source) {
+                return *id;
+            }
+
+            let id = SourceId(map.next_id);
+            map.next_id += 1;
+            map.sources.insert(id, source.clone());
+            map.ids.insert(source, id);
+            id
+        })
+    }
+
+    /// Unregisters the source registered with `id`, e.g. once an edited document
+    /// has been replaced by a newer version. Spans pointing into it lose their
+    /// path.
+    pub fn remove(id: SourceId) -> Option<Rc<Source>> {
+        SOURCE_MAP.with_borrow_mut(|map| {
+            let source = map.sources.remove(&id)?;
+            map.ids.remove(&source);
+            Some(source)
+        })
+    }
+
+    /// Returns the source registered with `id`.
+    pub fn get(id: SourceId) -> Option<Rc<Source>> {
+        SOURCE_MAP.with_borrow(|map| map.sources.get(&id).cloned())
+    }
+
+    /// Returns the path of the source registered with `id`.
+    pub fn path(id: SourceId) -> Option<String> {
+        Self::get(id).map(|source| source.path.clone())
+    }
+
+    /// Returns the text of the source registered with `id`, without copying it.
+    pub fn text(id: SourceId) -> Option<Rc<str>> {
+        Self::get(id).map(|source| source.text.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_get() {
+        let a = SourceMap::add("a.rsc", "(+ 1 2)");
+        let b = SourceMap::add("b.rsc", "(+ 1 2)");
+        assert_ne!(a, b);
+
+        assert_eq!(SourceMap::path(a).as_deref(), Some("a.rsc"));
+        assert_eq!(SourceMap::path(b).as_deref(), Some("b.rsc"));
+        assert_eq!(SourceMap::text(b).as_deref(), Some("(+ 1 2)"));
+    }
+
+    #[test]
+    fn test_add_same_source_twice() {
+        let a = SourceMap::add("a.rsc", "(+ 1 2)");
+        assert_eq!(SourceMap::add("a.rsc", "(+ 1 2)"), a);
+        assert_ne!(SourceMap::add("a.rsc", "(+ 1 3)"), a);
+    }
+
+    #[test]
+    fn test_remove() {
+        let a = SourceMap::add("removed.rsc", "(+ 1 2)");
+        let text = SourceMap::text(a).unwrap();
+        assert!(Rc::ptr_eq(&text, &SourceMap::get(a).unwrap().text));
+
+        assert_eq!(SourceMap::remove(a).unwrap().path, "removed.rsc");
+        assert!(SourceMap::get(a).is_none());
+        assert!(SourceMap::remove(a).is_none());
+
+        // a removed source is registered again under a new ID
+        assert_ne!(SourceMap::add("removed.rsc", "(+ 1 2)"), a);
+    }
+}
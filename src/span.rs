@@ -1,20 +1,31 @@
 use std::fmt;
 
+use crate::source::{SourceId, SourceMap};
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Loc {
     pub line: usize,
     pub column: usize,
+    /// Byte offset from the beginning of the source text.
+    pub offset: usize,
 }
 
 impl Loc {
-    pub fn new(line: usize, column: usize) -> Self {
-        Self { line, column }
+    pub fn new(line: usize, column: usize, offset: usize) -> Self {
+        Self {
+            line,
+            column,
+            offset,
+        }
     }
 
+    /// Moves the location forward within the same line. `offset` must not cross
+    /// any multi-byte characters.
     pub fn with_column_offset(&self, offset: usize) -> Loc {
         Self {
             line: self.line,
             column: self.column + offset,
+            offset: self.offset + offset,
         }
     }
 
@@ -31,22 +42,55 @@ impl fmt::Display for Loc {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Span {
+    pub source: Option<SourceId>,
     pub begin: Loc,
     pub end: Loc,
 }
 
 impl Span {
     pub fn new(begin: Loc, end: Loc) -> Self {
-        Self { begin, end }
+        Self {
+            source: None,
+            begin,
+            end,
+        }
     }
 
+    pub fn with_source(mut self, source: Option<SourceId>) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Creates a span covering both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        Span {
+            source: self.source,
+            begin: self.begin,
+            end: other.end,
+        }
+    }
+
+    /// Length of the span in bytes.
     pub fn len(&self) -> usize {
-        self.end.column - self.begin.column
+        self.end.offset - self.begin.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Path of the source this span belongs to, if it was registered in the
+    /// `SourceMap`.
+    pub fn path(&self) -> Option<String> {
+        self.source.and_then(SourceMap::path)
     }
 }
 
 impl fmt::Display for Span {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = self.path() {
+            write!(f, "{}:", path)?;
+        }
         if self.begin.line == self.end.line {
             write!(f, "{}-{}", self.begin, self.end.column)
         } else {
@@ -54,3 +98,22 @@ impl fmt::Display for Span {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let span = Span::new(Loc::new(1, 5, 4), Loc::new(1, 9, 8));
+        assert_eq!(span.to_string(), "1:5-9");
+        assert_eq!(span.len(), 4);
+
+        let span = Span::new(Loc::new(1, 5, 4), Loc::new(2, 3, 12));
+        assert_eq!(span.to_string(), "1:5-2:3");
+
+        let source = SourceMap::add("test.rsc", "(define x\n  1)");
+        let span = span.with_source(Some(source));
+        assert_eq!(span.to_string(), "test.rsc:1:5-2:3");
+    }
+}
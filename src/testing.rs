@@ -0,0 +1,90 @@
+//! Unit tests written in rusche.
+//!
+//! `(deftest name body...)` defines a test instead of running it, and the
+//! `assert-equal`, `assert-true` and `assert-error` procedures fail it. The
+//! tests defined by evaluating a file are then listed by the `TestRegistry` of
+//! the evaluator, so that a runner can run each of them.
+//!
+//! # Example
+//!
+//! ```
+//! use rusche::{eval::Evaluator, lexer::tokenize, parser::Parser, testing::TestOutcome};
+//!
+//! let text = r#"
+//! (define (square x) (* x x))
+//! (deftest "square" (assert-equal 4 (square 2)))
+//! (deftest "broken" (assert-equal 5 (square 2)))
+//! "#;
+//! let load = || {
+//!     let evaluator = Evaluator::with_prelude();
+//!     for expr in Parser::with_tokens(tokenize(text).unwrap()).parse_all().0 {
+//!         evaluator.eval(&expr).unwrap();
+//!     }
+//!     evaluator
+//! };
+//!
+//! // each test runs in an evaluator of its own
+//! let tests = load().tests().tests();
+//! assert!(matches!(tests[0].run(&load()), TestOutcome::Passed));
+//! assert!(matches!(tests[1].run(&load()), TestOutcome::Failed(_)));
+//! ```
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::{
+    eval::{EvalError, EvalErrorKind, Evaluator},
+    list::List,
+    span::Span,
+};
+
+/// A test defined with `deftest`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Test {
+    pub name: String,
+    pub body: List,
+    /// Span of the name.
+    pub span: Option<Span>,
+}
+
+/// How a test ended.
+#[derive(Debug, PartialEq)]
+pub enum TestOutcome {
+    Passed,
+    /// An assertion failed.
+    Failed(EvalError),
+    /// Any other error was raised.
+    Errored(EvalError),
+}
+
+impl Test {
+    /// Evaluates the body of the test at the top level of `evaluator`, which
+    /// should have evaluated the file that defined it.
+    pub fn run(&self, evaluator: &Evaluator) -> TestOutcome {
+        for expr in self.body.iter() {
+            match evaluator.eval(expr) {
+                Ok(_) => {}
+                Err(error) if matches!(*error.kind, EvalErrorKind::AssertionFailed { .. }) => {
+                    return TestOutcome::Failed(error);
+                }
+                Err(error) => return TestOutcome::Errored(error),
+            }
+        }
+        TestOutcome::Passed
+    }
+}
+
+/// Tests defined in an evaluator, in the order they were defined. Clones share
+/// the same tests.
+#[derive(Clone, Debug, Default)]
+pub struct TestRegistry(Rc<RefCell<Vec<Test>>>);
+
+impl TestRegistry {
+    pub fn tests(&self) -> Vec<Test> {
+        self.0.borrow().clone()
+    }
+
+    pub(crate) fn register(&self, test: Test) {
+        self.0.borrow_mut().push(test);
+    }
+}
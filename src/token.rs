@@ -1,15 +1,23 @@
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
-use crate::span::{Loc, Span};
+use crate::expr::write_escaped_str;
+use crate::number::write_number;
+use crate::span::Span;
 
 #[derive(Clone, Debug)]
 pub enum Token {
-    OpenParen(Loc),
-    CloseParen(Loc),
-    Quote(Loc),
-    Quasiquote(Loc),
-    Unquote(Loc),
-    UnquoteSplicing(Loc),
+    OpenParen(Span),
+    CloseParen(Span),
+    Quote(Span),
+    Quasiquote(Span),
+    Unquote(Span),
+    UnquoteSplicing(Span),
+    /// `#;`, which comments out the expression following it.
+    DatumComment(Span),
+    /// `#n=`, which labels the expression following it.
+    DatumLabel(usize, Span),
+    /// `#n#`, which refers to the expression labeled `n`.
+    DatumRef(usize, Span),
     Num(f64, Span),
     Str(String, Span),
     Sym(String, Span),
@@ -18,13 +26,18 @@ pub enum Token {
 impl Token {
     pub fn span(&self) -> Span {
         match self {
-            Token::OpenParen(loc)
-            | Token::CloseParen(loc)
-            | Token::Quote(loc)
-            | Token::Quasiquote(loc)
-            | Token::Unquote(loc) => Span::new(*loc, loc.with_column_offset(1)),
-            Token::UnquoteSplicing(loc) => Span::new(*loc, loc.with_column_offset(2)),
-            Token::Num(_, span) | Token::Str(_, span) | Token::Sym(_, span) => *span,
+            Token::OpenParen(span)
+            | Token::CloseParen(span)
+            | Token::Quote(span)
+            | Token::Quasiquote(span)
+            | Token::Unquote(span)
+            | Token::UnquoteSplicing(span)
+            | Token::DatumComment(span)
+            | Token::DatumLabel(_, span)
+            | Token::DatumRef(_, span)
+            | Token::Num(_, span)
+            | Token::Str(_, span)
+            | Token::Sym(_, span) => *span,
         }
     }
 }
@@ -38,6 +51,9 @@ impl PartialEq for Token {
             (Token::Quasiquote(_), Token::Quasiquote(_)) => true,
             (Token::Unquote(_), Token::Unquote(_)) => true,
             (Token::UnquoteSplicing(_), Token::UnquoteSplicing(_)) => true,
+            (Token::DatumComment(_), Token::DatumComment(_)) => true,
+            (Token::DatumLabel(a, _), Token::DatumLabel(b, _)) => a == b,
+            (Token::DatumRef(a, _), Token::DatumRef(b, _)) => a == b,
             (Token::Num(a, _), Token::Num(b, _)) => a == b,
             (Token::Str(a, _), Token::Str(b, _)) => a == b,
             (Token::Sym(a, _), Token::Sym(b, _)) => a == b,
@@ -55,8 +71,11 @@ impl Display for Token {
             Token::Quasiquote(_) => write!(f, "`"),
             Token::Unquote(_) => write!(f, ","),
             Token::UnquoteSplicing(_) => write!(f, ",@"),
-            Token::Num(value, _) => write!(f, "{}", value),
-            Token::Str(text, _) => write!(f, "\"{}\"", text),
+            Token::DatumComment(_) => write!(f, "#;"),
+            Token::DatumLabel(label, _) => write!(f, "#{}=", label),
+            Token::DatumRef(label, _) => write!(f, "#{}#", label),
+            Token::Num(value, _) => write_number(f, *value),
+            Token::Str(text, _) => write_escaped_str(f, text),
             Token::Sym(name, _) => write!(f, "{}", name),
         }
     }
@@ -65,20 +84,21 @@ impl Display for Token {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{lexer::tokenize, span::Loc};
 
     #[test]
     fn test_span_fixed_len() {
         macro_rules! assert_token_span_length_eq {
-            ($length:literal, $token_case:ident) => {
-                assert_eq!($length, Token::$token_case(Loc::new(0, 0)).span().len());
+            ($length:literal, $text:literal) => {
+                assert_eq!($length, tokenize($text).unwrap()[0].span().len());
             };
         }
-        assert_token_span_length_eq!(1, OpenParen);
-        assert_token_span_length_eq!(1, CloseParen);
-        assert_token_span_length_eq!(1, Quote);
-        assert_token_span_length_eq!(1, Quasiquote);
-        assert_token_span_length_eq!(1, Unquote);
-        assert_token_span_length_eq!(2, UnquoteSplicing);
+        assert_token_span_length_eq!(1, "(");
+        assert_token_span_length_eq!(1, ")");
+        assert_token_span_length_eq!(1, "'");
+        assert_token_span_length_eq!(1, "`");
+        assert_token_span_length_eq!(1, ",");
+        assert_token_span_length_eq!(2, ",@");
     }
 
     #[test]
@@ -86,7 +106,10 @@ mod tests {
         macro_rules! assert_token_format_eq {
             ($token_case:ident, $formatted:literal) => {
                 assert_eq!(
-                    format!("{}", Token::$token_case(Loc::new(1, 1))),
+                    format!(
+                        "{}",
+                        Token::$token_case(Span::new(Loc::new(1, 1, 0), Loc::new(1, 2, 1)))
+                    ),
                     $formatted
                 );
             };
@@ -94,7 +117,7 @@ mod tests {
                 assert_eq!(
                     format!(
                         "{}",
-                        Token::$token_case($value, Span::new(Loc::new(1, 1), Loc::new(1, 2)))
+                        Token::$token_case($value, Span::new(Loc::new(1, 1, 0), Loc::new(1, 2, 1)))
                     ),
                     $formatted
                 );
@@ -105,11 +128,16 @@ mod tests {
         assert_token_format_eq!(Quasiquote, "`");
         assert_token_format_eq!(Unquote, ",");
         assert_token_format_eq!(UnquoteSplicing, ",@");
+        assert_token_format_eq!(DatumLabel(0), "#0=");
+        assert_token_format_eq!(DatumRef(12), "#12#");
         assert_token_format_eq!(Num(0.0), "0");
         assert_token_format_eq!(Num(0.5), "0.5");
         assert_token_format_eq!(Num(1.0), "1");
         assert_token_format_eq!(Num(123.456), "123.456");
         assert_token_format_eq!(Num(123.456), "123.456");
+        assert_token_format_eq!(Num(f64::INFINITY), "+inf.0");
+        assert_token_format_eq!(Num(f64::NEG_INFINITY), "-inf.0");
+        assert_token_format_eq!(Num(f64::NAN), "+nan.0");
         assert_token_format_eq!(Str("str".to_string()), "\"str\"");
         assert_token_format_eq!(Sym("sym".to_string()), "sym");
     }
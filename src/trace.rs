@@ -0,0 +1,370 @@
+//! Tracing of individual procedures.
+//!
+//! Once a procedure is traced, e.g. with `(trace fib)`, each call to it prints
+//! the call with its arguments and then its result, indented by the number of
+//! traced calls it's nested in. Procedures print their evaluated arguments, while
+//! macros print them as written, since that's how they get them.
+//!
+//! Special forms like `if` and `define` can't be traced: they decide which of
+//! their arguments get evaluated, and when, so there are no values to print
+//! before they run.
+//!
+//! A traced procedure is the procedure value given to `trace`, not every
+//! procedure with the same name: a local procedure named `fib` isn't traced by
+//! `(trace fib)` at the top level.
+//!
+//! Nothing is printed and almost nothing is done while no procedure is traced.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::{env::Env, eval::EvalResult, expr::Expr, proc::Proc, span::Span};
+
+/// Receives each line of the trace, without a trailing newline.
+pub type TraceSink = Box<dyn FnMut(&str)>;
+
+#[derive(Default)]
+struct TracerState {
+    /// Whether any procedure is traced, checked before any other work on each
+    /// call.
+    is_tracing: Cell<bool>,
+    traced: RefCell<Vec<TracedProc>>,
+    /// Prints to stderr if `None`.
+    sink: RefCell<Option<TraceSink>>,
+    /// Number of traced calls that haven't returned yet.
+    active_calls: Cell<usize>,
+}
+
+/// Identifies a traced procedure. Procedures are copied when they are looked
+/// up, so closures and macros are identified by their definition instead.
+#[derive(Debug, PartialEq)]
+enum TracedProc {
+    Native {
+        name: String,
+    },
+    Closure {
+        name: String,
+        definition: Option<Span>,
+        env: *const Env,
+    },
+    Macro {
+        name: String,
+        definition: Option<Span>,
+    },
+}
+
+impl TracedProc {
+    fn new(proc: &Proc) -> Self {
+        let name = proc.name().to_string();
+        match proc {
+            Proc::Native { .. } => TracedProc::Native { name },
+            Proc::Closure { outer_context, .. } => TracedProc::Closure {
+                name,
+                definition: proc.definition_span(),
+                env: Rc::as_ptr(&outer_context.env),
+            },
+            Proc::Macro { .. } => TracedProc::Macro {
+                name,
+                definition: proc.definition_span(),
+            },
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            TracedProc::Native { name }
+            | TracedProc::Closure { name, .. }
+            | TracedProc::Macro { name, .. } => name,
+        }
+    }
+
+    fn matches(&self, proc: &Proc) -> bool {
+        match (self, proc) {
+            (TracedProc::Native { name }, Proc::Native { name: other, .. }) => {
+                name.as_str() == &**other
+            }
+            (
+                TracedProc::Closure {
+                    name,
+                    definition,
+                    env,
+                },
+                Proc::Closure { outer_context, .. },
+            ) => {
+                name == proc.name()
+                    && *env == Rc::as_ptr(&outer_context.env)
+                    && *definition == proc.definition_span()
+            }
+            (TracedProc::Macro { name, definition }, Proc::Macro { .. }) => {
+                name == proc.name() && *definition == proc.definition_span()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Traced procedures and where their trace goes. Clones share the same state.
+#[derive(Clone, Default)]
+pub struct Tracer(Rc<TracerState>);
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("traced", &self.traced())
+            .finish()
+    }
+}
+
+impl Tracer {
+    /// Sets where the trace goes. It goes to stderr by default.
+    pub fn set_sink(&self, sink: Option<TraceSink>) {
+        *self.0.sink.borrow_mut() = sink;
+    }
+
+    /// Traces `proc`. Returns false if it already was.
+    pub fn trace(&self, proc: &Proc) -> bool {
+        let mut traced = self.0.traced.borrow_mut();
+        if traced.iter().any(|traced| traced.matches(proc)) {
+            return false;
+        }
+        traced.push(TracedProc::new(proc));
+        self.0.is_tracing.set(true);
+        true
+    }
+
+    /// Stops tracing `proc`. Returns false if it wasn't.
+    pub fn untrace(&self, proc: &Proc) -> bool {
+        let mut traced = self.0.traced.borrow_mut();
+        let count = traced.len();
+        traced.retain(|traced| !traced.matches(proc));
+        self.0.is_tracing.set(!traced.is_empty());
+        traced.len() < count
+    }
+
+    /// Stops tracing every procedure.
+    pub fn untrace_all(&self) {
+        self.0.traced.borrow_mut().clear();
+        self.0.is_tracing.set(false);
+    }
+
+    /// Returns the names of the traced procedures, in alphabetical order.
+    pub fn traced(&self) -> Vec<String> {
+        let mut names = self
+            .0
+            .traced
+            .borrow()
+            .iter()
+            .map(|traced| traced.name().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    pub(crate) fn is_traced(&self, proc: &Proc) -> bool {
+        self.0.is_tracing.get()
+            && self
+                .0
+                .traced
+                .borrow()
+                .iter()
+                .any(|traced| traced.matches(proc))
+    }
+
+    pub(crate) fn active_calls(&self) -> usize {
+        self.0.active_calls.get()
+    }
+
+    /// Prints a call to `name` with `args`. Must be followed by `leave_calls`.
+    pub(crate) fn enter<'a>(&self, name: &str, args: impl Iterator<Item = &'a Expr>) {
+        let mut line = format!("({}", name);
+        for arg in args {
+            line.push(' ');
+            line.push_str(&arg.to_string());
+        }
+        line.push(')');
+
+        let depth = self.0.active_calls.get();
+        self.print(depth, &line);
+        self.0.active_calls.set(depth + 1);
+    }
+
+    /// Prints `result` as the result of every traced call entered after
+    /// `active_calls` of them were active, innermost first. There can be more
+    /// than one when tail calls are made to traced procedures, since they all
+    /// return when the last one does.
+    pub(crate) fn leave_calls(&self, active_calls: usize, result: &EvalResult) {
+        if self.0.active_calls.get() <= active_calls {
+            return;
+        }
+
+        let line = match result {
+            Ok(value) => format!("=> {}", value),
+            Err(error) => format!("=> error: {}", error.message()),
+        };
+        while self.0.active_calls.get() > active_calls {
+            let depth = self.0.active_calls.get() - 1;
+            self.0.active_calls.set(depth);
+            self.print(depth, &line);
+        }
+    }
+
+    fn print(&self, depth: usize, line: &str) {
+        let line = format!("{}{}", "  ".repeat(depth), line);
+
+        // the sink is taken out while it runs, in case it evaluates something
+        let sink = self.0.sink.borrow_mut().take();
+        match sink {
+            Some(mut sink) => {
+                sink(&line);
+                self.0.sink.borrow_mut().get_or_insert(sink);
+            }
+            None => eprintln!("{}", line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, lexer::tokenize, parser::Parser};
+
+    /// Evaluates `text` and returns the trace.
+    fn trace(text: &str) -> Vec<String> {
+        let evaluator = Evaluator::with_prelude();
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let sink = lines.clone();
+        evaluator.tracer().set_sink(Some(Box::new(move |line| {
+            sink.borrow_mut().push(line.to_string())
+        })));
+
+        let mut parser = Parser::with_tokens(tokenize(text).unwrap());
+        while let Some(expr) = parser.parse().unwrap() {
+            let _ = evaluator.eval(&expr);
+        }
+        let lines = lines.borrow().clone();
+        lines
+    }
+
+    #[test]
+    fn test_trace() {
+        let lines = trace(
+            "\
+(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+(trace fib)
+(fib 3)
+(untrace fib)
+(fib 3)",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "(fib 3)",
+                "  (fib 2)",
+                "    (fib 1)",
+                "    => 1",
+                "    (fib 0)",
+                "    => 0",
+                "  => 1",
+                "  (fib 1)",
+                "  => 1",
+                "=> 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_by_identity() {
+        let lines = trace(
+            "\
+(define (fib n) n)
+(define (outer n) (define (fib n) (* n 10)) (fib n))
+(trace fib)
+(outer 1)
+(fib 2)",
+        );
+        // the local `fib` isn't the traced one
+        assert_eq!(lines, vec!["(fib 2)", "=> 2"]);
+    }
+
+    #[test]
+    fn test_trace_tail_calls() {
+        let lines = trace(
+            "\
+(define (count-down n) (if (= n 0) 'done (count-down (- n 1))))
+(define (start n) (count-down n))
+(trace count-down start)
+(start 1)",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "(start 1)",
+                "  (count-down 1)",
+                "    (count-down 0)",
+                "    => done",
+                "  => done",
+                "=> done",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_natives_and_errors() {
+        let lines = trace(
+            "\
+(define (f x) (car x))
+(trace f car)
+(f '(1 2))
+(f 1)",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "(f (1 2))",
+                "  (car (1 2))",
+                "  => 1",
+                "=> 1",
+                "(f 1)",
+                "  (car 1)",
+                "  => error: car: expected a non-empty list, but got a number.",
+                "=> error: car: expected a non-empty list, but got a number.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_evaluates_native_args_once() {
+        let lines = trace(
+            "(define n 0)
+(define (next) (set! n (+ n 1)) n)
+(trace num-add)
+(num-add (next) (next))",
+        );
+        // each (next) adds 1 to n before the outer call prints its arguments
+        assert_eq!(
+            lines,
+            vec![
+                "(num-add 0 1)",
+                "=> 1",
+                "(num-add 1 1)",
+                "=> 2",
+                "(num-add 1 2)",
+                "=> 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_special_form() {
+        let evaluator = Evaluator::with_prelude();
+        let expr = Parser::with_tokens(tokenize("(trace if)").unwrap())
+            .parse()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            evaluator.eval(&expr).unwrap_err().message(),
+            "trace: can't trace the special form `if`"
+        );
+    }
+}
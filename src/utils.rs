@@ -1,8 +1,8 @@
-use std::any::Any;
 use std::rc::Rc;
 
-use crate::eval::{eval, EvalContext, EvalError};
+use crate::eval::{eval, Arity, EvalContext, EvalError, EvalErrorKind};
 use crate::expr::Expr;
+use crate::foreign::ForeignObject;
 use crate::list::List;
 
 /// Get exactly one argument from a list.
@@ -34,15 +34,10 @@ use crate::list::List;
 /// ```
 pub fn get_exact_1_arg<'a>(proc_name: &str, args: &'a List) -> Result<&'a Expr, EvalError> {
     let mut iter = args.iter();
-    let Some(arg) = iter.next() else {
-        return Err(EvalError::from(format!("{proc_name} needs an argument.")));
-    };
-    if iter.next().is_none() {
-        Ok(arg)
-    } else {
-        Err(EvalError::from(format!(
-            "{proc_name} expects only 1 argument."
-        )))
+
+    match (iter.next(), iter.next()) {
+        (Some(arg), None) => Ok(arg),
+        _ => Err(arity_mismatch(proc_name, Arity::Exactly(1), args)),
     }
 }
 
@@ -85,12 +80,7 @@ pub fn get_exact_2_args<'a>(
 
     match (arg1, arg2, arg3) {
         (Some(arg1), Some(arg2), None) => Ok((arg1, arg2)),
-        (Some(_), Some(_), Some(_)) => Err(EvalError::from(format!(
-            "{proc_name}: takes only two arguments"
-        ))),
-        _ => Err(EvalError::from(format!(
-            "{proc_name}: requres two arguments"
-        ))),
+        _ => Err(arity_mismatch(proc_name, Arity::Exactly(2), args)),
     }
 }
 
@@ -134,12 +124,7 @@ pub fn get_exact_3_args<'a>(
 
     match (arg1, arg2, arg3, arg4) {
         (Some(arg1), Some(arg2), Some(arg3), None) => Ok((arg1, arg2, arg3)),
-        (Some(_), Some(_), Some(_), Some(_)) => Err(EvalError::from(format!(
-            "{proc_name}: takes only two arguments"
-        ))),
-        _ => Err(EvalError::from(format!(
-            "{proc_name}: requres two arguments"
-        ))),
+        _ => Err(arity_mismatch(proc_name, Arity::Exactly(3), args)),
     }
 }
 
@@ -183,15 +168,21 @@ pub fn get_2_or_3_args<'a>(
 
     match (arg1, arg2, arg3, arg4) {
         (Some(arg1), Some(arg2), arg3, None) => Ok((arg1, arg2, arg3)),
-        (Some(_), Some(_), Some(_), Some(_)) => Err(EvalError::from(format!(
-            "{proc_name}: takes only up to 3 arguments"
-        ))),
-        _ => Err(EvalError::from(format!(
-            "{proc_name}: requres at least 2 arguments"
-        ))),
+        _ => Err(arity_mismatch(proc_name, Arity::Between(2, 3), args)),
     }
 }
 
+fn arity_mismatch(proc_name: &str, expected: Arity, args: &List) -> EvalError {
+    EvalError::new(
+        EvalErrorKind::ArityMismatch {
+            proc_name: proc_name.to_string(),
+            expected,
+            actual: args.len(),
+        },
+        args.span(),
+    )
+}
+
 /// Make a vector of symbol names from a list of arguments.
 ///
 /// Check if `list` contains only symbols. If so, return a vector of the symbols.
@@ -202,10 +193,10 @@ pub fn make_formal_args(list: &List) -> Result<Vec<String>, EvalError> {
     let mut formal_args = Vec::new();
     for item in list.iter() {
         let Expr::Sym(formal_arg, _) = item else {
-            return Err(EvalError {
-                message: format!("{item} is not a symbol."),
-                span: item.span(),
-            });
+            return Err(EvalError::new(
+                EvalErrorKind::type_mismatch("formal arguments", "symbol", item),
+                item.span(),
+            ));
         };
         formal_args.push(formal_arg.clone());
     }
@@ -244,10 +235,10 @@ pub fn eval_into_str(
 ) -> Result<String, EvalError> {
     match eval(expr, context)? {
         Expr::Str(text, _) => Ok(text),
-        _ => Err(EvalError {
-            message: format!("{proc_name}: `{expr}` does not evaluate to a string."),
-            span: expr.span(),
-        }),
+        value => Err(EvalError::new(
+            EvalErrorKind::type_mismatch(proc_name, "string", &value),
+            expr.span(),
+        )),
     }
 }
 
@@ -282,10 +273,10 @@ pub fn eval_into_num(
 ) -> Result<f64, EvalError> {
     match eval(expr, context)? {
         Expr::Num(value, _) => Ok(value),
-        _ => Err(EvalError {
-            message: format!("{proc_name}: `{expr}` does not evaluate to a number."),
-            span: expr.span(),
-        }),
+        value => Err(EvalError::new(
+            EvalErrorKind::type_mismatch(proc_name, "number", &value),
+            expr.span(),
+        )),
     }
 }
 
@@ -335,21 +326,22 @@ pub fn eval_into_int(
     if num.fract() == 0.0 {
         Ok(num as i32)
     } else {
-        Err(EvalError {
-            message: format!(
-                "{}: {} must be an integer, but got {}.",
-                proc_name, arg_name, num
-            ),
-            span: expr.span(),
-        })
+        Err(EvalError::new(
+            EvalErrorKind::TypeMismatch {
+                proc_name: proc_name.to_string(),
+                expected: format!("integer {arg_name}"),
+                actual: format!("fractional number {num}"),
+            },
+            expr.span(),
+        ))
     }
 }
 
 /// Evaluate an expression into a foreign object.
 ///
 /// Check if `expr` evaluates to a foreign object (`Expr::Foreign`). If so, return
-/// the object (`Rc<dyn Any>`). Otherwise, return an error message.
-/// The caller of this function can downcase the object to the expected type.
+/// the object (`Rc<dyn ForeignObject>`). Otherwise, return an error message.
+/// The caller of this function can downcast the object to the expected type.
 ///
 /// # Arguments
 ///
@@ -360,30 +352,39 @@ pub fn eval_into_int(
 /// # Example
 ///
 /// ```
-/// use std::{any::Any, rc::Rc};
+/// use std::rc::Rc;
 /// use rusche::{
 ///     eval::Evaluator,
 ///     expr::Expr,
+///     foreign::ForeignObject,
 ///     utils::eval_into_foreign,
 /// };
 ///
+/// struct Handle(i32);
+///
+/// impl ForeignObject for Handle {
+///     fn type_name(&self) -> &str {
+///         "handle"
+///     }
+/// }
+///
 /// let evaluator = Evaluator::new();
 /// let context = evaluator.context();
-/// let expr = Expr::Foreign(Rc::new(Vec::<i32>::new()));
+/// let expr = Expr::Foreign(Rc::new(Handle(1)));
 /// let object = eval_into_foreign("test", &expr, context).unwrap();
-/// assert!(object.downcast::<Vec<i32>>().is_ok());
+/// assert!(object.downcast::<Handle>().is_ok());
 /// ```
 pub fn eval_into_foreign(
     proc_name: &str,
     expr: &Expr,
     context: &EvalContext,
-) -> Result<Rc<dyn Any>, EvalError> {
+) -> Result<Rc<dyn ForeignObject>, EvalError> {
     match eval(expr, context)? {
         Expr::Foreign(object) => Ok(object),
-        _ => Err(EvalError {
-            message: format!("{proc_name}: `{expr}` does not evaluate to a foreign object."),
-            span: expr.span(),
-        }),
+        value => Err(EvalError::new(
+            EvalErrorKind::type_mismatch(proc_name, "foreign object", &value),
+            expr.span(),
+        )),
     }
 }
 
@@ -484,9 +485,17 @@ mod tests {
         let evaluator = Evaluator::new();
         let context = evaluator.context();
 
-        let expr = Expr::Foreign(Rc::new(Vec::<i32>::new()));
+        struct Handle;
+
+        impl ForeignObject for Handle {
+            fn type_name(&self) -> &str {
+                "handle"
+            }
+        }
+
+        let expr = Expr::Foreign(Rc::new(Handle));
         let object = eval_into_foreign("test", &expr, context).unwrap();
-        assert!(object.downcast::<Vec<i32>>().is_ok());
+        assert!(object.downcast::<Handle>().is_ok());
 
         assert!(eval_into_foreign("test", &Expr::from(1), context).is_err());
         assert!(eval_into_foreign("test", &Expr::from("str"), context).is_err());
@@ -0,0 +1,229 @@
+mod common;
+
+use common::EvalToStr;
+use rusche::{
+    eval::{Arity, EvalErrorKind, Evaluator},
+    lexer::{tokenize, tokenize_source},
+    parser::Parser,
+    source::SourceMap,
+};
+
+fn eval_source(path: &str, text: &str) -> Vec<String> {
+    let evaluator = Evaluator::with_prelude();
+    let source = SourceMap::add(path, text);
+    let tokens = tokenize_source(source).expect("Failed to tokenize");
+    let mut parser = Parser::with_tokens(tokens);
+
+    let mut errors = Vec::new();
+    while let Some(expr) = parser.parse().expect("Failed to parse") {
+        if let Err(error) = evaluator.eval(&expr) {
+            errors.push(error.to_string());
+        }
+    }
+    errors
+}
+
+#[test]
+fn test_error_location() {
+    let evaluator = Evaluator::with_builtin();
+    assert_eq!(
+        evaluator.eval_to_str("(car 1)"),
+        "Err: 1:6: car: expected a non-empty list, but got a number."
+    );
+}
+
+#[test]
+fn test_error_location_of_quoted_data() {
+    let evaluator = Evaluator::with_builtin();
+    assert_eq!(
+        evaluator.eval_to_str("(car 'a)"),
+        "Err: 1:6: car: expected a non-empty list, but got a symbol."
+    );
+    assert_eq!(
+        evaluator.eval_to_str("\n  ('a)"),
+        "Err: 2:4: `(quote a)` does not evaluate to a callable."
+    );
+}
+
+#[test]
+fn test_error_location_of_malformed_forms() {
+    let evaluator = Evaluator::with_prelude();
+    assert_eq!(
+        evaluator.eval_to_str("(define 1 2)"),
+        "Err: 1:9: define: expected a symbol or non-empty list, but got a number."
+    );
+    assert_eq!(
+        evaluator.eval_to_str("(define)"),
+        "Err: 1:1: define: expects at least 2 arguments, but got 0."
+    );
+    assert_eq!(
+        evaluator.eval_to_str("\n`,@'(1 2)"),
+        "Err: 2:2: quasiquote: expects 1 argument, but got 2."
+    );
+    assert_eq!(
+        evaluator.eval_to_str("(define x)"),
+        "Err: 1:1: define: expects 2 arguments, but got 1."
+    );
+    assert_eq!(
+        evaluator.eval_to_str("(set! 1 2)"),
+        "Err: 1:7: set!: expected a symbol, but got a number."
+    );
+    assert_eq!(
+        evaluator.eval_to_str("(lambda 1)"),
+        "Err: 1:9: lambda: expected a list, but got a number."
+    );
+    assert_eq!(
+        evaluator.eval_to_str("(defmacro (1) 2)"),
+        "Err: 1:12: defmacro: expected a symbol, but got a number."
+    );
+    assert_eq!(
+        evaluator.eval_to_str("`(1 (unquote))"),
+        "Err: 1:5: unquote: expects 1 argument, but got 0."
+    );
+}
+
+#[test]
+fn test_error_location_with_path() {
+    let errors = eval_source("main.rsc", "(define x 1)\n(car x)");
+    assert_eq!(
+        errors,
+        ["main.rsc:2:6: car: expected a non-empty list, but got a number."]
+    );
+}
+
+#[test]
+fn test_error_locations_in_multiple_files() {
+    let a = eval_source("a.rsc", "(car 1)");
+    let b = eval_source("b.rsc", "\n\n(cdr 2)");
+    assert_eq!(
+        a,
+        ["a.rsc:1:6: car: expected a non-empty list, but got a number."]
+    );
+    assert_eq!(
+        b,
+        ["b.rsc:3:6: cdr: expected a non-empty list, but got a number."]
+    );
+}
+
+fn eval_backtrace(src: &[&str]) -> String {
+    let evaluator = Evaluator::with_prelude();
+    let (last, defs) = src.split_last().unwrap();
+    for def in defs {
+        let _ = evaluator.eval_to_str(def);
+    }
+
+    let source = SourceMap::add("bt.rsc", *last);
+    let tokens = tokenize_source(source).unwrap();
+    let expr = Parser::with_tokens(tokens).parse().unwrap().unwrap();
+    let error = evaluator.eval(&expr).expect_err("evaluation must fail");
+    error
+        .backtrace
+        .expect("backtrace must be attached")
+        .to_string()
+}
+
+#[test]
+fn test_backtrace() {
+    let backtrace = eval_backtrace(&["(define (f x) (car x))", "(f 1)"]);
+    assert_eq!(
+        backtrace,
+        "  at car (1:15)\n  at f (bt.rsc:1:1) [1 tail call]\n"
+    );
+}
+
+#[test]
+fn test_backtrace_without_proc_call() {
+    let evaluator = Evaluator::with_builtin();
+    let Some(expr) = Parser::with_tokens(tokenize("x").unwrap()).parse().unwrap() else {
+        panic!("an expression must be parsed");
+    };
+    assert_eq!(evaluator.eval(&expr).unwrap_err().backtrace, None);
+}
+
+#[test]
+fn test_backtrace_collapses_tail_calls() {
+    let backtrace = eval_backtrace(&[
+        "(define (loop n) (if (= n 0) (car n) (loop (- n 1))))",
+        "(loop 3)",
+    ]);
+    assert_eq!(
+        backtrace,
+        "  at car (1:30)\n  at loop (bt.rsc:1:1) [8 tail calls]\n"
+    );
+}
+
+#[test]
+fn test_backtrace_of_deep_recursion() {
+    let backtrace = eval_backtrace(&[
+        "(define (f n) (if (= n 0) (car n) (+ 1 (f (- n 1)))))",
+        "(f 100)",
+    ]);
+    let lines = backtrace.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 21);
+    assert_eq!(lines[0], "  at car (1:27)");
+    assert_eq!(lines[1], "  at f (1:40) [2 tail calls]");
+    assert_eq!(lines[10], "  ... 182 frames omitted ...");
+    assert_eq!(lines[20], "  at f (bt.rsc:1:1) [2 tail calls]");
+}
+
+fn eval_error_kind(src: &str) -> EvalErrorKind {
+    let evaluator = Evaluator::with_prelude();
+    let expr = Parser::with_tokens(tokenize(src).unwrap())
+        .parse()
+        .unwrap()
+        .unwrap();
+    *evaluator.eval(&expr).unwrap_err().kind
+}
+
+#[test]
+fn test_error_kind_unbound_variable() {
+    assert_eq!(
+        eval_error_kind("(car undefined-thing)"),
+        EvalErrorKind::UnboundVariable {
+            name: "undefined-thing".to_string()
+        }
+    );
+}
+
+#[test]
+fn test_error_kind_arity_mismatch() {
+    assert_eq!(
+        eval_error_kind("(car '(1) '(2))"),
+        EvalErrorKind::ArityMismatch {
+            proc_name: "car".to_string(),
+            expected: Arity::Exactly(1),
+            actual: 2,
+        }
+    );
+    assert_eq!(
+        eval_error_kind("((lambda (a b *rest) a) 1)"),
+        EvalErrorKind::ArityMismatch {
+            proc_name: "unnamed-closure".to_string(),
+            expected: Arity::AtLeast(2),
+            actual: 1,
+        }
+    );
+}
+
+#[test]
+fn test_error_kind_type_mismatch() {
+    assert_eq!(
+        eval_error_kind(r#"(str-length 'sym)"#),
+        EvalErrorKind::TypeMismatch {
+            proc_name: "str-length".to_string(),
+            expected: "string".to_string(),
+            actual: "symbol".to_string(),
+        }
+    );
+}
+
+#[test]
+fn test_error_kind_user_raised() {
+    let kind = eval_error_kind(r#"(error "bad value:" (+ 1 2) 'x)"#);
+    assert_eq!(kind.to_string(), "bad value: 3 x");
+    let EvalErrorKind::UserRaised { message, irritants } = kind else {
+        panic!("error must raise a user error");
+    };
+    assert_eq!(message, "bad value:");
+    assert_eq!(irritants.len(), 2);
+}
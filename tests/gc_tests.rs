@@ -1,7 +1,26 @@
 mod common;
 
+use std::{cell::RefCell, rc::Rc};
+
 use common::EvalToStr;
-use rusche::eval::Evaluator;
+use rusche::{
+    eval::{EvalContext, Evaluator},
+    expr::Expr,
+    foreign::ForeignObject,
+    list::List,
+};
+
+struct Holder(RefCell<Vec<Expr>>);
+
+impl ForeignObject for Holder {
+    fn type_name(&self) -> &str {
+        "holder"
+    }
+
+    fn trace(&self, visit: &mut dyn FnMut(&Expr)) {
+        self.0.borrow().iter().for_each(visit);
+    }
+}
 
 #[test]
 fn test_gc() {
@@ -29,6 +48,231 @@ fn test_gc() {
     let _ = e.eval_to_str("(f)");
     assert_eq!(e.count_unreachable_envs(), 4);
 
+    let stats = e.collect_garbage();
+    assert_eq!(stats.envs_reclaimed, 4);
+    assert_eq!(stats.bindings_freed, 4); // one `g` per env
+    assert_eq!(e.count_unreachable_envs(), 0);
+    assert_eq!(e.gc_stats().collections, 1);
+}
+
+#[test]
+fn test_gc_closure_in_foreign() {
+    let e = Evaluator::with_builtin();
+    let holder = Rc::new(Holder(RefCell::new(Vec::new())));
+    e.root_env().define("holder", Expr::Foreign(holder.clone()));
+
+    let _ = e.eval_to_str(
+        r#"
+        (define (make-counter)
+            (define n 0)
+            (lambda () (set! n (num-add n 1)) n))
+        "#,
+    );
+    let _ = e.eval_to_str("(define counter (make-counter))");
+    holder
+        .0
+        .borrow_mut()
+        .push(e.root_env().lookup("counter").unwrap());
+    let _ = e.eval_to_str("(set! counter '())");
+
+    assert_eq!(e.count_unreachable_envs(), 0);
+    e.collect_garbage();
+
+    let counter = holder.0.borrow()[0].clone();
+    e.root_env().define("counter", counter);
+    assert_eq!(e.eval_to_str("(counter)"), "1");
+    assert_eq!(e.eval_to_str("(counter)"), "2");
+}
+
+#[test]
+fn test_foreign_type() {
+    let e = Evaluator::with_builtin();
+    let holder = Holder(RefCell::new(Vec::new()));
+    e.root_env()
+        .define("holder", Expr::Foreign(Rc::new(holder)));
+
+    assert_eq!(e.eval_to_str("(foreign-type holder)"), "\"holder\"");
+    assert_eq!(e.eval_to_str("(eq? holder holder)"), "1");
+}
+
+const MAKE_COUNTER: &str = r#"
+    (define (make-counter)
+        (define n 0)
+        (lambda () (set! n (num-add n 1)) n))
+    "#;
+
+#[test]
+fn test_gc_closure_in_list() {
+    let e = Evaluator::with_builtin();
+    let _ = e.eval_to_str(MAKE_COUNTER);
+    let _ = e.eval_to_str("(define counters (cons (make-counter) '()))");
+
+    assert_eq!(e.count_unreachable_envs(), 0);
+    e.collect_garbage();
+
+    assert_eq!(e.eval_to_str("((car counters))"), "1");
+    assert_eq!(e.eval_to_str("((car counters))"), "2");
+}
+
+#[test]
+fn test_gc_closure_in_quoted_structure() {
+    let e = Evaluator::with_builtin();
+    let _ = e.eval_to_str(MAKE_COUNTER);
+    let _ = e.eval_to_str("(define data `(counter (nested ,(make-counter))))");
+
+    assert_eq!(e.count_unreachable_envs(), 0);
     e.collect_garbage();
+
+    let _ = e.eval_to_str("(define counter (car (cdr (car (cdr data)))))");
+    assert_eq!(e.eval_to_str("(counter)"), "1");
+}
+
+#[test]
+fn test_gc_closure_in_closure_body() {
+    let e = Evaluator::with_builtin();
+    let _ = e.eval_to_str(MAKE_COUNTER);
+
+    // `wrapper`'s body holds the counter closure itself, not an expression creating it.
+    let _ = e.eval_to_str(
+        "(define wrapper (eval (cons 'lambda (cons '() (cons (cons (make-counter) '()) '())))))",
+    );
+
     assert_eq!(e.count_unreachable_envs(), 0);
+    e.collect_garbage();
+
+    assert_eq!(e.eval_to_str("(wrapper)"), "1");
+    assert_eq!(e.eval_to_str("(wrapper)"), "2");
+}
+
+#[test]
+fn test_gc_nested_closure_keeps_outer_env() {
+    let e = Evaluator::with_builtin();
+    let _ = e.eval_to_str(
+        r#"
+        (define (outer x)
+            (define (inner) (lambda () x))
+            (inner))
+        "#,
+    );
+    let _ = e.eval_to_str("(define get-x (outer 10))");
+
+    assert_eq!(e.count_unreachable_envs(), 0);
+    e.collect_garbage();
+
+    assert_eq!(e.eval_to_str("(get-x)"), "10");
+}
+
+#[test]
+fn test_gc_closure_in_self_referencing_foreign() {
+    let e = Evaluator::with_builtin();
+    let holder = Rc::new(Holder(RefCell::new(Vec::new())));
+    let _ = e.eval_to_str(MAKE_COUNTER);
+    let _ = e.eval_to_str("(define counter (make-counter))");
+
+    holder
+        .0
+        .borrow_mut()
+        .push(e.root_env().lookup("counter").unwrap());
+    holder.0.borrow_mut().push(Expr::Foreign(holder.clone()));
+    e.root_env().define("holder", Expr::Foreign(holder.clone()));
+    let _ = e.eval_to_str("(set! counter '())");
+
+    assert_eq!(e.count_unreachable_envs(), 0);
+    e.collect_garbage();
+
+    e.root_env().define("counter", holder.0.borrow()[0].clone());
+    assert_eq!(e.eval_to_str("(counter)"), "1");
+
+    // break the cycle so the holder can be dropped
+    holder.0.borrow_mut().clear();
+}
+
+#[test]
+fn test_gc_tail_call() {
+    let e = Evaluator::with_builtin();
+    let _ = e.eval_to_str("(define (f) '())");
+
+    let Some(Expr::Proc(proc, _)) = e.root_env().lookup("f") else {
+        panic!("f must be a procedure");
+    };
+    let tail_call = Expr::TailCall {
+        proc,
+        args: List::Nil,
+        context: EvalContext::derive_from(e.context()),
+        span: None,
+    };
+    e.root_env().define("pending", tail_call);
+
+    assert_eq!(e.count_unreachable_envs(), 0);
+}
+
+const MAKE_GARBAGE: &str = r#"
+    (define (make-garbage)
+        (define (g) '())
+        g)
+    "#;
+
+const CHURN: &str = r#"
+    (define (churn n)
+        (if (< n 1)
+            '()
+            (begin (make-garbage) (churn (- n 1)))))
+    "#;
+
+#[test]
+fn test_gc_closure_held_by_host() {
+    let e = Evaluator::with_builtin();
+    let _ = e.eval_to_str(MAKE_COUNTER);
+    let _ = e.eval_to_str("(define counter (make-counter))");
+
+    let counter = e.root_env().lookup("counter").unwrap();
+    let _ = e.eval_to_str("(set! counter '())");
+
+    assert_eq!(e.count_unreachable_envs(), 0);
+    e.collect_garbage();
+
+    e.root_env().define("counter", counter);
+    assert_eq!(e.eval_to_str("(counter)"), "1");
+}
+
+#[test]
+fn test_auto_gc_between_evaluations() {
+    let e = Evaluator::with_prelude();
+    e.set_gc_threshold(10);
+    let _ = e.eval_to_str(MAKE_GARBAGE);
+    let _ = e.eval_to_str(CHURN);
+
+    for _ in 0..100 {
+        let _ = e.eval_to_str("(make-garbage)");
+        let _ = e.eval(&Expr::from(0));
+    }
+    assert!(e.count_unreachable_envs() < 10);
+}
+
+#[test]
+fn test_auto_gc_during_evaluation() {
+    let e = Evaluator::with_prelude();
+    e.set_gc_threshold(10);
+    let _ = e.eval_to_str(MAKE_COUNTER);
+    let _ = e.eval_to_str(MAKE_GARBAGE);
+    let _ = e.eval_to_str(CHURN);
+
+    // The counter is only held by the Rust stack of `cons` while `churn` runs.
+    let _ = e.eval_to_str("(define pair (cons (make-counter) (churn 100)))");
+    assert!(e.count_unreachable_envs() < 10);
+
+    assert_eq!(e.eval_to_str("((car pair))"), "1");
+    assert_eq!(e.eval_to_str("((car pair))"), "2");
+}
+
+#[test]
+fn test_auto_gc_disabled() {
+    let e = Evaluator::with_prelude();
+    e.set_auto_gc(false);
+    e.set_gc_threshold(10);
+    let _ = e.eval_to_str(MAKE_GARBAGE);
+    let _ = e.eval_to_str(CHURN);
+    let _ = e.eval_to_str("(churn 100)");
+
+    assert_eq!(e.count_unreachable_envs(), 100);
 }